@@ -0,0 +1,487 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{
+    paper::trade_info, prelude::*, ClientOrder, ClientOrderRequest, Error, FeeRates, L2BookData,
+    MatchingEngine, Message, Placement, PlacementStatus, Position, PositionTracker, SimFill,
+    SimOrder, TimeInForce, Trade, EPSILON,
+};
+
+/// Strategy code driven by a [`Backtest`].
+///
+/// Callbacks receive the backtest to place and cancel orders through. Orders reach the simulated
+/// exchange after the configured latency, at which point [`BacktestStrategy::on_placement`] or
+/// [`BacktestStrategy::on_cancel`] is called.
+pub trait BacktestStrategy {
+    fn on_book(&mut self, _book: &L2BookData, _backtest: &mut Backtest) {}
+
+    fn on_trade(&mut self, _trade: &Trade, _backtest: &mut Backtest) {}
+
+    fn on_mids(&mut self, _mids: &HashMap<String, String>, _backtest: &mut Backtest) {}
+
+    /// Called for every fill, including those of aggressive orders also reported in their
+    /// placement.
+    fn on_fill(&mut self, _fill: &SimFill, _backtest: &mut Backtest) {}
+
+    fn on_placement(&mut self, _placement: &Placement, _backtest: &mut Backtest) {}
+
+    /// `canceled` is false if the order had already been filled or canceled.
+    fn on_cancel(&mut self, _oid: u64, _canceled: bool, _backtest: &mut Backtest) {}
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BacktestConfig {
+    pub fees: FeeRates,
+    /// Delay between an order or cancel being sent and reaching the exchange
+    pub latency_ms: u64,
+}
+
+/// Inventory of a single coin over the course of a backtest.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InventoryStats {
+    pub max_long: f64,
+    pub max_short: f64,
+    /// Time-weighted average of the absolute position size
+    pub mean_abs: f64,
+    pub last: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BacktestReport {
+    pub start_time: u64,
+    pub end_time: u64,
+    pub realized_pnl: f64,
+    /// PnL of open positions at the last mid
+    pub unrealized_pnl: f64,
+    pub fees: f64,
+    /// Orders that reached the exchange
+    pub orders: usize,
+    pub rejected_orders: usize,
+    /// Orders that were at least partially filled
+    pub filled_orders: usize,
+    pub fills: usize,
+    /// Notional traded
+    pub volume: f64,
+    pub inventory: HashMap<String, InventoryStats>,
+}
+
+impl BacktestReport {
+    /// Realized and unrealized PnL net of fees.
+    pub fn total_pnl(&self) -> f64 {
+        self.realized_pnl + self.unrealized_pnl - self.fees
+    }
+
+    /// Share of accepted orders that were at least partially filled.
+    pub fn fill_rate(&self) -> f64 {
+        let accepted = self.orders - self.rejected_orders;
+        if accepted == 0 {
+            0.0
+        } else {
+            self.filled_orders as f64 / accepted as f64
+        }
+    }
+}
+
+#[derive(Debug)]
+enum PendingAction {
+    Place(SimOrder, bool),
+    Cancel(u64),
+}
+
+#[derive(Debug, Default)]
+struct InventoryAccumulator {
+    max_long: f64,
+    max_short: f64,
+    // Integral of |szi| over time, in size * ms
+    abs_time: f64,
+}
+
+/// Replays recorded `L2Book`, `Trades` and `AllMids` messages through a [`MatchingEngine`],
+/// driving a [`BacktestStrategy`].
+///
+/// Time is taken from the market data; `AllMids` messages carry no timestamp and are treated as
+/// arriving at the time of the previous message.
+#[derive(Debug)]
+pub struct Backtest {
+    config: BacktestConfig,
+    engine: MatchingEngine,
+    positions: PositionTracker,
+    pending: VecDeque<(u64, PendingAction)>,
+    next_oid: u64,
+    marks: HashMap<String, f64>,
+    start_time: Option<u64>,
+    last_time: u64,
+    filled_oids: HashSet<u64>,
+    inventory: HashMap<String, InventoryAccumulator>,
+    report: BacktestReport,
+}
+
+impl Backtest {
+    pub fn new(config: BacktestConfig) -> Backtest {
+        Backtest {
+            engine: MatchingEngine::new(config.fees),
+            config,
+            positions: PositionTracker::new(),
+            pending: VecDeque::new(),
+            next_oid: 0,
+            marks: HashMap::new(),
+            start_time: None,
+            last_time: 0,
+            filled_oids: HashSet::new(),
+            inventory: HashMap::new(),
+            report: BacktestReport::default(),
+        }
+    }
+
+    /// Runs `strategy` over `messages`, which must be in chronological order.
+    pub fn run(
+        mut self,
+        strategy: &mut impl BacktestStrategy,
+        messages: impl IntoIterator<Item = Message>,
+    ) -> Result<BacktestReport> {
+        for message in messages {
+            self.on_message(strategy, &message)?;
+        }
+        Ok(self.report())
+    }
+
+    /// Processes a single message; [`Backtest::run`] calls this for every message.
+    pub fn on_message(
+        &mut self,
+        strategy: &mut impl BacktestStrategy,
+        message: &Message,
+    ) -> Result<()> {
+        match message {
+            Message::L2Book(book) => {
+                self.advance(strategy, book.data.time)?;
+                let fills = self.engine.on_book(&book.data)?;
+                if let Some(mid) = self.engine.mid(&book.data.coin) {
+                    self.marks.insert(book.data.coin.clone(), mid);
+                }
+                self.apply_fills(strategy, &fills)?;
+                strategy.on_book(&book.data, self);
+            }
+            Message::Trades(trades) => {
+                for trade in &trades.data {
+                    self.advance(strategy, trade.time)?;
+                    let fills = self.engine.on_trade(trade)?;
+                    self.apply_fills(strategy, &fills)?;
+                    strategy.on_trade(trade, self);
+                }
+            }
+            Message::AllMids(all_mids) => {
+                self.advance(strategy, self.last_time)?;
+                for (coin, mid) in &all_mids.data.mids {
+                    if let Ok(mid) = mid.parse() {
+                        self.marks.insert(coin.clone(), mid);
+                    }
+                }
+                strategy.on_mids(&all_mids.data.mids, self);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Sends an order, returning the oid it will have on the exchange. Only limit orders are
+    /// supported.
+    pub fn place(&mut self, order: &ClientOrderRequest) -> Result<u64> {
+        let ClientOrder::Limit(limit) = &order.order_type else {
+            return Err(Error::OrderTypeNotFound);
+        };
+        self.next_oid += 1;
+        let order_to_place = SimOrder {
+            oid: self.next_oid,
+            coin: order.asset.clone(),
+            is_buy: order.is_buy,
+            limit_px: order.limit_px,
+            sz: order.sz,
+            orig_sz: order.sz,
+            tif: TimeInForce::parse(&limit.tif)?,
+            cloid: order.cloid,
+            timestamp: 0,
+        };
+        self.send(PendingAction::Place(order_to_place, order.reduce_only));
+        Ok(self.next_oid)
+    }
+
+    pub fn cancel(&mut self, oid: u64) {
+        self.send(PendingAction::Cancel(oid));
+    }
+
+    /// Time of the latest market event, in milliseconds.
+    pub fn now(&self) -> u64 {
+        self.last_time
+    }
+
+    pub fn mid(&self, coin: &str) -> Option<f64> {
+        self.marks.get(coin).copied()
+    }
+
+    pub fn position(&self, coin: &str) -> Option<&Position> {
+        self.positions.position(coin)
+    }
+
+    /// Orders resting on the simulated exchange, excluding those still in flight.
+    pub fn open_orders(&self) -> impl Iterator<Item = &SimOrder> {
+        self.engine.open_orders()
+    }
+
+    pub fn report(&self) -> BacktestReport {
+        let mut report = self.report.clone();
+        report.start_time = self.start_time.unwrap_or_default();
+        report.end_time = self.last_time;
+        let duration = report.end_time.saturating_sub(report.start_time);
+        for position in self.positions.positions() {
+            report.realized_pnl += position.realized_pnl;
+            report.fees += position.fees_paid;
+            if let Some(mark) = self.marks.get(&position.coin) {
+                report.unrealized_pnl += (mark - position.entry_px) * position.szi;
+            }
+        }
+        report.inventory = self
+            .inventory
+            .iter()
+            .map(|(coin, inventory)| {
+                let stats = InventoryStats {
+                    max_long: inventory.max_long,
+                    max_short: inventory.max_short,
+                    mean_abs: if duration == 0 {
+                        0.0
+                    } else {
+                        inventory.abs_time / duration as f64
+                    },
+                    last: self.szi(coin),
+                };
+                (coin.clone(), stats)
+            })
+            .collect();
+        report
+    }
+
+    fn send(&mut self, action: PendingAction) {
+        self.pending
+            .push_back((self.last_time + self.config.latency_ms, action));
+    }
+
+    fn szi(&self, coin: &str) -> f64 {
+        self.positions
+            .position(coin)
+            .map(|position| position.szi)
+            .unwrap_or(0.0)
+    }
+
+    // Moves the clock to `time`, executing actions arriving on the way
+    fn advance(&mut self, strategy: &mut impl BacktestStrategy, time: u64) -> Result<()> {
+        while self
+            .pending
+            .front()
+            .is_some_and(|(arrival, _)| *arrival <= time)
+        {
+            let (arrival, action) = self.pending.pop_front().expect("checked above");
+            self.set_time(arrival);
+            self.engine.set_time(arrival);
+            match action {
+                PendingAction::Place(order, reduce_only) => {
+                    self.execute_place(strategy, order, reduce_only)?
+                }
+                PendingAction::Cancel(oid) => {
+                    let canceled = self.engine.cancel(oid).is_some();
+                    strategy.on_cancel(oid, canceled, self);
+                }
+            }
+        }
+        self.set_time(time);
+        Ok(())
+    }
+
+    fn execute_place(
+        &mut self,
+        strategy: &mut impl BacktestStrategy,
+        mut order: SimOrder,
+        reduce_only: bool,
+    ) -> Result<()> {
+        self.report.orders += 1;
+        let placement = if reduce_only && !self.reduces(&mut order) {
+            Placement {
+                order,
+                status: PlacementStatus::Rejected(
+                    "Reduce only order would increase position.".to_string(),
+                ),
+                fills: Vec::new(),
+            }
+        } else {
+            self.engine.place(order)
+        };
+        if matches!(placement.status, PlacementStatus::Rejected(_)) {
+            self.report.rejected_orders += 1;
+        }
+        self.apply_fills(strategy, &placement.fills)?;
+        strategy.on_placement(&placement, self);
+        Ok(())
+    }
+
+    // Clips a reduce only order to the position, returning false if it would increase it
+    fn reduces(&self, order: &mut SimOrder) -> bool {
+        let szi = self.szi(&order.coin);
+        if szi.abs() < EPSILON || (szi > 0.0) == order.is_buy {
+            return false;
+        }
+        order.sz = order.sz.min(szi.abs());
+        true
+    }
+
+    fn apply_fills(
+        &mut self,
+        strategy: &mut impl BacktestStrategy,
+        fills: &[SimFill],
+    ) -> Result<()> {
+        for fill in fills {
+            let start_position = self.szi(&fill.coin);
+            self.positions
+                .apply_fill(&trade_info(fill, start_position))?;
+
+            self.report.fills += 1;
+            self.report.volume += fill.px * fill.sz;
+            if self.filled_oids.insert(fill.oid) {
+                self.report.filled_orders += 1;
+            }
+            let szi = self.szi(&fill.coin);
+            let inventory = self.inventory.entry(fill.coin.clone()).or_default();
+            inventory.max_long = inventory.max_long.max(szi);
+            inventory.max_short = inventory.max_short.min(szi);
+
+            strategy.on_fill(fill, self);
+        }
+        Ok(())
+    }
+
+    fn set_time(&mut self, time: u64) {
+        let start_time = *self.start_time.get_or_insert(time);
+        let time = time.max(start_time).max(self.last_time);
+        let elapsed = (time - self.last_time.max(start_time)) as f64;
+        for position in self.positions.positions() {
+            if let Some(inventory) = self.inventory.get_mut(&position.coin) {
+                inventory.abs_time += position.szi.abs() * elapsed;
+            }
+        }
+        self.last_time = time;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BookLevel, ClientLimit, L2Book, Trades};
+
+    fn book(time: u64, bid: &str, ask: &str) -> Message {
+        let level = |px: &str| {
+            vec![BookLevel {
+                px: px.to_string(),
+                sz: "10".to_string(),
+                n: 1,
+            }]
+        };
+        Message::L2Book(L2Book {
+            data: L2BookData {
+                coin: "ETH".to_string(),
+                time,
+                levels: vec![level(bid), level(ask)],
+            },
+        })
+    }
+
+    fn trade(time: u64, side: &str, px: &str) -> Message {
+        Message::Trades(Trades {
+            data: vec![Trade {
+                coin: "ETH".to_string(),
+                side: side.to_string(),
+                px: px.to_string(),
+                sz: "1".to_string(),
+                time,
+                hash: String::new(),
+                tid: time,
+            }],
+        })
+    }
+
+    // Quotes one unit a dollar away from the mid on each side whenever it is flat on that side
+    #[derive(Default)]
+    struct Quoter {
+        placements: Vec<Placement>,
+        quoted: bool,
+    }
+
+    impl BacktestStrategy for Quoter {
+        fn on_book(&mut self, book: &L2BookData, backtest: &mut Backtest) {
+            if self.quoted {
+                return;
+            }
+            self.quoted = true;
+            let mid = backtest.mid(&book.coin).unwrap();
+            for (is_buy, limit_px) in [(true, mid - 1.0), (false, mid + 1.0)] {
+                backtest
+                    .place(&ClientOrderRequest {
+                        asset: book.coin.clone(),
+                        is_buy,
+                        reduce_only: false,
+                        limit_px,
+                        sz: 1.0,
+                        cloid: None,
+                        order_type: ClientOrder::Limit(ClientLimit {
+                            tif: "Alo".to_string(),
+                        }),
+                    })
+                    .unwrap();
+            }
+        }
+
+        fn on_placement(&mut self, placement: &Placement, _backtest: &mut Backtest) {
+            self.placements.push(placement.clone());
+        }
+    }
+
+    #[test]
+    fn test_backtest() -> Result<()> {
+        let backtest = Backtest::new(BacktestConfig {
+            fees: FeeRates {
+                maker: 0.0001,
+                taker: 0.0003,
+            },
+            latency_ms: 50,
+        });
+        let mut strategy = Quoter::default();
+        let report = backtest.run(
+            &mut strategy,
+            vec![
+                book(1000, "1999", "2001"),
+                // Trades before the orders arrive don't fill them
+                trade(1020, "A", "1999"),
+                book(1100, "1999", "2001"),
+                trade(1200, "A", "1999"),
+                trade(1300, "B", "2001"),
+                book(2000, "2004", "2006"),
+            ],
+        )?;
+
+        assert_eq!(strategy.placements.len(), 2);
+        assert!(strategy
+            .placements
+            .iter()
+            .all(|placement| placement.order.timestamp == 1050));
+        assert_eq!(
+            (report.orders, report.filled_orders, report.fills),
+            (2, 2, 2)
+        );
+        assert!((report.fill_rate() - 1.0).abs() < EPSILON);
+        assert!((report.realized_pnl - 2.0).abs() < EPSILON);
+        assert!((report.fees - 4000.0 * 0.0001).abs() < EPSILON);
+        assert!((report.volume - 4000.0).abs() < EPSILON);
+
+        // Long one unit from 1200 to 1300 out of a 1000ms run
+        let inventory = &report.inventory["ETH"];
+        assert!((inventory.max_long - 1.0).abs() < EPSILON);
+        assert!((inventory.mean_abs - 0.1).abs() < EPSILON);
+        assert!(inventory.last.abs() < EPSILON);
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+/*
+This is an example of keeping trading keys short-lived.
+
+The master wallet only approves agents; orders are signed by the current agent, which is replaced by
+a freshly generated one every hour. Agent keys are stored encrypted in the `agents` directory.
+*/
+use std::{sync::Arc, time::Duration};
+
+use ethers::signers::{LocalWallet, Signer};
+use log::info;
+use tokio::sync::{Mutex, RwLock};
+
+use hyperliquid_rust_sdk::{AgentManager, BaseUrl, ExchangeClient};
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    // Key was randomly generated for testing and shouldn't be used with any real funds
+    let wallet: LocalWallet = "e908f86dbb4d55ac876378565aafeabc187f6690f046459397b17d9b9a19688e"
+        .parse()
+        .unwrap();
+
+    let exchange_client = Arc::new(RwLock::new(
+        ExchangeClient::new(None, wallet.clone(), Some(BaseUrl::Testnet), None, None)
+            .await
+            .unwrap(),
+    ));
+
+    std::fs::create_dir_all("agents").unwrap();
+    let mut manager = AgentManager::new(
+        wallet,
+        exchange_client.clone(),
+        "agents",
+        "correct horse battery staple".to_string(),
+        "bot",
+    );
+    let agent = manager.rotate().await.unwrap();
+    info!("Trading with agent {agent:?}");
+    info!(
+        "Client now signs with {:?}",
+        exchange_client.read().await.wallet.address()
+    );
+
+    // The next rotation happens an hour from now
+    let manager = Arc::new(Mutex::new(manager));
+    AgentManager::start_rotation(manager, Duration::from_secs(60 * 60))
+        .await
+        .unwrap();
+}
@@ -0,0 +1,54 @@
+/*
+This is an example of building candles of an interval the exchange doesn't offer from trades.
+
+Two minute ETH bars are built from the trades channel, starting from an hour of history. Whenever the
+connection drops, the bars are backfilled once it is back so that they have no gaps.
+*/
+use std::time::Duration;
+
+use log::info;
+use tokio::sync::mpsc::unbounded_channel;
+
+use hyperliquid_rust_sdk::{BaseUrl, CandleAggregator, InfoClient, Message, Subscription};
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let mut info_client = InfoClient::with_reconnect(None, Some(BaseUrl::Mainnet))
+        .await
+        .unwrap();
+    let mut aggregator = CandleAggregator::new("ETH", Duration::from_secs(120));
+
+    let (sender, mut receiver) = unbounded_channel();
+    info_client
+        .subscribe(
+            Subscription::Trades {
+                coin: "ETH".to_string(),
+            },
+            sender,
+        )
+        .await
+        .unwrap();
+
+    let start_time = chrono::Utc::now().timestamp_millis() as u64 - 60 * 60 * 1000;
+    aggregator.backfill(&info_client, start_time).await.unwrap();
+
+    // Backfilling on the disconnect itself would miss the trades until the reconnect, so wait for
+    // the first message after it
+    let mut needs_backfill = false;
+    while let Some(message) = receiver.recv().await {
+        if let Message::NoData = message {
+            needs_backfill = true;
+            continue;
+        }
+        if needs_backfill {
+            let latest = aggregator
+                .latest()
+                .map_or(start_time, |candle| candle.time_open);
+            aggregator.backfill(&info_client, latest).await.unwrap();
+            needs_backfill = false;
+        }
+        aggregator.on_message(&message).unwrap();
+        info!("Latest candle: {:?}", aggregator.latest());
+    }
+}
@@ -1,5 +1,5 @@
 use ethers::types::H160;
-use hyperliquid_rust_sdk::{BaseUrl, InfoClient};
+use hyperliquid_rust_sdk::{BaseUrl, CandleInterval, Cloid, InfoClient};
 use log::info;
 
 const ADDRESS: &str = "0xc64cc00b46101bd40aa1c3121195e85c0b0918d8";
@@ -9,6 +9,7 @@ async fn main() {
     env_logger::init();
     let info_client = InfoClient::new(None, Some(BaseUrl::Testnet)).await.unwrap();
     open_orders_example(&info_client).await;
+    frontend_open_orders_example(&info_client).await;
     user_state_example(&info_client).await;
     user_states_example(&info_client).await;
     recent_trades(&info_client).await;
@@ -24,8 +25,10 @@ async fn main() {
     spot_meta_example(&info_client).await;
     spot_meta_and_asset_contexts_example(&info_client).await;
     query_order_by_oid_example(&info_client).await;
+    query_order_by_cloid_example(&info_client).await;
     query_referral_state_example(&info_client).await;
     historical_orders_example(&info_client).await;
+    extra_agents_example(&info_client).await;
 }
 
 fn address() -> H160 {
@@ -41,6 +44,15 @@ async fn open_orders_example(info_client: &InfoClient) {
     );
 }
 
+async fn frontend_open_orders_example(info_client: &InfoClient) {
+    let user = address();
+
+    info!(
+        "Frontend open order data for {user}: {:?}",
+        info_client.frontend_open_orders(user).await.unwrap()
+    );
+}
+
 async fn user_state_example(info_client: &InfoClient) {
     let user = address();
 
@@ -121,18 +133,25 @@ async fn l2_snapshot_example(info_client: &InfoClient) {
         "L2 snapshot data for {coin}: {:?}",
         info_client.l2_snapshot(coin.to_string()).await.unwrap()
     );
+    info!(
+        "L2 snapshot data for {coin} aggregated to 3 significant figures: {:?}",
+        info_client
+            .l2_snapshot_aggregated(coin.to_string(), Some(3), None)
+            .await
+            .unwrap()
+    );
 }
 
 async fn candles_snapshot_example(info_client: &InfoClient) {
     let coin = "ETH";
     let start_timestamp = 1690540602225;
     let end_timestamp = 1690569402225;
-    let interval = "1h";
+    let interval = CandleInterval::OneHour;
 
     info!(
         "Candles snapshot data for {coin} between timestamps {start_timestamp} and {end_timestamp} with interval {interval}: {:?}",
         info_client
-            .candles_snapshot(coin.to_string(), interval.to_string(), start_timestamp, end_timestamp)
+            .candles_snapshot(coin.to_string(), interval, start_timestamp, end_timestamp)
             .await
             .unwrap()
     );
@@ -168,6 +187,15 @@ async fn query_order_by_oid_example(info_client: &InfoClient) {
     );
 }
 
+async fn query_order_by_cloid_example(info_client: &InfoClient) {
+    let user = address();
+    let cloid = Cloid::from_label("my-order");
+    info!(
+        "Order status for {user} for cloid {cloid}: {:?}",
+        info_client.query_order_by_cloid(user, cloid).await.unwrap()
+    );
+}
+
 async fn query_referral_state_example(info_client: &InfoClient) {
     let user = address();
     info!(
@@ -183,3 +211,11 @@ async fn historical_orders_example(info_client: &InfoClient) {
         info_client.historical_orders(user).await.unwrap()
     );
 }
+
+async fn extra_agents_example(info_client: &InfoClient) {
+    let user = address();
+    info!(
+        "Approved agents for {user}: {:?}",
+        info_client.extra_agents(user).await.unwrap()
+    );
+}
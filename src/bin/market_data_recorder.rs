@@ -0,0 +1,46 @@
+/*
+This is an example of recording ETH market data to disk and replaying it.
+
+A minute of mainnet ETH book and trade frames is written to the `recordings` directory, then replayed
+at ten times the original speed.
+*/
+use std::time::Duration;
+
+use log::info;
+use tokio::{sync::mpsc::unbounded_channel, time::sleep};
+
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient, Recorder, Replayer, Subscription};
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let mut info_client = InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap();
+
+    let recorder = Recorder::new("recordings", "eth", Duration::from_secs(60 * 60));
+    let handle = recorder
+        .start(
+            &mut info_client,
+            vec![
+                Subscription::L2Book {
+                    coin: "ETH".to_string(),
+                    n_sig_figs: None,
+                    mantissa: None,
+                },
+                Subscription::Trades {
+                    coin: "ETH".to_string(),
+                },
+            ],
+        )
+        .await
+        .unwrap();
+    sleep(Duration::from_secs(60)).await;
+    handle.stop().await.unwrap();
+
+    let replayer = Replayer::from_dir("recordings", "eth").unwrap();
+    info!("Replaying {:?}", replayer.files());
+    let (sender, mut receiver) = unbounded_channel();
+    replayer.start(10.0, sender).unwrap();
+    while let Some(message) = receiver.recv().await {
+        info!("Replayed: {message:?}");
+    }
+}
@@ -1,11 +1,11 @@
 /*
 This is an example of a basic market making strategy.
 
-We subscribe to the current mid price and build a market around this price. Whenever our market becomes outdated, we place and cancel orders to renew it.
+We subscribe to the current mid price and build a market of several levels per side around this price, shifted away from our position. Whenever our market becomes outdated, we modify, place and cancel orders to renew it.
 */
 use ethers::signers::LocalWallet;
 
-use hyperliquid_rust_sdk::{MarketMaker, MarketMakerInput};
+use hyperliquid_rust_sdk::{BaseUrl, MarketMaker, MarketMakerInput};
 
 #[tokio::main]
 async fn main() {
@@ -20,8 +20,13 @@ async fn main() {
         max_bps_diff: 2,
         half_spread: 1,
         max_absolute_position_size: 0.5,
-        decimals: 1,
+        levels: 2,
+        level_spacing: 5,
+        size_multiplier: 1.0,
+        inventory_skew: 2,
+        owns_asset: true,
         wallet,
+        network: BaseUrl::Testnet.into(),
     };
-    MarketMaker::new(market_maker_input).await.start().await
+    MarketMaker::new(market_maker_input).start().await.unwrap()
 }
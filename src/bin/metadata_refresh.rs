@@ -0,0 +1,29 @@
+use std::{sync::Arc, time::Duration};
+
+use hyperliquid_rust_sdk::{BaseUrl, MetadataCache};
+use log::{info, warn};
+use tokio::sync::broadcast::error::RecvError;
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let metadata = Arc::new(
+        MetadataCache::new(BaseUrl::Testnet.into(), None)
+            .await
+            .unwrap(),
+    );
+    info!("Known coins: {}", metadata.assets().coin_to_asset.len());
+
+    // Re-fetch metadata every minute and log any listings or delistings
+    let mut events = metadata.subscribe();
+    let _refresh = metadata.start_auto_refresh(Duration::from_secs(60));
+
+    loop {
+        match events.recv().await {
+            Ok(event) => info!("Metadata event: {event:?}"),
+            Err(RecvError::Lagged(missed)) => warn!("Missed {missed} metadata events"),
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use log::info;
+
+use ethers::signers::{LocalWallet, Signer};
+use hyperliquid_rust_sdk::{BaseUrl, ExchangeClient, InfoClient};
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    // Key was randomly generated for testing and shouldn't be used with any real funds
+    let wallet: LocalWallet = "e908f86dbb4d55ac876378565aafeabc187f6690f046459397b17d9b9a19688e"
+        .parse()
+        .unwrap();
+    let user = wallet.address();
+
+    let exchange_client = ExchangeClient::new(None, wallet, Some(BaseUrl::Testnet), None, None)
+        .await
+        .unwrap();
+
+    /*
+        Approve an agent whose key was generated elsewhere, e.g. in an HSM.
+        Only the agent's address is needed to approve it.
+    */
+    let agent: LocalWallet = "a0a6f2f7e3d8d3c6f1b2e4a5c6d7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d5"
+        .parse()
+        .unwrap();
+    let response = exchange_client
+        .approve_agent_with(agent.address(), Some("hsm-bot".to_string()), None)
+        .await
+        .unwrap();
+    info!("Agent approval response: {response:?}");
+
+    let info_client = InfoClient::new(None, Some(BaseUrl::Testnet)).await.unwrap();
+    info!(
+        "Approved agents: {:?}",
+        info_client.extra_agents(user).await.unwrap()
+    );
+}
@@ -0,0 +1,57 @@
+/*
+This is an example of splitting an order into build, sign and submit stages.
+
+The action is built from exchange metadata, signed without any network access, written out as JSON
+and later read back and broadcast. In a real cold-wallet setup the signing step runs on an
+air-gapped machine and only the signed payload is carried over to the online one.
+*/
+use ethers::signers::LocalWallet;
+use log::info;
+
+use hyperliquid_rust_sdk::{
+    next_nonce, BaseUrl, ClientLimit, ClientOrder, ClientOrderRequest, ExchangeClient,
+    ExchangePayload,
+};
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    // Key was randomly generated for testing and shouldn't be used with any real funds
+    let wallet: LocalWallet = "e908f86dbb4d55ac876378565aafeabc187f6690f046459397b17d9b9a19688e"
+        .parse()
+        .unwrap();
+
+    let exchange_client = ExchangeClient::new(None, wallet, Some(BaseUrl::Testnet), None, None)
+        .await
+        .unwrap();
+
+    let order = ClientOrderRequest {
+        asset: "ETH".to_string(),
+        is_buy: true,
+        reduce_only: false,
+        limit_px: 1800.0,
+        sz: 0.01,
+        cloid: None,
+        order_type: ClientOrder::Limit(ClientLimit {
+            tif: "Gtc".to_string(),
+        }),
+    };
+
+    // Build: needs asset metadata, so this normally happens on the online machine
+    let action = exchange_client
+        .bulk_order_action(vec![order], None)
+        .unwrap();
+    let unsigned = serde_json::to_string(&action).unwrap();
+    info!("Unsigned action: {unsigned}");
+
+    // Sign: no network access required
+    let nonce = next_nonce();
+    let payload = exchange_client.sign_action(&action, nonce, None).unwrap();
+    let signed = serde_json::to_string(&payload).unwrap();
+    info!("Signed payload: {signed}");
+
+    // Submit: broadcast the stored payload
+    let payload: ExchangePayload = serde_json::from_str(&signed).unwrap();
+    let response = exchange_client.submit(&payload).await.unwrap();
+    info!("Order placed: {response:?}");
+}
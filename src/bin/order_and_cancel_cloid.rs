@@ -2,7 +2,8 @@ use ethers::signers::LocalWallet;
 use log::info;
 
 use hyperliquid_rust_sdk::{
-    BaseUrl, ClientCancelRequestCloid, ClientLimit, ClientOrder, ClientOrderRequest, ExchangeClient,
+    BaseUrl, ClientCancelRequestCloid, ClientLimit, ClientOrder, ClientOrderRequest, Cloid,
+    ExchangeClient,
 };
 use std::{thread::sleep, time::Duration};
 
@@ -25,7 +26,7 @@ async fn main() {
         reduce_only: false,
         limit_px: 1800.0,
         sz: 0.01,
-        cloid: Some("my_own_custom_id".to_string()),
+        cloid: Some(Cloid::from_label("my_own_custom_id")),
         order_type: ClientOrder::Limit(ClientLimit {
             tif: "Gtc".to_string(),
         }),
@@ -39,7 +40,7 @@ async fn main() {
 
     let cancel = ClientCancelRequestCloid {
         asset: "ETH".to_string(),
-        cloid: "my_own_custom_id".to_string(),
+        cloid: Cloid::from_label("my_own_custom_id"),
     };
 
     // This response will return an error if order was filled (since you can't cancel a filled order), otherwise it will cancel the order
@@ -0,0 +1,77 @@
+/*
+This is an example of trading against live mainnet market data without sending any orders.
+
+Orders placed through the exchange client are matched against the ETH book by a paper exchange,
+which reports fills on its own channel in the same shape as the websocket feeds.
+*/
+use std::{sync::Arc, time::Duration};
+
+use ethers::signers::{LocalWallet, Signer};
+use log::info;
+use tokio::time::sleep;
+
+use hyperliquid_rust_sdk::{
+    BaseUrl, ClientLimit, ClientOrder, ClientOrderRequest, ExchangeClient, FeeRates, InfoClient,
+    MarketCloseParams, PaperExchange,
+};
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    // Key was randomly generated for testing and shouldn't be used with any real funds
+    let wallet: LocalWallet = "e908f86dbb4d55ac876378565aafeabc187f6690f046459397b17d9b9a19688e"
+        .parse()
+        .unwrap();
+
+    let paper_exchange = Arc::new(PaperExchange::new(wallet.address(), FeeRates::default()));
+    let mut info_client = InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap();
+    paper_exchange
+        .connect(&mut info_client, &["ETH"])
+        .await
+        .unwrap();
+    let mut receiver = paper_exchange.subscribe();
+
+    let exchange_client = ExchangeClient::new(None, wallet, Some(BaseUrl::Mainnet), None, None)
+        .await
+        .unwrap()
+        .with_paper_exchange(paper_exchange.clone());
+
+    // Wait for the first book snapshot
+    while paper_exchange.mid("ETH").is_none() {
+        sleep(Duration::from_millis(100)).await;
+    }
+    let mid = paper_exchange.mid("ETH").unwrap();
+
+    let order = ClientOrderRequest {
+        asset: "ETH".to_string(),
+        is_buy: true,
+        reduce_only: false,
+        limit_px: (mid * 1.01).round(),
+        sz: 0.1,
+        cloid: None,
+        order_type: ClientOrder::Limit(ClientLimit {
+            tif: "Ioc".to_string(),
+        }),
+    };
+    let response = exchange_client.order(order, None).await.unwrap();
+    info!("Paper order placed: {response:?}");
+
+    sleep(Duration::from_secs(30)).await;
+    let response = exchange_client
+        .market_close(MarketCloseParams {
+            asset: "ETH",
+            sz: None,
+            px: None,
+            slippage: Some(0.01),
+            cloid: None,
+            wallet: None,
+        })
+        .await
+        .unwrap();
+    info!("Paper position closed: {response:?}");
+
+    while let Ok(message) = receiver.try_recv() {
+        info!("{message:?}");
+    }
+    info!("Final position: {:?}", paper_exchange.position("ETH"));
+}
@@ -0,0 +1,70 @@
+/*
+This is an example of tracking an account's spot balances live.
+
+Balances and the holds of open orders are synced once, then kept up to date from the user's fills,
+order updates and ledger updates. The combined perp and spot portfolio is logged on every change.
+Whenever the connection drops, balances are synced again once it is back.
+*/
+use ethers::types::H160;
+use log::info;
+use tokio::sync::mpsc::unbounded_channel;
+
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient, Message, SpotBalances, Subscription};
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let user: H160 = "0xc64cc00b46101bd40aa1c3121195e85c0b0918d8"
+        .parse()
+        .unwrap();
+    let mut info_client = InfoClient::with_reconnect(None, Some(BaseUrl::Testnet))
+        .await
+        .unwrap();
+
+    // Subscribe before syncing so that no update is missed in between
+    let (sender, mut receiver) = unbounded_channel();
+    for subscription in [
+        Subscription::UserFills { user },
+        Subscription::OrderUpdates { user },
+        Subscription::UserNonFundingLedgerUpdates { user },
+    ] {
+        info_client
+            .subscribe(subscription, sender.clone())
+            .await
+            .unwrap();
+    }
+
+    let mut balances = SpotBalances::new(user, &info_client.spot_meta().await.unwrap());
+    balances.sync(&info_client).await.unwrap();
+
+    // Syncing on the disconnect itself would miss the updates until the reconnect, which only
+    // come back as skipped snapshots, so wait for the first message after it
+    let mut needs_sync = false;
+    loop {
+        let portfolio = balances
+            .portfolio(
+                &info_client.user_state(user).await.unwrap(),
+                &info_client.all_mids().await.unwrap(),
+            )
+            .unwrap();
+        info!(
+            "Perp account value: {}, spot value: {}, spot balances: {:?}",
+            portfolio.perp_account_value,
+            portfolio.spot_value(),
+            portfolio.spot
+        );
+
+        let Some(message) = receiver.recv().await else {
+            break;
+        };
+        if let Message::NoData = message {
+            needs_sync = true;
+            continue;
+        }
+        if needs_sync {
+            balances.sync(&info_client).await.unwrap();
+            needs_sync = false;
+        }
+        balances.on_message(&message).unwrap();
+    }
+}
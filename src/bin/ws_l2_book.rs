@@ -18,6 +18,8 @@ async fn main() {
         .subscribe(
             Subscription::L2Book {
                 coin: "ETH".to_string(),
+                n_sig_figs: None,
+                mantissa: None,
             },
             sender,
         )
@@ -30,6 +30,12 @@ async fn main() {
 
     // this loop ends when we unsubscribe
     while let Some(Message::WebData2(web_data2)) = receiver.recv().await {
-        info!("Received web data: {web_data2:?}");
+        let data = web_data2.data;
+        info!(
+            "Account value: {}, open orders: {}, ETH context: {:?}",
+            data.clearinghouse_state.margin_summary.account_value,
+            data.open_orders.len(),
+            data.asset_ctx("ETH")
+        );
     }
 }
@@ -0,0 +1,461 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    helpers::{float_to_string_for_hashing, now_timestamp_ms},
+    prelude::*,
+    CandleData, CandlesSnapshotResponse, Error, InfoClient, Message, Trade,
+};
+
+/// Candle intervals supported by the `candle` subscription and `candles_snapshot`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CandleInterval {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "3m")]
+    ThreeMinutes,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "30m")]
+    ThirtyMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "2h")]
+    TwoHours,
+    #[serde(rename = "4h")]
+    FourHours,
+    #[serde(rename = "8h")]
+    EightHours,
+    #[serde(rename = "12h")]
+    TwelveHours,
+    #[serde(rename = "1d")]
+    OneDay,
+    #[serde(rename = "3d")]
+    ThreeDays,
+    #[serde(rename = "1w")]
+    OneWeek,
+    #[serde(rename = "1M")]
+    OneMonth,
+}
+
+// Intervals dividing a day, so their candles line up with bars aligned to the Unix epoch
+const BACKFILL_INTERVALS: [CandleInterval; 11] = [
+    CandleInterval::OneDay,
+    CandleInterval::TwelveHours,
+    CandleInterval::EightHours,
+    CandleInterval::FourHours,
+    CandleInterval::TwoHours,
+    CandleInterval::OneHour,
+    CandleInterval::ThirtyMinutes,
+    CandleInterval::FifteenMinutes,
+    CandleInterval::FiveMinutes,
+    CandleInterval::ThreeMinutes,
+    CandleInterval::OneMinute,
+];
+
+impl CandleInterval {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CandleInterval::OneMinute => "1m",
+            CandleInterval::ThreeMinutes => "3m",
+            CandleInterval::FiveMinutes => "5m",
+            CandleInterval::FifteenMinutes => "15m",
+            CandleInterval::ThirtyMinutes => "30m",
+            CandleInterval::OneHour => "1h",
+            CandleInterval::TwoHours => "2h",
+            CandleInterval::FourHours => "4h",
+            CandleInterval::EightHours => "8h",
+            CandleInterval::TwelveHours => "12h",
+            CandleInterval::OneDay => "1d",
+            CandleInterval::ThreeDays => "3d",
+            CandleInterval::OneWeek => "1w",
+            CandleInterval::OneMonth => "1M",
+        }
+    }
+
+    /// Length of the interval, `None` for months as they vary in length.
+    pub fn duration(&self) -> Option<Duration> {
+        let minutes = match self {
+            CandleInterval::OneMinute => 1,
+            CandleInterval::ThreeMinutes => 3,
+            CandleInterval::FiveMinutes => 5,
+            CandleInterval::FifteenMinutes => 15,
+            CandleInterval::ThirtyMinutes => 30,
+            CandleInterval::OneHour => 60,
+            CandleInterval::TwoHours => 2 * 60,
+            CandleInterval::FourHours => 4 * 60,
+            CandleInterval::EightHours => 8 * 60,
+            CandleInterval::TwelveHours => 12 * 60,
+            CandleInterval::OneDay => 24 * 60,
+            CandleInterval::ThreeDays => 3 * 24 * 60,
+            CandleInterval::OneWeek => 7 * 24 * 60,
+            CandleInterval::OneMonth => return None,
+        };
+        Some(Duration::from_secs(minutes * 60))
+    }
+}
+
+impl fmt::Display for CandleInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CandleInterval {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<CandleInterval> {
+        serde_json::from_value(serde_json::Value::String(s.to_string()))
+            .map_err(|_| Error::GenericParse(format!("Unknown candle interval: {s}")))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Bar {
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+    num_trades: u64,
+}
+
+impl Bar {
+    // Extends the bar with a later one
+    fn extend(&mut self, later: &Bar) {
+        self.high = self.high.max(later.high);
+        self.low = self.low.min(later.low);
+        self.close = later.close;
+        self.volume += later.volume;
+        self.num_trades += later.num_trades;
+    }
+}
+
+/// Builds [`CandleData`] bars of any interval from the `trades` channel.
+///
+/// Bars are aligned to multiples of the interval since the Unix epoch. After a reconnect, the
+/// trades missed in the meantime can be filled in with [`CandleAggregator::backfill`].
+#[derive(Debug, Clone)]
+pub struct CandleAggregator {
+    coin: String,
+    interval: String,
+    interval_ms: u64,
+    bars: BTreeMap<u64, Bar>,
+    // Latest trade time counted and the trades counted at that time, so that trades sent again
+    // on resubscribing aren't counted twice
+    last_trade_time: u64,
+    last_trade_tids: HashSet<u64>,
+}
+
+impl CandleAggregator {
+    pub fn new(coin: &str, interval: Duration) -> CandleAggregator {
+        let interval_ms = (interval.as_millis() as u64).max(1);
+        CandleAggregator {
+            coin: coin.to_string(),
+            interval: interval_label(interval_ms),
+            interval_ms,
+            bars: BTreeMap::new(),
+            last_trade_time: 0,
+            last_trade_tids: HashSet::new(),
+        }
+    }
+
+    pub fn coin(&self) -> &str {
+        &self.coin
+    }
+
+    /// Counts trades of the aggregated coin, ignoring others.
+    pub fn on_message(&mut self, message: &Message) -> Result<()> {
+        if let Message::Trades(trades) = message {
+            for trade in &trades.data {
+                self.on_trade(trade)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn on_trade(&mut self, trade: &Trade) -> Result<()> {
+        if trade.coin != self.coin
+            || trade.time < self.last_trade_time
+            || (trade.time == self.last_trade_time && self.last_trade_tids.contains(&trade.tid))
+        {
+            return Ok(());
+        }
+        let px: f64 = trade.px.parse().map_err(|_| Error::FloatStringParse)?;
+        let sz: f64 = trade.sz.parse().map_err(|_| Error::FloatStringParse)?;
+
+        if trade.time > self.last_trade_time {
+            self.last_trade_time = trade.time;
+            self.last_trade_tids.clear();
+        }
+        self.last_trade_tids.insert(trade.tid);
+
+        let bar = Bar {
+            open: px,
+            high: px,
+            low: px,
+            close: px,
+            volume: sz,
+            num_trades: 1,
+        };
+        let time_open = trade.time - trade.time % self.interval_ms;
+        self.bars
+            .entry(time_open)
+            .and_modify(|existing| existing.extend(&bar))
+            .or_insert(bar);
+        Ok(())
+    }
+
+    /// Replaces the bars covered by `candles` with ones built from them. `candles` must be of an
+    /// interval dividing the aggregator's, and `as_of` is when they were fetched: trades up to
+    /// then are already counted in them, so they are ignored from here on.
+    pub fn merge_snapshot(
+        &mut self,
+        candles: &[CandlesSnapshotResponse],
+        as_of: u64,
+    ) -> Result<()> {
+        let mut snapshot_bars: BTreeMap<u64, Bar> = BTreeMap::new();
+        let mut candles: Vec<&CandlesSnapshotResponse> = candles
+            .iter()
+            .filter(|candle| candle.coin == self.coin)
+            .collect();
+        candles.sort_by_key(|candle| candle.time_open);
+        for candle in candles {
+            let interval: CandleInterval = candle.candle_interval.parse()?;
+            let interval_ms = interval
+                .duration()
+                .map(|duration| duration.as_millis() as u64);
+            if !interval_ms.is_some_and(|interval_ms| self.interval_ms.is_multiple_of(interval_ms))
+            {
+                return Err(Error::GenericParse(format!(
+                    "Cannot merge {interval} candles into {} bars",
+                    self.interval
+                )));
+            }
+
+            let parse = |value: &str| value.parse::<f64>().map_err(|_| Error::FloatStringParse);
+            let bar = Bar {
+                open: parse(&candle.open)?,
+                high: parse(&candle.high)?,
+                low: parse(&candle.low)?,
+                close: parse(&candle.close)?,
+                volume: parse(&candle.vlm)?,
+                num_trades: candle.num_trades,
+            };
+            let time_open = candle.time_open - candle.time_open % self.interval_ms;
+            snapshot_bars
+                .entry(time_open)
+                .and_modify(|existing| existing.extend(&bar))
+                .or_insert(bar);
+        }
+        self.bars.extend(snapshot_bars);
+
+        if as_of > self.last_trade_time {
+            self.last_trade_time = as_of;
+            self.last_trade_tids.clear();
+        }
+        Ok(())
+    }
+
+    /// Fetches candles from `start_time` until now and merges them in with
+    /// [`CandleAggregator::merge_snapshot`], e.g. after a reconnect.
+    ///
+    /// The coarsest supported interval dividing the aggregator's is fetched, so the interval
+    /// must be a whole number of minutes. Snapshots are limited to the latest 5000 candles.
+    pub async fn backfill(&mut self, info_client: &InfoClient, start_time: u64) -> Result<()> {
+        let interval = BACKFILL_INTERVALS
+            .into_iter()
+            .find(|interval| {
+                interval.duration().is_some_and(|duration| {
+                    self.interval_ms.is_multiple_of(duration.as_millis() as u64)
+                })
+            })
+            .ok_or_else(|| {
+                Error::GenericRequest(format!("Cannot backfill {} bars", self.interval))
+            })?;
+        let start_time = start_time - start_time % self.interval_ms;
+        let as_of = now_timestamp_ms();
+        let candles = info_client
+            .candles_snapshot(self.coin.clone(), interval, start_time, as_of)
+            .await?;
+        self.merge_snapshot(&candles, as_of)
+    }
+
+    /// All bars, oldest first. The latest one is still being built.
+    pub fn candles(&self) -> Vec<CandleData> {
+        self.bars
+            .iter()
+            .map(|(&time_open, bar)| self.candle(time_open, bar))
+            .collect()
+    }
+
+    pub fn latest(&self) -> Option<CandleData> {
+        self.bars
+            .last_key_value()
+            .map(|(&time_open, bar)| self.candle(time_open, bar))
+    }
+
+    /// Drops bars opened before `time`.
+    pub fn prune_before(&mut self, time: u64) {
+        self.bars = self.bars.split_off(&time);
+    }
+
+    fn candle(&self, time_open: u64, bar: &Bar) -> CandleData {
+        CandleData {
+            time_close: time_open + self.interval_ms - 1,
+            close: float_to_string_for_hashing(bar.close),
+            high: float_to_string_for_hashing(bar.high),
+            interval: self.interval.clone(),
+            low: float_to_string_for_hashing(bar.low),
+            num_trades: bar.num_trades,
+            open: float_to_string_for_hashing(bar.open),
+            coin: self.coin.clone(),
+            time_open,
+            volume: float_to_string_for_hashing(bar.volume),
+        }
+    }
+}
+
+// Name of a supported interval of the same length, otherwise its length in the largest fitting unit
+fn interval_label(interval_ms: u64) -> String {
+    if let Some(interval) = BACKFILL_INTERVALS
+        .into_iter()
+        .chain([CandleInterval::ThreeDays, CandleInterval::OneWeek])
+        .find(|interval| {
+            interval
+                .duration()
+                .is_some_and(|duration| duration.as_millis() as u64 == interval_ms)
+        })
+    {
+        return interval.to_string();
+    }
+    for (unit, unit_ms) in [
+        ("d", 86_400_000),
+        ("h", 3_600_000),
+        ("m", 60_000),
+        ("s", 1_000),
+    ] {
+        if interval_ms.is_multiple_of(unit_ms) {
+            return format!("{}{unit}", interval_ms / unit_ms);
+        }
+    }
+    format!("{interval_ms}ms")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(time: u64, tid: u64, px: &str, sz: &str) -> Trade {
+        Trade {
+            coin: "ETH".to_string(),
+            side: "B".to_string(),
+            px: px.to_string(),
+            sz: sz.to_string(),
+            time,
+            hash: "0x0".to_string(),
+            tid,
+        }
+    }
+
+    #[test]
+    fn test_candle_interval() -> Result<()> {
+        assert_eq!(
+            "15m".parse::<CandleInterval>()?,
+            CandleInterval::FifteenMinutes
+        );
+        assert_eq!(CandleInterval::OneMonth.to_string(), "1M");
+        assert!("2m".parse::<CandleInterval>().is_err());
+        assert_eq!(
+            serde_json::to_string(&crate::Subscription::Candle {
+                coin: "ETH".to_string(),
+                interval: CandleInterval::OneHour,
+            })
+            .unwrap(),
+            r#"{"type":"candle","coin":"ETH","interval":"1h"}"#
+        );
+        assert_eq!(interval_label(120_000), "2m");
+        assert_eq!(interval_label(604_800_000), "1w");
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_and_merge() -> Result<()> {
+        let mut aggregator = CandleAggregator::new("ETH", Duration::from_secs(120));
+        for trade in [
+            trade(1_000, 1, "2000", "1"),
+            trade(50_000, 2, "2010", "0.5"),
+            trade(50_000, 2, "2010", "0.5"),
+            trade(119_999, 3, "1990", "2"),
+            trade(120_000, 4, "1995", "1"),
+            // Sent again on resubscribing
+            trade(1_000, 1, "2000", "1"),
+        ] {
+            aggregator.on_trade(&trade)?;
+        }
+
+        let candles = aggregator.candles();
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0].interval, "2m");
+        assert_eq!((candles[0].time_open, candles[0].time_close), (0, 119_999));
+        assert_eq!(
+            [
+                &candles[0].open,
+                &candles[0].high,
+                &candles[0].low,
+                &candles[0].close
+            ],
+            ["2000", "2010", "1990", "1990"]
+        );
+        assert_eq!(
+            (candles[0].volume.as_str(), candles[0].num_trades),
+            ("3.5", 3)
+        );
+
+        // Trades from 120s to 300s were missed while disconnected
+        let snapshot: Vec<CandlesSnapshotResponse> = serde_json::from_value(serde_json::json!([
+            {"t": 120_000, "T": 179_999, "s": "ETH", "i": "1m", "o": "1995", "c": "1998", "h": "2001", "l": "1994", "v": "3", "n": 4},
+            {"t": 180_000, "T": 239_999, "s": "ETH", "i": "1m", "o": "1998", "c": "2003", "h": "2004", "l": "1997", "v": "2", "n": 2},
+            {"t": 240_000, "T": 299_999, "s": "ETH", "i": "1m", "o": "2003", "c": "2002", "h": "2003", "l": "2002", "v": "1", "n": 1},
+        ]))
+        .unwrap();
+        aggregator.merge_snapshot(&snapshot, 260_000)?;
+        aggregator.on_trade(&trade(255_000, 5, "2002", "1"))?;
+        aggregator.on_trade(&trade(270_000, 6, "2005", "0.5"))?;
+
+        let candles = aggregator.candles();
+        assert_eq!(candles.len(), 3);
+        assert_eq!(
+            [
+                &candles[1].open,
+                &candles[1].high,
+                &candles[1].low,
+                &candles[1].close
+            ],
+            ["1995", "2004", "1994", "2003"]
+        );
+        assert_eq!(
+            (candles[1].volume.as_str(), candles[1].num_trades),
+            ("5", 6)
+        );
+        let latest = aggregator.latest().unwrap();
+        assert_eq!(latest.time_open, 240_000);
+        assert_eq!(
+            (latest.close.as_str(), latest.high.as_str()),
+            ("2005", "2005")
+        );
+        assert_eq!((latest.volume.as_str(), latest.num_trades), ("1.5", 2));
+
+        aggregator.prune_before(120_000);
+        assert_eq!(aggregator.candles()[0].time_open, 120_000);
+        Ok(())
+    }
+}
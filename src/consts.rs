@@ -1,5 +1,7 @@
 pub static MAINNET_API_URL: &str = "https://api.hyperliquid.xyz";
 pub static TESTNET_API_URL: &str = "https://api.hyperliquid-testnet.xyz";
 pub static LOCAL_API_URL: &str = "http://localhost:3001";
+/// Chain id used in the EIP-712 domain of user-signed actions (Arbitrum Sepolia).
+pub const DEFAULT_SIGNATURE_CHAIN_ID: u64 = 421614;
 pub const EPSILON: f64 = 1e-9;
 pub(crate) const INF_BPS: u16 = 10_001;
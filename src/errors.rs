@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::RiskViolation;
+
 #[derive(Error, Debug, Clone)]
 pub enum Error {
     // TODO: turn some embedded types into errors instead of strings
@@ -59,6 +61,25 @@ pub enum Error {
     NoCloid,
     #[error("ECDSA signature failed: {0:?}")]
     SignatureFailure(String),
+    #[error("Signature recovery failed: {0:?}")]
+    SignatureRecovery(String),
     #[error("Vault address not found")]
     VaultAddressNotFound,
+    #[error("Order rejected by risk checks: {0}")]
+    RiskRejected(RiskViolation),
+    #[error("Invalid cloid: {0:?}")]
+    InvalidCloid(String),
+    #[error("Agent approval failed: {0:?}")]
+    AgentApproval(String),
+    #[error("Action nonce {action} does not match payload nonce {payload}")]
+    NonceMismatch { action: u64, payload: u64 },
+    #[error("Replay speed must be positive, got {0}")]
+    InvalidReplaySpeed(f64),
+    #[error("IO error: {0:?}")]
+    Io(String),
+    #[error("Invalid book aggregation: {n_sig_figs:?} significant figures, mantissa {mantissa:?}")]
+    InvalidBookAggregation {
+        n_sig_figs: Option<u32>,
+        mantissa: Option<u32>,
+    },
 }
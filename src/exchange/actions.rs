@@ -1,14 +1,14 @@
-pub(crate) use ethers::abi::{ParamType, Tokenizable, encode};
+pub(crate) use ethers::abi::{encode, ParamType, Tokenizable};
 pub(crate) use ethers::types::transaction::eip712;
 pub(crate) use ethers::types::transaction::eip712::{
-    EIP712Domain, Eip712, Eip712Error, encode_eip712_type,
+    encode_eip712_type, EIP712Domain, Eip712, Eip712Error,
 };
 pub(crate) use ethers::types::{H160, U256};
 pub(crate) use ethers::utils::keccak256;
 use serde::{Deserialize, Serialize};
 
-use super::BuilderInfo;
 use super::cancel::CancelRequestCloid;
+use super::BuilderInfo;
 use crate::exchange::cancel::CancelRequest;
 use crate::exchange::modify::ModifyRequest;
 use crate::exchange::order::OrderRequest;
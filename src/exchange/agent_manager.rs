@@ -0,0 +1,254 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use ethers::{
+    signers::{LocalWallet, Signer},
+    types::H160,
+};
+use log::{error, info, warn};
+use rand::thread_rng;
+use tokio::{
+    spawn,
+    sync::{Mutex, RwLock},
+    task::JoinHandle,
+    time,
+};
+
+use crate::{helpers::next_nonce, prelude::*, Error, ExchangeClient, ExchangeResponseStatus};
+
+/// An agent approved and persisted by an [`AgentManager`].
+#[derive(Debug, Clone)]
+pub struct ManagedAgent {
+    pub name: String,
+    pub address: H160,
+    pub keystore_path: PathBuf,
+}
+
+/// Creates, persists and rotates named agents for an [`ExchangeClient`].
+///
+/// Agent keys never leave the manager unencrypted: each new agent is written to
+/// `keystore_dir` as an encrypted JSON keystore before it is approved with the master wallet.
+/// Once approved, the client's signing wallet is switched over to the agent and the previous
+/// agent is revoked and its keystore removed.
+#[derive(Debug)]
+pub struct AgentManager {
+    master_wallet: LocalWallet,
+    exchange_client: Arc<RwLock<ExchangeClient>>,
+    keystore_dir: PathBuf,
+    password: String,
+    name_prefix: String,
+    current: Option<ManagedAgent>,
+    // Agents replaced by a rotation whose revocation failed, retried on the next rotation
+    pending_revocations: Vec<ManagedAgent>,
+}
+
+impl AgentManager {
+    /// `name_prefix` is combined with a nonce to name each agent, so keep it short.
+    pub fn new(
+        master_wallet: LocalWallet,
+        exchange_client: Arc<RwLock<ExchangeClient>>,
+        keystore_dir: impl Into<PathBuf>,
+        password: String,
+        name_prefix: &str,
+    ) -> AgentManager {
+        AgentManager {
+            master_wallet,
+            exchange_client,
+            keystore_dir: keystore_dir.into(),
+            password,
+            name_prefix: name_prefix.to_string(),
+            current: None,
+            pending_revocations: Vec::new(),
+        }
+    }
+
+    pub fn current(&self) -> Option<&ManagedAgent> {
+        self.current.as_ref()
+    }
+
+    /// Decrypts a previously persisted agent and switches the client over to it, e.g. after a
+    /// restart. The agent is assumed to still be approved.
+    pub async fn load(&mut self, name: &str) -> Result<ManagedAgent> {
+        let keystore_path = self.keystore_dir.join(name);
+        let wallet = LocalWallet::decrypt_keystore(&keystore_path, &self.password)
+            .map_err(|e| Error::Wallet(e.to_string()))?;
+        let agent = ManagedAgent {
+            name: name.to_string(),
+            address: wallet.address(),
+            keystore_path,
+        };
+
+        self.exchange_client.write().await.wallet = wallet;
+        self.current = Some(agent.clone());
+        Ok(agent)
+    }
+
+    /// Creates and approves a new agent, switches the client to it and revokes the previous one.
+    pub async fn rotate(&mut self) -> Result<ManagedAgent> {
+        // Nonces are unique within the process, so rotations can't overwrite a keystore
+        let name = format!("{}-{}", self.name_prefix, next_nonce());
+        let (wallet, _) = LocalWallet::new_keystore(
+            &self.keystore_dir,
+            &mut thread_rng(),
+            &self.password,
+            Some(&name),
+        )
+        .map_err(|e| Error::Wallet(e.to_string()))?;
+        let agent = ManagedAgent {
+            name: name.clone(),
+            address: wallet.address(),
+            keystore_path: self.keystore_dir.join(&name),
+        };
+
+        if let Err(err) = self.approve(agent.address, &agent.name).await {
+            remove_keystore(&agent.keystore_path);
+            return Err(err);
+        }
+        info!("Approved agent {} ({:?})", agent.name, agent.address);
+
+        self.exchange_client.write().await.wallet = wallet;
+        if let Some(previous) = self.current.replace(agent.clone()) {
+            self.pending_revocations.push(previous);
+        }
+        self.revoke_pending().await;
+
+        Ok(agent)
+    }
+
+    async fn approve(&self, address: H160, name: &str) -> Result<()> {
+        let response = self
+            .exchange_client
+            .read()
+            .await
+            .approve_agent_with(address, Some(name.to_string()), Some(&self.master_wallet))
+            .await?;
+        match response {
+            ExchangeResponseStatus::Ok(_) => Ok(()),
+            ExchangeResponseStatus::Err(e) => Err(Error::AgentApproval(e)),
+        }
+    }
+
+    async fn revoke_pending(&mut self) {
+        let mut still_pending = Vec::new();
+        for agent in std::mem::take(&mut self.pending_revocations) {
+            // Revoking the current agent's name would remove the agent the client signs with
+            if self
+                .current
+                .as_ref()
+                .is_some_and(|current| current.name == agent.name)
+            {
+                warn!("Not revoking agent {}: it is the current agent", agent.name);
+                continue;
+            }
+            // Approving the zero address under an agent's name removes that agent
+            match self.approve(H160::zero(), &agent.name).await {
+                Ok(()) => {
+                    info!("Revoked agent {} ({:?})", agent.name, agent.address);
+                    remove_keystore(&agent.keystore_path);
+                }
+                Err(err) => {
+                    error!("Could not revoke agent {}: {err}", agent.name);
+                    still_pending.push(agent);
+                }
+            }
+        }
+        self.pending_revocations = still_pending;
+    }
+
+    /// Rotates the agent every `interval`, starting one `interval` from now.
+    pub fn start_rotation(manager: Arc<Mutex<AgentManager>>, interval: Duration) -> JoinHandle<()> {
+        spawn(async move {
+            let mut interval = time::interval(interval);
+            // The first tick completes immediately
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(err) = manager.lock().await.rotate().await {
+                    error!("Agent rotation failed: {err}");
+                }
+            }
+        })
+    }
+}
+
+fn remove_keystore(path: &Path) {
+    if let Err(err) = fs::remove_file(path) {
+        warn!("Could not remove agent keystore {}: {err}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn test_manager(keystore_dir: &Path) -> Result<AgentManager> {
+        // The paper exchange accepts agent approvals without any network access
+        let (exchange_client, _) = ExchangeClient::paper_test_client().await?;
+        let master_wallet = exchange_client.wallet.clone();
+
+        fs::create_dir_all(keystore_dir).map_err(|e| Error::Wallet(e.to_string()))?;
+        Ok(AgentManager::new(
+            master_wallet,
+            Arc::new(RwLock::new(exchange_client)),
+            keystore_dir,
+            "password".to_string(),
+            "test",
+        ))
+    }
+
+    fn keystore_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("agent-manager-{name}-{}", std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn test_keystore_round_trip() -> Result<()> {
+        let dir = keystore_dir("round-trip");
+        let mut manager = test_manager(&dir).await?;
+        let agent = manager.rotate().await?;
+        assert!(agent.keystore_path.exists());
+
+        let mut restarted = test_manager(&dir).await?;
+        let loaded = restarted.load(&agent.name).await?;
+        assert_eq!(loaded.address, agent.address);
+        assert_eq!(
+            restarted.exchange_client.read().await.wallet.address(),
+            agent.address
+        );
+
+        fs::remove_dir_all(&dir).map_err(|e| Error::Wallet(e.to_string()))?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rotate_and_revoke() -> Result<()> {
+        let dir = keystore_dir("rotate");
+        let mut manager = test_manager(&dir).await?;
+        let first = manager.rotate().await?;
+        let second = manager.rotate().await?;
+        assert_ne!(first.name, second.name);
+        assert_ne!(first.address, second.address);
+        assert_eq!(
+            manager.exchange_client.read().await.wallet.address(),
+            second.address
+        );
+        assert!(!first.keystore_path.exists());
+        assert!(second.keystore_path.exists());
+        assert!(manager.pending_revocations.is_empty());
+
+        // An agent sharing the current agent's name is never revoked
+        manager.pending_revocations.push(ManagedAgent {
+            address: first.address,
+            ..second.clone()
+        });
+        manager.revoke_pending().await;
+        assert!(second.keystore_path.exists());
+        assert!(manager.pending_revocations.is_empty());
+
+        fs::remove_dir_all(&dir).map_err(|e| Error::Wallet(e.to_string()))?;
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::Cloid;
+
 #[derive(Debug)]
 pub struct ClientCancelRequest {
     pub asset: String,
@@ -17,11 +19,11 @@ pub struct CancelRequest {
 #[derive(Debug)]
 pub struct ClientCancelRequestCloid {
     pub asset: String,
-    pub cloid: String,
+    pub cloid: Cloid,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CancelRequestCloid {
     pub asset: u32,
-    pub cloid: String,
+    pub cloid: Cloid,
 }
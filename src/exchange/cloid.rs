@@ -0,0 +1,193 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use ethers::utils::{hex, keccak256};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{prelude::*, Error};
+
+/// A 128-bit client order id, sent to the exchange as a 0x-prefixed hex string.
+///
+/// Raw values are parsed with [`str::parse`] and labels are hashed with [`Cloid::from_label`];
+/// there is no conversion from strings that guesses which of the two was meant.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cloid([u8; 16]);
+
+impl Cloid {
+    pub fn from_raw(bytes: [u8; 16]) -> Cloid {
+        Cloid(bytes)
+    }
+
+    /// Derives a cloid from an arbitrary label by taking the first 16 bytes of its keccak hash.
+    /// The label can't be recovered from the result; keep it in a [`CloidRegistry`] if needed.
+    pub fn from_label(label: &str) -> Cloid {
+        let hash = keccak256(label.as_bytes());
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&hash[..16]);
+        Cloid(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+/// Parses a raw 0x-prefixed 128-bit value, e.g. a cloid returned by the exchange.
+impl FromStr for Cloid {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Cloid> {
+        let digits = s
+            .strip_prefix("0x")
+            .filter(|digits| digits.len() == 32)
+            .ok_or_else(|| Error::InvalidCloid(s.to_string()))?;
+        let mut bytes = [0u8; 16];
+        hex::decode_to_slice(digits, &mut bytes).map_err(|_| Error::InvalidCloid(s.to_string()))?;
+        Ok(Cloid(bytes))
+    }
+}
+
+impl fmt::Display for Cloid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{}", hex::encode(self.0))
+    }
+}
+
+impl fmt::Debug for Cloid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cloid({self})")
+    }
+}
+
+impl Serialize for Cloid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Cloid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Cloid, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Maps cloids back to the local labels they were created from, so that cloids seen in open
+/// orders, order updates or fills can be attributed to our own ids.
+#[derive(Debug, Default, Clone)]
+pub struct CloidRegistry {
+    labels: HashMap<Cloid, String>,
+    cloids: HashMap<String, Cloid>,
+}
+
+impl CloidRegistry {
+    pub fn new() -> CloidRegistry {
+        CloidRegistry::default()
+    }
+
+    /// Returns the cloid for `label`, deriving and remembering it on first use.
+    pub fn register(&mut self, label: &str) -> Cloid {
+        if let Some(&cloid) = self.cloids.get(label) {
+            return cloid;
+        }
+        let cloid = Cloid::from_label(label);
+        self.insert(cloid, label);
+        cloid
+    }
+
+    /// Associates an existing cloid, e.g. a raw one, with a label.
+    pub fn insert(&mut self, cloid: Cloid, label: &str) {
+        if let Some(previous_label) = self.labels.insert(cloid, label.to_string()) {
+            self.cloids.remove(&previous_label);
+        }
+        if let Some(previous_cloid) = self.cloids.insert(label.to_string(), cloid) {
+            if previous_cloid != cloid {
+                self.labels.remove(&previous_cloid);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, cloid: &Cloid) -> Option<String> {
+        let label = self.labels.remove(cloid)?;
+        self.cloids.remove(&label);
+        Some(label)
+    }
+
+    pub fn label(&self, cloid: &Cloid) -> Option<&str> {
+        self.labels.get(cloid).map(String::as_str)
+    }
+
+    pub fn cloid(&self, label: &str) -> Option<Cloid> {
+        self.cloids.get(label).copied()
+    }
+
+    /// Looks up the label for a cloid string as reported by the exchange.
+    pub fn resolve(&self, exchange_cloid: &str) -> Option<&str> {
+        self.label(&exchange_cloid.parse().ok()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cloid_parsing() -> Result<()> {
+        let raw: Cloid = "0x00000000000000000000000000000001".parse()?;
+        assert_eq!(raw.as_bytes()[15], 1);
+        assert_eq!(raw.to_string(), "0x00000000000000000000000000000001");
+
+        assert!("0x1".parse::<Cloid>().is_err());
+        assert!("0x0000000000000000000000000000000g"
+            .parse::<Cloid>()
+            .is_err());
+
+        let json = serde_json::to_string(&raw).map_err(|e| Error::JsonParse(e.to_string()))?;
+        assert_eq!(json, "\"0x00000000000000000000000000000001\"");
+        let parsed: Cloid =
+            serde_json::from_str(&json).map_err(|e| Error::JsonParse(e.to_string()))?;
+        assert_eq!(parsed, raw);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cloid_registry() {
+        let mut registry = CloidRegistry::new();
+        let cloid = registry.register("grid-level-3");
+        assert_eq!(cloid, Cloid::from_label("grid-level-3"));
+        assert_eq!(registry.register("grid-level-3"), cloid);
+        assert_eq!(registry.resolve(&cloid.to_string()), Some("grid-level-3"));
+
+        let raw = Cloid::from_raw([7; 16]);
+        registry.insert(raw, "hedge");
+        assert_eq!(registry.cloid("hedge"), Some(raw));
+        assert_eq!(registry.label(&raw), Some("hedge"));
+
+        assert_eq!(registry.remove(&cloid).as_deref(), Some("grid-level-3"));
+        assert_eq!(registry.cloid("grid-level-3"), None);
+        assert_eq!(registry.resolve("not a cloid"), None);
+    }
+
+    #[test]
+    fn test_cloid_from_label() {
+        // Basic test case
+        assert_eq!(
+            Cloid::from_label("test").to_string(),
+            "0x9c22ff5f21f0b81b113e63f7db6da94fedef11b2119b4088b89664fb9a3cb658"[0..34] // 0x + 16*2 hex chars
+        );
+        // Test with empty string
+        assert_eq!(
+            Cloid::from_label("").to_string(),
+            "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"[0..34]
+        );
+        // Test with a longer string
+        assert_eq!(
+            Cloid::from_label("a longer test string for hashing").to_string(),
+            "0xb2ae80682ae56e5e8a930a907beeb1460f768176176b60f677105e9e661e3aaa"[0..34]
+        );
+        // Test with unicode
+        assert_eq!(
+            Cloid::from_label("你好世界").to_string(), // "Hello World" in Chinese
+            "0xd3f05f6be06f8b0a91c1ab0ea49f4554921f35d01eb600778ca36273f6b6d2a8"[0..34]
+        );
+    }
+}
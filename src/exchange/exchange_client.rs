@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::sync::Arc;
 
 use ethers::abi::AbiEncode;
 use ethers::signers::{LocalWallet, Signer};
@@ -10,7 +10,7 @@ use serde::{Deserialize, Serialize};
 
 use super::cancel::ClientCancelRequestCloid;
 use super::order::{MarketCloseParams, MarketOrderParams};
-use super::{BuilderInfo, ClientLimit, ClientOrder, UsdClassTransfer};
+use super::{BuilderInfo, ClientLimit, ClientOrder, RiskManager, UsdClassTransfer};
 
 use crate::exchange::actions::{
     ApproveAgent, ApproveBuilderFee, BulkCancel, BulkModify, BulkOrder, SetReferrer,
@@ -19,33 +19,41 @@ use crate::exchange::actions::{
 use crate::exchange::cancel::{CancelRequest, CancelRequestCloid};
 use crate::exchange::modify::{ClientModifyRequest, ModifyRequest};
 use crate::exchange::{ClientCancelRequest, ClientOrderRequest};
-use crate::helpers::{generate_random_key, next_nonce, string_to_hex_string};
-use crate::info::info_client::InfoClient;
+use crate::helpers::{generate_random_key, next_nonce};
 use crate::meta::Meta;
+use crate::metadata_cache::{AssetMetadata, MetadataCache};
 use crate::prelude::*;
 use crate::req::HttpClient;
-use crate::signature::{sign_l1_action, sign_typed_data};
+use crate::signature::{sign_l1_action, sign_typed_data, verify_l1_action, verify_typed_data};
 use crate::{
-    BaseUrl, BulkCancelCloid, Error, ExchangeResponseStatus, SpotSend, SpotUser, VaultTransfer,
-    Withdraw3,
+    BaseUrl, BulkCancelCloid, Error, ExchangeResponseStatus, NetworkConfig, PaperExchange,
+    SpotSend, SpotUser, VaultTransfer, Withdraw3,
 };
 
 #[derive(Debug)]
 pub struct ExchangeClient {
     pub http_client: HttpClient,
+    pub network: NetworkConfig,
     pub wallet: LocalWallet,
-    pub meta: Meta,
+    pub metadata: Arc<MetadataCache>,
     pub vault_address: Option<H160>,
-    pub coin_to_asset: HashMap<String, u32>,
+    /// Pre-trade checks applied to orders and modifies before signing
+    pub risk_manager: Option<Arc<RiskManager>>,
+    /// Simulated exchange that signed actions are sent to instead of the API
+    pub paper_exchange: Option<Arc<PaperExchange>>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// A signed `/exchange` request.
+///
+/// Payloads serialize to exactly what the exchange endpoint expects, so they can be signed on
+/// one machine, written to disk and submitted later with [`ExchangeClient::submit`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-struct ExchangePayload {
-    action: serde_json::Value,
-    signature: Signature,
-    nonce: u64,
-    vault_address: Option<H160>,
+pub struct ExchangePayload {
+    pub action: serde_json::Value,
+    pub signature: Signature,
+    pub nonce: u64,
+    pub vault_address: Option<H160>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -70,7 +78,7 @@ pub enum Actions {
 }
 
 impl Actions {
-    fn hash(&self, timestamp: u64, vault_address: Option<H160>) -> Result<H256> {
+    pub fn hash(&self, timestamp: u64, vault_address: Option<H160>) -> Result<H256> {
         let mut bytes =
             rmp_serde::to_vec_named(self).map_err(|e| Error::RmpParse(e.to_string()))?;
         bytes.extend(timestamp.to_be_bytes());
@@ -82,6 +90,121 @@ impl Actions {
         }
         Ok(H256(ethers::utils::keccak256(bytes)))
     }
+
+    /// Nonce (or time) carried inside user-signed actions, `None` for L1 actions.
+    pub fn user_signed_nonce(&self) -> Option<u64> {
+        match self {
+            Actions::UsdSend(usd_send) => Some(usd_send.time),
+            Actions::ApproveAgent(approve_agent) => Some(approve_agent.nonce),
+            Actions::Withdraw3(withdraw) => Some(withdraw.time),
+            Actions::SpotSend(spot_send) => Some(spot_send.time),
+            Actions::ApproveBuilderFee(approve_builder_fee) => Some(approve_builder_fee.nonce),
+            Actions::UsdClassTransfer(usd_class_transfer) => Some(usd_class_transfer.nonce),
+            _ => None,
+        }
+    }
+
+    fn check_nonce(&self, nonce: u64) -> Result<()> {
+        match self.user_signed_nonce() {
+            Some(action) if action != nonce => Err(Error::NonceMismatch {
+                action,
+                payload: nonce,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Signs the action without touching the network.
+    ///
+    /// User-signed actions (transfers, withdrawals, agent and builder approvals) are signed as
+    /// EIP-712 typed data and carry their own nonce, which must match `nonce` or
+    /// [`Error::NonceMismatch`] is returned. Every other action is signed as an L1 action over
+    /// [`Actions::hash`].
+    pub fn sign(
+        &self,
+        wallet: &LocalWallet,
+        nonce: u64,
+        vault_address: Option<H160>,
+        is_mainnet: bool,
+    ) -> Result<ExchangePayload> {
+        self.check_nonce(nonce)?;
+        let signature = match self {
+            Actions::UsdSend(usd_send) => sign_typed_data(usd_send, wallet)?,
+            Actions::ApproveAgent(approve_agent) => sign_typed_data(approve_agent, wallet)?,
+            Actions::Withdraw3(withdraw) => sign_typed_data(withdraw, wallet)?,
+            Actions::SpotSend(spot_send) => sign_typed_data(spot_send, wallet)?,
+            Actions::ApproveBuilderFee(approve_builder_fee) => {
+                sign_typed_data(approve_builder_fee, wallet)?
+            }
+            Actions::UsdClassTransfer(usd_class_transfer) => {
+                sign_typed_data(usd_class_transfer, wallet)?
+            }
+            Actions::UpdateLeverage(_)
+            | Actions::UpdateIsolatedMargin(_)
+            | Actions::Order(_)
+            | Actions::Cancel(_)
+            | Actions::CancelByCloid(_)
+            | Actions::BatchModify(_)
+            | Actions::SpotUser(_)
+            | Actions::VaultTransfer(_)
+            | Actions::SetReferrer(_) => {
+                let connection_id = self.hash(nonce, vault_address)?;
+                sign_l1_action(wallet, connection_id, is_mainnet)?
+            }
+        };
+        let action = serde_json::to_value(self).map_err(|e| Error::JsonParse(e.to_string()))?;
+
+        Ok(ExchangePayload {
+            action,
+            signature,
+            nonce,
+            vault_address,
+        })
+    }
+
+    /// Recovers the address that signed this action, the counterpart of [`Actions::sign`].
+    /// User-signed actions must carry `nonce` as their own nonce.
+    pub fn verify(
+        &self,
+        signature: &Signature,
+        nonce: u64,
+        vault_address: Option<H160>,
+        is_mainnet: bool,
+    ) -> Result<H160> {
+        self.check_nonce(nonce)?;
+        match self {
+            Actions::UsdSend(usd_send) => verify_typed_data(usd_send, signature),
+            Actions::ApproveAgent(approve_agent) => verify_typed_data(approve_agent, signature),
+            Actions::Withdraw3(withdraw) => verify_typed_data(withdraw, signature),
+            Actions::SpotSend(spot_send) => verify_typed_data(spot_send, signature),
+            Actions::ApproveBuilderFee(approve_builder_fee) => {
+                verify_typed_data(approve_builder_fee, signature)
+            }
+            Actions::UsdClassTransfer(usd_class_transfer) => {
+                verify_typed_data(usd_class_transfer, signature)
+            }
+            Actions::UpdateLeverage(_)
+            | Actions::UpdateIsolatedMargin(_)
+            | Actions::Order(_)
+            | Actions::Cancel(_)
+            | Actions::CancelByCloid(_)
+            | Actions::BatchModify(_)
+            | Actions::SpotUser(_)
+            | Actions::VaultTransfer(_)
+            | Actions::SetReferrer(_) => {
+                verify_l1_action(self, nonce, vault_address, signature, is_mainnet)
+            }
+        }
+    }
+}
+
+impl ExchangePayload {
+    /// Recovers the address that signed this payload, e.g. to audit logged requests.
+    pub fn verify(&self, is_mainnet: bool) -> Result<H160> {
+        let action: Actions = serde_json::from_value(self.action.clone())
+            .map_err(|e| Error::JsonParse(e.to_string()))?;
+        action.verify(&self.signature, self.nonce, self.vault_address, is_mainnet)
+    }
 }
 
 impl ExchangeClient {
@@ -92,52 +215,126 @@ impl ExchangeClient {
         meta: Option<Meta>,
         vault_address: Option<H160>,
     ) -> Result<ExchangeClient> {
-        let client = client.unwrap_or_default();
-        let base_url = base_url.unwrap_or(BaseUrl::Mainnet);
-
-        let info = InfoClient::new(None, Some(base_url)).await?;
-        let meta = if let Some(meta) = meta {
-            meta
-        } else {
-            info.meta().await?
-        };
+        let network = base_url.unwrap_or(BaseUrl::Mainnet).into();
+        Self::from_network_config(client, wallet, network, meta, vault_address).await
+    }
 
-        let mut coin_to_asset = HashMap::new();
-        for (asset_ind, asset) in meta.universe.iter().enumerate() {
-            coin_to_asset.insert(asset.name.clone(), asset_ind as u32);
-        }
+    /// Creates a client for an arbitrary deployment, e.g. a private network or a local mock.
+    pub async fn from_network_config(
+        client: Option<Client>,
+        wallet: LocalWallet,
+        network: NetworkConfig,
+        meta: Option<Meta>,
+        vault_address: Option<H160>,
+    ) -> Result<ExchangeClient> {
+        let metadata = MetadataCache::new(network.clone(), meta).await?;
+        Ok(Self::from_metadata_cache(
+            client,
+            wallet,
+            network,
+            Arc::new(metadata),
+            vault_address,
+        ))
+    }
 
-        coin_to_asset = info
-            .spot_meta()
-            .await?
-            .add_pair_and_name_to_index_map(coin_to_asset);
+    /// Creates a client sharing an existing metadata cache, e.g. with other clients or with
+    /// a cache kept current from the websocket.
+    pub fn from_metadata_cache(
+        client: Option<Client>,
+        wallet: LocalWallet,
+        network: NetworkConfig,
+        metadata: Arc<MetadataCache>,
+        vault_address: Option<H160>,
+    ) -> ExchangeClient {
+        let client = client.unwrap_or_default();
 
-        Ok(ExchangeClient {
+        ExchangeClient {
             wallet,
-            meta,
+            metadata,
             vault_address,
+            risk_manager: None,
+            paper_exchange: None,
             http_client: HttpClient {
                 client,
-                base_url: base_url.get_url(),
+                base_url: network.api_url.clone(),
             },
-            coin_to_asset,
-        })
+            network,
+        }
+    }
+
+    /// Metadata snapshot holding the perp metadata, formerly the `meta` field, as `.meta`.
+    #[deprecated(note = "use `metadata.assets().meta`, which stays current across listings")]
+    pub fn meta(&self) -> Arc<AssetMetadata> {
+        self.metadata.assets()
+    }
+
+    /// Metadata snapshot holding the coin to asset index mapping, formerly the `coin_to_asset`
+    /// field, as `.coin_to_asset`.
+    #[deprecated(note = "use `metadata.assets().asset_index`, which stays current across listings")]
+    pub fn coin_to_asset(&self) -> Arc<AssetMetadata> {
+        self.metadata.assets()
+    }
+
+    pub fn with_risk_manager(mut self, risk_manager: Arc<RiskManager>) -> ExchangeClient {
+        self.risk_manager = Some(risk_manager);
+        self
     }
 
-    async fn post(
+    /// Routes all actions to `paper_exchange` instead of the exchange. Actions are still signed.
+    pub fn with_paper_exchange(mut self, paper_exchange: Arc<PaperExchange>) -> ExchangeClient {
+        self.paper_exchange = Some(paper_exchange);
+        self
+    }
+
+    // Makes sure the mids used by the price band check are fresh before the action is built
+    async fn refresh_risk_mids(&self, coins: &[&str]) {
+        if !self
+            .risk_manager
+            .as_ref()
+            .is_some_and(|risk_manager| risk_manager.needs_mids())
+        {
+            return;
+        }
+        for coin in coins.iter().copied() {
+            // A missing mid is reported by the check itself
+            let _ = self.metadata.mid(coin).await;
+        }
+    }
+
+    fn check_risk(&self, orders: &[&ClientOrderRequest], is_modify: bool) -> Result<()> {
+        let Some(risk_manager) = &self.risk_manager else {
+            return Ok(());
+        };
+
+        let mid = |coin: &str| self.metadata.cached_mid(coin);
+        if is_modify {
+            risk_manager.check_modifies(orders, mid)
+        } else {
+            risk_manager.check_orders(orders, mid)
+        }
+    }
+
+    /// Signs an action built by one of the `*_action` methods (or deserialized from elsewhere)
+    /// with `wallet`, defaulting to the client's wallet.
+    pub fn sign_action(
         &self,
-        action: serde_json::Value,
-        signature: Signature,
+        action: &Actions,
         nonce: u64,
-    ) -> Result<ExchangeResponseStatus> {
-        let exchange_payload = ExchangePayload {
-            action,
-            signature,
-            nonce,
-            vault_address: self.vault_address,
-        };
-        let res = serde_json::to_string(&exchange_payload)
-            .map_err(|e| Error::JsonParse(e.to_string()))?;
+        wallet: Option<&LocalWallet>,
+    ) -> Result<ExchangePayload> {
+        let wallet = wallet.unwrap_or(&self.wallet);
+        action.sign(wallet, nonce, self.vault_address, self.network.is_mainnet)
+    }
+
+    /// Sends a previously signed payload to the exchange.
+    pub async fn submit(&self, payload: &ExchangePayload) -> Result<ExchangeResponseStatus> {
+        if let Some(paper_exchange) = &self.paper_exchange {
+            let action: Actions = serde_json::from_value(payload.action.clone())
+                .map_err(|e| Error::JsonParse(e.to_string()))?;
+            return paper_exchange.handle(&action, &self.metadata.assets());
+        }
+
+        let res = serde_json::to_string(payload).map_err(|e| Error::JsonParse(e.to_string()))?;
         debug!("Sending request {res:?}");
 
         let output = &self
@@ -148,32 +345,39 @@ impl ExchangeClient {
         serde_json::from_str(output).map_err(|e| Error::JsonParse(e.to_string()))
     }
 
+    async fn sign_and_submit(
+        &self,
+        action: Actions,
+        nonce: u64,
+        wallet: Option<&LocalWallet>,
+    ) -> Result<ExchangeResponseStatus> {
+        let payload = self.sign_action(&action, nonce, wallet)?;
+        self.submit(&payload).await
+    }
+
+    fn hyperliquid_chain(&self) -> String {
+        self.network.hyperliquid_chain.clone()
+    }
+
     pub async fn usdc_transfer(
         &self,
         amount: &str,
         destination: &str,
         wallet: Option<&LocalWallet>,
     ) -> Result<ExchangeResponseStatus> {
-        let wallet = wallet.unwrap_or(&self.wallet);
-        let hyperliquid_chain = if self.http_client.is_mainnet() {
-            "Mainnet".to_string()
-        } else {
-            "Testnet".to_string()
-        };
-
         let timestamp = next_nonce();
-        let usd_send = UsdSend {
-            signature_chain_id: 421614.into(),
-            hyperliquid_chain,
+        let action = self.usdc_transfer_action(amount, destination, timestamp);
+        self.sign_and_submit(action, timestamp, wallet).await
+    }
+
+    pub fn usdc_transfer_action(&self, amount: &str, destination: &str, time: u64) -> Actions {
+        Actions::UsdSend(UsdSend {
+            signature_chain_id: self.network.signature_chain_id,
+            hyperliquid_chain: self.hyperliquid_chain(),
             destination: destination.to_string(),
             amount: amount.to_string(),
-            time: timestamp,
-        };
-        let signature = sign_typed_data(&usd_send, wallet)?;
-        let action = serde_json::to_value(Actions::UsdSend(usd_send))
-            .map_err(|e| Error::JsonParse(e.to_string()))?;
-
-        self.post(action, signature, timestamp).await
+            time,
+        })
     }
 
     pub async fn usd_class_transfer(
@@ -182,30 +386,20 @@ impl ExchangeClient {
         to_perp: bool,
         wallet: Option<&LocalWallet>,
     ) -> Result<ExchangeResponseStatus> {
-        // payload expects usdc without decimals
-        let wallet = wallet.unwrap_or(&self.wallet);
-
         let timestamp = next_nonce();
+        let action = self.usd_class_transfer_action(usdc, to_perp, timestamp);
+        self.sign_and_submit(action, timestamp, wallet).await
+    }
 
-        let hyperliquid_chain = if self.http_client.is_mainnet() {
-            "Mainnet".to_string()
-        } else {
-            "Testnet".to_string()
-        };
-
-        let usd_class_transfer = UsdClassTransfer {
-            signature_chain_id: 421614.into(), // Arbitrum chain ID
-            hyperliquid_chain,
+    pub fn usd_class_transfer_action(&self, usdc: f64, to_perp: bool, nonce: u64) -> Actions {
+        // payload expects usdc without decimals
+        Actions::UsdClassTransfer(UsdClassTransfer {
+            signature_chain_id: self.network.signature_chain_id,
+            hyperliquid_chain: self.hyperliquid_chain(),
             amount: usdc.to_string(),
             to_perp,
-            nonce: timestamp,
-        };
-
-        let signature = sign_typed_data(&usd_class_transfer, wallet)?;
-        let action = serde_json::to_value(Actions::UsdClassTransfer(usd_class_transfer))
-            .map_err(|e| Error::JsonParse(e.to_string()))?;
-
-        self.post(action, signature, timestamp).await
+            nonce,
+        })
     }
 
     pub async fn vault_transfer(
@@ -215,25 +409,27 @@ impl ExchangeClient {
         vault_address: Option<H160>,
         wallet: Option<&LocalWallet>,
     ) -> Result<ExchangeResponseStatus> {
+        let timestamp = next_nonce();
+        let action = self.vault_transfer_action(is_deposit, usd, vault_address)?;
+        self.sign_and_submit(action, timestamp, wallet).await
+    }
+
+    pub fn vault_transfer_action(
+        &self,
+        is_deposit: bool,
+        usd: u64,
+        vault_address: Option<H160>,
+    ) -> Result<Actions> {
         let vault_address = self
             .vault_address
             .or(vault_address)
             .ok_or(Error::VaultAddressNotFound)?;
-        let wallet = wallet.unwrap_or(&self.wallet);
 
-        let timestamp = next_nonce();
-
-        let action = Actions::VaultTransfer(VaultTransfer {
+        Ok(Actions::VaultTransfer(VaultTransfer {
             vault_address,
             is_deposit,
             usd,
-        });
-        let connection_id = action.hash(timestamp, self.vault_address)?;
-        let action = serde_json::to_value(&action).map_err(|e| Error::JsonParse(e.to_string()))?;
-        let is_mainnet = self.http_client.is_mainnet();
-        let signature = sign_l1_action(wallet, connection_id, is_mainnet)?;
-
-        self.post(action, signature, timestamp).await
+        }))
     }
 
     pub async fn market_open(
@@ -292,25 +488,30 @@ impl ExchangeClient {
         let slippage = params.slippage.unwrap_or(0.05); // Default 5% slippage
         let wallet = params.wallet.unwrap_or(&self.wallet);
 
-        let base_url = match self.http_client.base_url.as_str() {
-            "https://api.hyperliquid.xyz" => BaseUrl::Mainnet,
-            "https://api.hyperliquid-testnet.xyz" => BaseUrl::Testnet,
-            _ => return Err(Error::GenericRequest("Invalid base URL".to_string())),
-        };
-        let info_client = InfoClient::new(None, Some(base_url)).await?;
-        let user_state = info_client.user_state(wallet.address()).await?;
-
-        let position = user_state
-            .asset_positions
-            .iter()
-            .find(|p| p.position.coin == params.asset)
-            .ok_or(Error::AssetNotFound)?;
+        let szi = if let Some(paper_exchange) = &self.paper_exchange {
+            paper_exchange
+                .position(params.asset)
+                .ok_or(Error::AssetNotFound)?
+                .szi
+        } else {
+            let user_state = self
+                .metadata
+                .info_client()
+                .user_state(wallet.address())
+                .await?;
+
+            let position = user_state
+                .asset_positions
+                .iter()
+                .find(|p| p.position.coin == params.asset)
+                .ok_or(Error::AssetNotFound)?;
 
-        let szi = position
-            .position
-            .szi
-            .parse::<f64>()
-            .map_err(|_| Error::FloatStringParse)?;
+            position
+                .position
+                .szi
+                .parse::<f64>()
+                .map_err(|_| Error::FloatStringParse)?
+        };
 
         let (px, sz_decimals) = self
             .calculate_slippage_price(params.asset, szi < 0.0, slippage, params.px)
@@ -340,37 +541,20 @@ impl ExchangeClient {
         slippage: f64,
         px: Option<f64>,
     ) -> Result<(f64, u32)> {
-        let base_url = match self.http_client.base_url.as_str() {
-            "https://api.hyperliquid.xyz" => BaseUrl::Mainnet,
-            "https://api.hyperliquid-testnet.xyz" => BaseUrl::Testnet,
-            _ => return Err(Error::GenericRequest("Invalid base URL".to_string())),
-        };
-        let info_client = InfoClient::new(None, Some(base_url)).await?;
-        let meta = info_client.meta().await?;
-
-        let asset_meta = meta
-            .universe
-            .iter()
-            .find(|a| a.name == asset)
-            .ok_or(Error::AssetNotFound)?;
-
-        let sz_decimals = asset_meta.sz_decimals;
-        let max_decimals: u32 = if self.coin_to_asset[asset] < 10000 {
-            6
-        } else {
-            8
-        };
+        let assets = self.metadata.ensure_coins([asset]).await?;
+        let asset_index = assets.asset_index(asset).ok_or(Error::AssetNotFound)?;
+        let sz_decimals = assets.sz_decimals(asset).ok_or(Error::AssetNotFound)?;
+        let max_decimals: u32 = if asset_index < 10000 { 6 } else { 8 };
         let price_decimals = max_decimals.saturating_sub(sz_decimals);
 
-        let px = if let Some(px) = px {
+        let paper_mid = self
+            .paper_exchange
+            .as_ref()
+            .and_then(|paper_exchange| paper_exchange.mid(asset));
+        let px = if let Some(px) = px.or(paper_mid) {
             px
         } else {
-            let all_mids = info_client.all_mids().await?;
-            all_mids
-                .get(asset)
-                .ok_or(Error::AssetNotFound)?
-                .parse::<f64>()
-                .map_err(|_| Error::FloatStringParse)?
+            self.metadata.mid(asset).await?
         };
 
         debug!("px before slippage: {px:?}");
@@ -411,56 +595,67 @@ impl ExchangeClient {
         orders: Vec<ClientOrderRequest>,
         wallet: Option<&LocalWallet>,
     ) -> Result<ExchangeResponseStatus> {
-        let wallet = wallet.unwrap_or(&self.wallet);
+        self.metadata
+            .ensure_coins(orders.iter().map(|order| order.asset.as_str()))
+            .await?;
+        self.refresh_risk_mids(
+            &orders
+                .iter()
+                .map(|order| order.asset.as_str())
+                .collect::<Vec<_>>(),
+        )
+        .await;
         let timestamp = next_nonce();
-
-        let mut transformed_orders = Vec::new();
-
-        for order in orders {
-            transformed_orders.push(order.convert(&self.coin_to_asset)?);
-        }
-
-        let action = Actions::Order(BulkOrder {
-            orders: transformed_orders,
-            grouping: "na".to_string(),
-            builder: None,
-        });
-        let connection_id = action.hash(timestamp, self.vault_address)?;
-        let action = serde_json::to_value(&action).map_err(|e| Error::JsonParse(e.to_string()))?;
-
-        let is_mainnet = self.http_client.is_mainnet();
-        let signature = sign_l1_action(wallet, connection_id, is_mainnet)?;
-        self.post(action, signature, timestamp).await
+        let action = self.bulk_order_action(orders, None)?;
+        self.sign_and_submit(action, timestamp, wallet).await
     }
 
     pub async fn bulk_order_with_builder(
         &self,
         orders: Vec<ClientOrderRequest>,
         wallet: Option<&LocalWallet>,
-        mut builder: BuilderInfo,
+        builder: BuilderInfo,
     ) -> Result<ExchangeResponseStatus> {
-        let wallet = wallet.unwrap_or(&self.wallet);
+        self.metadata
+            .ensure_coins(orders.iter().map(|order| order.asset.as_str()))
+            .await?;
+        self.refresh_risk_mids(
+            &orders
+                .iter()
+                .map(|order| order.asset.as_str())
+                .collect::<Vec<_>>(),
+        )
+        .await;
         let timestamp = next_nonce();
+        let action = self.bulk_order_action(orders, Some(builder))?;
+        self.sign_and_submit(action, timestamp, wallet).await
+    }
 
-        builder.builder = builder.builder.to_lowercase();
+    /// Builds an order action after running the risk checks, which use cached mids for the
+    /// price band.
+    pub fn bulk_order_action(
+        &self,
+        orders: Vec<ClientOrderRequest>,
+        builder: Option<BuilderInfo>,
+    ) -> Result<Actions> {
+        self.check_risk(&orders.iter().collect::<Vec<_>>(), false)?;
+        let builder = builder.map(|mut builder| {
+            builder.builder = builder.builder.to_lowercase();
+            builder
+        });
 
+        let assets = self.metadata.assets();
         let mut transformed_orders = Vec::new();
 
         for order in orders {
-            transformed_orders.push(order.convert(&self.coin_to_asset)?);
+            transformed_orders.push(order.convert(&assets.coin_to_asset)?);
         }
 
-        let action = Actions::Order(BulkOrder {
+        Ok(Actions::Order(BulkOrder {
             orders: transformed_orders,
             grouping: "na".to_string(),
-            builder: Some(builder),
-        });
-        let connection_id = action.hash(timestamp, self.vault_address)?;
-        let action = serde_json::to_value(&action).map_err(|e| Error::JsonParse(e.to_string()))?;
-
-        let is_mainnet = self.http_client.is_mainnet();
-        let signature = sign_l1_action(wallet, connection_id, is_mainnet)?;
-        self.post(action, signature, timestamp).await
+            builder,
+        }))
     }
 
     pub async fn cancel(
@@ -476,14 +671,20 @@ impl ExchangeClient {
         cancels: Vec<ClientCancelRequest>,
         wallet: Option<&LocalWallet>,
     ) -> Result<ExchangeResponseStatus> {
-        let wallet = wallet.unwrap_or(&self.wallet);
+        self.metadata
+            .ensure_coins(cancels.iter().map(|cancel| cancel.asset.as_str()))
+            .await?;
         let timestamp = next_nonce();
+        let action = self.bulk_cancel_action(cancels)?;
+        self.sign_and_submit(action, timestamp, wallet).await
+    }
 
+    pub fn bulk_cancel_action(&self, cancels: Vec<ClientCancelRequest>) -> Result<Actions> {
+        let assets = self.metadata.assets();
         let mut transformed_cancels = Vec::new();
         for cancel in cancels.into_iter() {
-            let &asset = self
-                .coin_to_asset
-                .get(&cancel.asset)
+            let asset = assets
+                .asset_index(&cancel.asset)
                 .ok_or(Error::AssetNotFound)?;
             transformed_cancels.push(CancelRequest {
                 asset,
@@ -491,16 +692,9 @@ impl ExchangeClient {
             });
         }
 
-        let action = Actions::Cancel(BulkCancel {
+        Ok(Actions::Cancel(BulkCancel {
             cancels: transformed_cancels,
-        });
-        let connection_id = action.hash(timestamp, self.vault_address)?;
-
-        let action = serde_json::to_value(&action).map_err(|e| Error::JsonParse(e.to_string()))?;
-        let is_mainnet = self.http_client.is_mainnet();
-        let signature = sign_l1_action(wallet, connection_id, is_mainnet)?;
-
-        self.post(action, signature, timestamp).await
+        }))
     }
 
     pub async fn modify(
@@ -516,27 +710,42 @@ impl ExchangeClient {
         modifies: Vec<ClientModifyRequest>,
         wallet: Option<&LocalWallet>,
     ) -> Result<ExchangeResponseStatus> {
-        let wallet = wallet.unwrap_or(&self.wallet);
+        self.metadata
+            .ensure_coins(modifies.iter().map(|modify| modify.order.asset.as_str()))
+            .await?;
+        self.refresh_risk_mids(
+            &modifies
+                .iter()
+                .map(|modify| modify.order.asset.as_str())
+                .collect::<Vec<_>>(),
+        )
+        .await;
         let timestamp = next_nonce();
+        let action = self.bulk_modify_action(modifies)?;
+        self.sign_and_submit(action, timestamp, wallet).await
+    }
 
+    /// Like [`ExchangeClient::bulk_order_action`], for modifies.
+    pub fn bulk_modify_action(&self, modifies: Vec<ClientModifyRequest>) -> Result<Actions> {
+        self.check_risk(
+            &modifies
+                .iter()
+                .map(|modify| &modify.order)
+                .collect::<Vec<_>>(),
+            true,
+        )?;
+        let assets = self.metadata.assets();
         let mut transformed_modifies = Vec::new();
         for modify in modifies.into_iter() {
             transformed_modifies.push(ModifyRequest {
                 oid: modify.oid,
-                order: modify.order.convert(&self.coin_to_asset)?,
+                order: modify.order.convert(&assets.coin_to_asset)?,
             });
         }
 
-        let action = Actions::BatchModify(BulkModify {
+        Ok(Actions::BatchModify(BulkModify {
             modifies: transformed_modifies,
-        });
-        let connection_id = action.hash(timestamp, self.vault_address)?;
-
-        let action = serde_json::to_value(&action).map_err(|e| Error::JsonParse(e.to_string()))?;
-        let is_mainnet = self.http_client.is_mainnet();
-        let signature = sign_l1_action(wallet, connection_id, is_mainnet)?;
-
-        self.post(action, signature, timestamp).await
+        }))
     }
 
     pub async fn cancel_by_cloid(
@@ -552,31 +761,33 @@ impl ExchangeClient {
         cancels: Vec<ClientCancelRequestCloid>,
         wallet: Option<&LocalWallet>,
     ) -> Result<ExchangeResponseStatus> {
-        let wallet = wallet.unwrap_or(&self.wallet);
+        self.metadata
+            .ensure_coins(cancels.iter().map(|cancel| cancel.asset.as_str()))
+            .await?;
         let timestamp = next_nonce();
+        let action = self.bulk_cancel_by_cloid_action(cancels)?;
+        self.sign_and_submit(action, timestamp, wallet).await
+    }
 
+    pub fn bulk_cancel_by_cloid_action(
+        &self,
+        cancels: Vec<ClientCancelRequestCloid>,
+    ) -> Result<Actions> {
+        let assets = self.metadata.assets();
         let mut transformed_cancels: Vec<CancelRequestCloid> = Vec::new();
         for cancel in cancels.into_iter() {
-            let &asset = self
-                .coin_to_asset
-                .get(&cancel.asset)
+            let asset = assets
+                .asset_index(&cancel.asset)
                 .ok_or(Error::AssetNotFound)?;
             transformed_cancels.push(CancelRequestCloid {
                 asset,
-                cloid: string_to_hex_string(&cancel.cloid),
+                cloid: cancel.cloid,
             });
         }
 
-        let action = Actions::CancelByCloid(BulkCancelCloid {
+        Ok(Actions::CancelByCloid(BulkCancelCloid {
             cancels: transformed_cancels,
-        });
-
-        let connection_id = action.hash(timestamp, self.vault_address)?;
-        let action = serde_json::to_value(&action).map_err(|e| Error::JsonParse(e.to_string()))?;
-        let is_mainnet = self.http_client.is_mainnet();
-        let signature = sign_l1_action(wallet, connection_id, is_mainnet)?;
-
-        self.post(action, signature, timestamp).await
+        }))
     }
 
     pub async fn update_leverage(
@@ -586,22 +797,28 @@ impl ExchangeClient {
         is_cross: bool,
         wallet: Option<&LocalWallet>,
     ) -> Result<ExchangeResponseStatus> {
-        let wallet = wallet.unwrap_or(&self.wallet);
-
+        self.metadata.ensure_coins([coin]).await?;
         let timestamp = next_nonce();
+        let action = self.update_leverage_action(leverage, coin, is_cross)?;
+        self.sign_and_submit(action, timestamp, wallet).await
+    }
 
-        let &asset_index = self.coin_to_asset.get(coin).ok_or(Error::AssetNotFound)?;
-        let action = Actions::UpdateLeverage(UpdateLeverage {
+    pub fn update_leverage_action(
+        &self,
+        leverage: u32,
+        coin: &str,
+        is_cross: bool,
+    ) -> Result<Actions> {
+        let asset_index = self
+            .metadata
+            .assets()
+            .asset_index(coin)
+            .ok_or(Error::AssetNotFound)?;
+        Ok(Actions::UpdateLeverage(UpdateLeverage {
             asset: asset_index,
             is_cross,
             leverage,
-        });
-        let connection_id = action.hash(timestamp, self.vault_address)?;
-        let action = serde_json::to_value(&action).map_err(|e| Error::JsonParse(e.to_string()))?;
-        let is_mainnet = self.http_client.is_mainnet();
-        let signature = sign_l1_action(wallet, connection_id, is_mainnet)?;
-
-        self.post(action, signature, timestamp).await
+        }))
     }
 
     pub async fn update_isolated_margin(
@@ -610,30 +827,31 @@ impl ExchangeClient {
         coin: &str,
         wallet: Option<&LocalWallet>,
     ) -> Result<ExchangeResponseStatus> {
-        let wallet = wallet.unwrap_or(&self.wallet);
+        self.metadata.ensure_coins([coin]).await?;
+        let timestamp = next_nonce();
+        let action = self.update_isolated_margin_action(amount, coin)?;
+        self.sign_and_submit(action, timestamp, wallet).await
+    }
 
+    pub fn update_isolated_margin_action(&self, amount: f64, coin: &str) -> Result<Actions> {
         let amount = (amount * 1_000_000.0).round() as i64;
-        let timestamp = next_nonce();
 
-        let &asset_index = self.coin_to_asset.get(coin).ok_or(Error::AssetNotFound)?;
-        let action = Actions::UpdateIsolatedMargin(UpdateIsolatedMargin {
+        let asset_index = self
+            .metadata
+            .assets()
+            .asset_index(coin)
+            .ok_or(Error::AssetNotFound)?;
+        Ok(Actions::UpdateIsolatedMargin(UpdateIsolatedMargin {
             asset: asset_index,
             is_buy: true,
             ntli: amount,
-        });
-        let connection_id = action.hash(timestamp, self.vault_address)?;
-        let action = serde_json::to_value(&action).map_err(|e| Error::JsonParse(e.to_string()))?;
-        let is_mainnet = self.http_client.is_mainnet();
-        let signature = sign_l1_action(wallet, connection_id, is_mainnet)?;
-
-        self.post(action, signature, timestamp).await
+        }))
     }
 
     pub async fn approve_agent(
         &self,
         wallet: Option<&LocalWallet>,
     ) -> Result<(String, ExchangeResponseStatus)> {
-        let wallet = wallet.unwrap_or(&self.wallet);
         let key = H256::from(generate_random_key()?).encode_hex()[2..].to_string();
 
         let address = key
@@ -641,24 +859,37 @@ impl ExchangeClient {
             .map_err(|e| Error::PrivateKeyParse(e.to_string()))?
             .address();
 
-        let hyperliquid_chain = if self.http_client.is_mainnet() {
-            "Mainnet".to_string()
-        } else {
-            "Testnet".to_string()
-        };
+        Ok((key, self.approve_agent_with(address, None, wallet).await?))
+    }
 
+    /// Approves an agent whose key is held elsewhere, e.g. in an HSM.
+    ///
+    /// Named agents are kept alongside each other; approving a new agent under an existing name
+    /// replaces the previous one, as does approving another unnamed agent.
+    pub async fn approve_agent_with(
+        &self,
+        agent_address: H160,
+        agent_name: Option<String>,
+        wallet: Option<&LocalWallet>,
+    ) -> Result<ExchangeResponseStatus> {
         let nonce = next_nonce();
-        let approve_agent = ApproveAgent {
-            signature_chain_id: 421614.into(),
-            hyperliquid_chain,
-            agent_address: address,
-            agent_name: None,
+        let action = self.approve_agent_action(agent_address, agent_name, nonce);
+        self.sign_and_submit(action, nonce, wallet).await
+    }
+
+    pub fn approve_agent_action(
+        &self,
+        agent_address: H160,
+        agent_name: Option<String>,
+        nonce: u64,
+    ) -> Actions {
+        Actions::ApproveAgent(ApproveAgent {
+            signature_chain_id: self.network.signature_chain_id,
+            hyperliquid_chain: self.hyperliquid_chain(),
+            agent_address,
+            agent_name,
             nonce,
-        };
-        let signature = sign_typed_data(&approve_agent, wallet)?;
-        let action = serde_json::to_value(Actions::ApproveAgent(approve_agent))
-            .map_err(|e| Error::JsonParse(e.to_string()))?;
-        Ok((key, self.post(action, signature, nonce).await?))
+        })
     }
 
     pub async fn withdraw_from_bridge(
@@ -667,26 +898,24 @@ impl ExchangeClient {
         destination: &str,
         wallet: Option<&LocalWallet>,
     ) -> Result<ExchangeResponseStatus> {
-        let wallet = wallet.unwrap_or(&self.wallet);
-        let hyperliquid_chain = if self.http_client.is_mainnet() {
-            "Mainnet".to_string()
-        } else {
-            "Testnet".to_string()
-        };
-
         let timestamp = next_nonce();
-        let withdraw = Withdraw3 {
-            signature_chain_id: 421614.into(),
-            hyperliquid_chain,
+        let action = self.withdraw_from_bridge_action(amount, destination, timestamp);
+        self.sign_and_submit(action, timestamp, wallet).await
+    }
+
+    pub fn withdraw_from_bridge_action(
+        &self,
+        amount: &str,
+        destination: &str,
+        time: u64,
+    ) -> Actions {
+        Actions::Withdraw3(Withdraw3 {
+            signature_chain_id: self.network.signature_chain_id,
+            hyperliquid_chain: self.hyperliquid_chain(),
             destination: destination.to_string(),
             amount: amount.to_string(),
-            time: timestamp,
-        };
-        let signature = sign_typed_data(&withdraw, wallet)?;
-        let action = serde_json::to_value(Actions::Withdraw3(withdraw))
-            .map_err(|e| Error::JsonParse(e.to_string()))?;
-
-        self.post(action, signature, timestamp).await
+            time,
+        })
     }
 
     pub async fn spot_transfer(
@@ -696,27 +925,26 @@ impl ExchangeClient {
         token: &str,
         wallet: Option<&LocalWallet>,
     ) -> Result<ExchangeResponseStatus> {
-        let wallet = wallet.unwrap_or(&self.wallet);
-        let hyperliquid_chain = if self.http_client.is_mainnet() {
-            "Mainnet".to_string()
-        } else {
-            "Testnet".to_string()
-        };
-
         let timestamp = next_nonce();
-        let spot_send = SpotSend {
-            signature_chain_id: 421614.into(),
-            hyperliquid_chain,
+        let action = self.spot_transfer_action(amount, destination, token, timestamp);
+        self.sign_and_submit(action, timestamp, wallet).await
+    }
+
+    pub fn spot_transfer_action(
+        &self,
+        amount: &str,
+        destination: &str,
+        token: &str,
+        time: u64,
+    ) -> Actions {
+        Actions::SpotSend(SpotSend {
+            signature_chain_id: self.network.signature_chain_id,
+            hyperliquid_chain: self.hyperliquid_chain(),
             destination: destination.to_string(),
             amount: amount.to_string(),
-            time: timestamp,
+            time,
             token: token.to_string(),
-        };
-        let signature = sign_typed_data(&spot_send, wallet)?;
-        let action = serde_json::to_value(Actions::SpotSend(spot_send))
-            .map_err(|e| Error::JsonParse(e.to_string()))?;
-
-        self.post(action, signature, timestamp).await
+        })
     }
 
     pub async fn set_referrer(
@@ -724,17 +952,13 @@ impl ExchangeClient {
         code: String,
         wallet: Option<&LocalWallet>,
     ) -> Result<ExchangeResponseStatus> {
-        let wallet = wallet.unwrap_or(&self.wallet);
         let timestamp = next_nonce();
+        let action = self.set_referrer_action(code);
+        self.sign_and_submit(action, timestamp, wallet).await
+    }
 
-        let action = Actions::SetReferrer(SetReferrer { code });
-
-        let connection_id = action.hash(timestamp, self.vault_address)?;
-        let action = serde_json::to_value(&action).map_err(|e| Error::JsonParse(e.to_string()))?;
-
-        let is_mainnet = self.http_client.is_mainnet();
-        let signature = sign_l1_action(wallet, connection_id, is_mainnet)?;
-        self.post(action, signature, timestamp).await
+    pub fn set_referrer_action(&self, code: String) -> Actions {
+        Actions::SetReferrer(SetReferrer { code })
     }
 
     pub async fn approve_builder_fee(
@@ -743,30 +967,27 @@ impl ExchangeClient {
         max_fee_rate: String,
         wallet: Option<&LocalWallet>,
     ) -> Result<ExchangeResponseStatus> {
-        let wallet = wallet.unwrap_or(&self.wallet);
         let timestamp = next_nonce();
+        let action = self.approve_builder_fee_action(builder, max_fee_rate, timestamp);
+        self.sign_and_submit(action, timestamp, wallet).await
+    }
 
+    pub fn approve_builder_fee_action(
+        &self,
+        builder: String,
+        max_fee_rate: String,
+        nonce: u64,
+    ) -> Actions {
         // Ensure builder address is lowercase
         let builder = builder.to_lowercase();
 
-        let hyperliquid_chain = if self.http_client.is_mainnet() {
-            "Mainnet".to_string()
-        } else {
-            "Testnet".to_string()
-        };
-
-        let approve_builder_fee = ApproveBuilderFee {
-            signature_chain_id: 421614.into(),
-            hyperliquid_chain,
+        Actions::ApproveBuilderFee(ApproveBuilderFee {
+            signature_chain_id: self.network.signature_chain_id,
+            hyperliquid_chain: self.hyperliquid_chain(),
             builder,
             max_fee_rate,
-            nonce: timestamp,
-        };
-        let signature = sign_typed_data(&approve_builder_fee, wallet)?;
-        let action = serde_json::to_value(Actions::ApproveBuilderFee(approve_builder_fee))
-            .map_err(|e| Error::JsonParse(e.to_string()))?;
-
-        self.post(action, signature, timestamp).await
+            nonce,
+        })
     }
 }
 
@@ -783,11 +1004,46 @@ fn round_to_significant_and_decimal(value: f64, sig_figs: u32, max_decimals: u32
     round_to_decimals(rounded.copysign(value), max_decimals)
 }
 
+#[cfg(test)]
+impl ExchangeClient {
+    /// Offline client for the test key, trading an ETH-only universe on a paper exchange.
+    pub(crate) async fn paper_test_client() -> Result<(ExchangeClient, Arc<PaperExchange>)> {
+        let wallet: LocalWallet =
+            "e908f86dbb4d55ac876378565aafeabc187f6690f046459397b17d9b9a19688e"
+                .parse()
+                .map_err(|e: ethers::signers::WalletError| Error::Wallet(e.to_string()))?;
+        let meta: Meta = serde_json::from_str(
+            r#"{"universe": [{"name": "ETH", "szDecimals": 4, "maxLeverage": 50}]}"#,
+        )
+        .map_err(|e| Error::JsonParse(e.to_string()))?;
+        let spot_meta: crate::SpotMeta = serde_json::from_str(r#"{"universe": [], "tokens": []}"#)
+            .map_err(|e| Error::JsonParse(e.to_string()))?;
+        let metadata = MetadataCache::from_metadata(
+            BaseUrl::Localhost.into(),
+            AssetMetadata::new(meta, spot_meta),
+        )
+        .await?;
+        let paper_exchange = Arc::new(PaperExchange::new(
+            wallet.address(),
+            crate::FeeRates::default(),
+        ));
+        let client = ExchangeClient::from_metadata_cache(
+            None,
+            wallet,
+            BaseUrl::Localhost.into(),
+            Arc::new(metadata),
+            None,
+        )
+        .with_paper_exchange(paper_exchange.clone());
+        Ok((client, paper_exchange))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::exchange::order::{Limit, OrderRequest, Trigger};
-    use crate::Order;
+    use crate::{Cloid, Order};
 
     fn get_wallet() -> Result<LocalWallet> {
         let priv_key = "e908f86dbb4d55ac876378565aafeabc187f6690f046459397b17d9b9a19688e";
@@ -839,7 +1095,7 @@ mod tests {
                 order_type: Order::Limit(Limit {
                     tif: "Ioc".to_string(),
                 }),
-                cloid: Some(string_to_hex_string(cloid_string)),
+                cloid: Some(Cloid::from_label(cloid_string)),
             }],
             grouping: "na".to_string(),
             builder: None,
@@ -920,6 +1176,57 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_sign_action_payload() -> Result<()> {
+        let wallet = get_wallet()?;
+        let action = Actions::Cancel(BulkCancel {
+            cancels: vec![CancelRequest {
+                asset: 1,
+                oid: 82382,
+            }],
+        });
+
+        let payload = action.sign(&wallet, 1583838, None, false)?;
+        assert_eq!(payload.signature.to_string(), "6ffebadfd48067663390962539fbde76cfa36f53be65abe2ab72c9db6d0db44457720db9d7c4860f142a484f070c84eb4b9694c3a617c83f0d698a27e55fd5e01c");
+        assert_eq!(payload.nonce, 1583838);
+
+        let serialized =
+            serde_json::to_string(&payload).map_err(|e| Error::JsonParse(e.to_string()))?;
+        let deserialized: ExchangePayload =
+            serde_json::from_str(&serialized).map_err(|e| Error::JsonParse(e.to_string()))?;
+        assert_eq!(deserialized.signature, payload.signature);
+        assert_eq!(deserialized.action, payload.action);
+        assert_eq!(deserialized.action["type"], "cancel");
+
+        let usd_send = UsdSend {
+            signature_chain_id: 421614.into(),
+            hyperliquid_chain: "Testnet".to_string(),
+            destination: "0x0D1d9635D0640821d15e323ac8AdADfA9c111414".to_string(),
+            amount: "1".to_string(),
+            time: 1690393044548,
+        };
+        let expected_sig = sign_typed_data(&usd_send, &wallet)?;
+        let action = Actions::UsdSend(usd_send);
+        let payload = action.sign(&wallet, 1690393044548, None, false)?;
+        assert_eq!(payload.signature, expected_sig);
+
+        assert!(matches!(
+            action.sign(&wallet, 1690393044549, None, false),
+            Err(Error::NonceMismatch {
+                action: 1690393044548,
+                payload: 1690393044549
+            })
+        ));
+        let mut mismatched = payload.clone();
+        mismatched.nonce = 1690393044549;
+        assert!(matches!(
+            mismatched.verify(false),
+            Err(Error::NonceMismatch { .. })
+        ));
+
+        Ok(())
+    }
+
     #[test]
     fn test_usd_class_transfer_action_hashing() -> Result<()> {
         let wallet = get_wallet()?;
@@ -997,4 +1304,40 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_action_builders_run_risk_checks() -> Result<()> {
+        let risk_manager = Arc::new(RiskManager::default());
+        let client = ExchangeClient::paper_test_client()
+            .await?
+            .0
+            .with_risk_manager(risk_manager.clone());
+
+        let order = |reduce_only| ClientOrderRequest {
+            asset: "ETH".to_string(),
+            is_buy: false,
+            reduce_only,
+            limit_px: 1800.0,
+            sz: 1.0,
+            cloid: None,
+            order_type: ClientOrder::Limit(ClientLimit {
+                tif: "Ioc".to_string(),
+            }),
+        };
+        risk_manager.kill();
+        assert!(matches!(
+            client.bulk_order_action(vec![order(false)], None),
+            Err(Error::RiskRejected(crate::RiskViolation::KillSwitch))
+        ));
+        assert!(matches!(
+            client.bulk_modify_action(vec![ClientModifyRequest {
+                oid: 1,
+                order: order(false),
+            }]),
+            Err(Error::RiskRejected(crate::RiskViolation::KillSwitch))
+        ));
+        // Reduce-only orders can still close positions
+        assert!(client.bulk_order_action(vec![order(true)], None).is_ok());
+        Ok(())
+    }
 }
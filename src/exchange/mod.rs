@@ -1,14 +1,19 @@
 mod actions;
+mod agent_manager;
 mod builder;
 mod cancel;
+mod cloid;
 mod exchange_client;
 mod exchange_responses;
 mod modify;
 mod order;
+mod risk;
 
 pub use actions::*;
+pub use agent_manager::{AgentManager, ManagedAgent};
 pub use builder::*;
 pub use cancel::{ClientCancelRequest, ClientCancelRequestCloid};
+pub use cloid::{Cloid, CloidRegistry};
 pub use exchange_client::*;
 pub use exchange_responses::*;
 pub use modify::{ClientModifyRequest, ModifyRequest};
@@ -16,3 +21,4 @@ pub use order::{
     ClientLimit, ClientOrder, ClientOrderRequest, ClientTrigger, MarketCloseParams,
     MarketOrderParams, Order,
 };
+pub use risk::{RiskLimits, RiskManager, RiskViolation};
@@ -1,8 +1,4 @@
-use crate::{
-    errors::Error,
-    helpers::{float_to_string_for_hashing, string_to_hex_string},
-    prelude::*,
-};
+use crate::{errors::Error, helpers::float_to_string_for_hashing, prelude::*, Cloid};
 use ethers::signers::LocalWallet;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -43,7 +39,7 @@ pub struct OrderRequest {
     #[serde(rename = "t", alias = "orderType")]
     pub order_type: Order,
     #[serde(rename = "c", alias = "cloid", skip_serializing_if = "Option::is_none")]
-    pub cloid: Option<String>,
+    pub cloid: Option<Cloid>,
 }
 
 #[derive(Debug)]
@@ -65,7 +61,7 @@ pub struct MarketOrderParams<'a> {
     pub sz: f64,
     pub px: Option<f64>,
     pub slippage: Option<f64>,
-    pub cloid: Option<String>,
+    pub cloid: Option<Cloid>,
     pub wallet: Option<&'a LocalWallet>,
 }
 
@@ -75,7 +71,7 @@ pub struct MarketCloseParams<'a> {
     pub sz: Option<f64>,
     pub px: Option<f64>,
     pub slippage: Option<f64>,
-    pub cloid: Option<String>,
+    pub cloid: Option<Cloid>,
     pub wallet: Option<&'a LocalWallet>,
 }
 
@@ -92,7 +88,7 @@ pub struct ClientOrderRequest {
     pub reduce_only: bool,
     pub limit_px: f64,
     pub sz: f64,
-    pub cloid: Option<String>,
+    pub cloid: Option<Cloid>,
     pub order_type: ClientOrder,
 }
 
@@ -108,8 +104,6 @@ impl ClientOrderRequest {
         };
         let &asset = coin_to_asset.get(&self.asset).ok_or(Error::AssetNotFound)?;
 
-        let cloid = self.cloid.as_deref().map(string_to_hex_string);
-
         Ok(OrderRequest {
             asset,
             is_buy: self.is_buy,
@@ -117,7 +111,7 @@ impl ClientOrderRequest {
             limit_px: float_to_string_for_hashing(self.limit_px),
             sz: float_to_string_for_hashing(self.sz),
             order_type,
-            cloid,
+            cloid: self.cloid,
         })
     }
 }
@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        PoisonError, RwLock,
+    },
+};
+
+use thiserror::Error;
+
+use crate::{prelude::*, ClientOrder, ClientOrderRequest, Error, OrderTracker, PositionTracker};
+
+/// Limits enforced by a [`RiskManager`]. `None` disables a check.
+#[derive(Debug, Clone, Default)]
+pub struct RiskLimits {
+    /// Max `limit_px * sz` of a single order
+    pub max_order_notional: Option<f64>,
+    /// Max absolute position size per coin, including the orders being placed
+    pub max_position: HashMap<String, f64>,
+    /// Max number of open orders, including the orders being placed
+    pub max_open_orders: Option<usize>,
+    /// Max deviation of a resting limit order's price from the mid, in bps. IOC orders, such as
+    /// those sent by `market_open` and `market_close` at mid ± slippage, and trigger orders are
+    /// exempt, as their limit price is a worst-case bound rather than where they will fill.
+    pub max_price_deviation_bps: Option<u16>,
+}
+
+/// Why a [`RiskManager`] rejected an order.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum RiskViolation {
+    #[error("kill switch is active")]
+    KillSwitch,
+    #[error("{coin} order notional {notional} exceeds {limit}")]
+    OrderNotional {
+        coin: String,
+        notional: f64,
+        limit: f64,
+    },
+    #[error("{coin} position would be {position}, limit is {limit}")]
+    PositionLimit {
+        coin: String,
+        position: f64,
+        limit: f64,
+    },
+    #[error("{count} open orders would exceed {limit}")]
+    OpenOrders { count: usize, limit: usize },
+    #[error("{coin} limit price {px} is {deviation_bps} bps from mid {mid}, limit is {limit_bps}")]
+    PriceBand {
+        coin: String,
+        px: f64,
+        mid: f64,
+        deviation_bps: f64,
+        limit_bps: u16,
+    },
+    #[error("no mid price for {0} to check the price band against")]
+    NoMid(String),
+}
+
+/// Pre-trade checks applied by an [`crate::ExchangeClient`] before orders are signed.
+///
+/// Positions and the open order count are not tracked by the manager itself; keep them current
+/// with [`RiskManager::sync_positions`] and [`RiskManager::sync_open_orders`] or the setters.
+#[derive(Debug, Default)]
+pub struct RiskManager {
+    limits: RwLock<RiskLimits>,
+    killed: AtomicBool,
+    positions: RwLock<HashMap<String, f64>>,
+    open_orders: AtomicUsize,
+}
+
+impl RiskManager {
+    pub fn new(limits: RiskLimits) -> RiskManager {
+        RiskManager {
+            limits: RwLock::new(limits),
+            ..Default::default()
+        }
+    }
+
+    pub fn limits(&self) -> RiskLimits {
+        self.limits
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn set_limits(&self, limits: RiskLimits) {
+        *self.limits.write().unwrap_or_else(PoisonError::into_inner) = limits;
+    }
+
+    /// Rejects all new orders until [`RiskManager::resume`] is called. Cancels and reduce-only
+    /// orders, which can't increase exposure, are unaffected so positions can still be closed.
+    pub fn kill(&self) {
+        self.killed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.killed.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::SeqCst)
+    }
+
+    pub fn set_position(&self, coin: &str, szi: f64) {
+        self.positions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(coin.to_string(), szi);
+    }
+
+    pub fn sync_positions(&self, tracker: &PositionTracker) {
+        *self
+            .positions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = tracker
+            .positions()
+            .map(|position| (position.coin.clone(), position.szi))
+            .collect();
+    }
+
+    pub fn set_open_orders(&self, count: usize) {
+        self.open_orders.store(count, Ordering::SeqCst);
+    }
+
+    pub fn sync_open_orders(&self, tracker: &OrderTracker) {
+        self.set_open_orders(tracker.open_orders().count());
+    }
+
+    pub(crate) fn needs_mids(&self) -> bool {
+        self.limits
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .max_price_deviation_bps
+            .is_some()
+    }
+
+    /// Checks `orders` as one batch against the limits, looking up mids with `mid` if a price
+    /// band is configured.
+    pub fn check_orders(
+        &self,
+        orders: &[&ClientOrderRequest],
+        mid: impl Fn(&str) -> Option<f64>,
+    ) -> Result<()> {
+        self.check(orders, orders.len(), mid)
+            .map_err(Error::RiskRejected)
+    }
+
+    /// Like [`RiskManager::check_orders`], for orders replacing already open ones.
+    pub fn check_modifies(
+        &self,
+        orders: &[&ClientOrderRequest],
+        mid: impl Fn(&str) -> Option<f64>,
+    ) -> Result<()> {
+        self.check(orders, 0, mid).map_err(Error::RiskRejected)
+    }
+
+    fn check(
+        &self,
+        orders: &[&ClientOrderRequest],
+        new_orders: usize,
+        mid: impl Fn(&str) -> Option<f64>,
+    ) -> std::result::Result<(), RiskViolation> {
+        if self.is_killed() && orders.iter().any(|order| !order.reduce_only) {
+            return Err(RiskViolation::KillSwitch);
+        }
+        let limits = self.limits();
+
+        if let Some(limit) = limits.max_open_orders {
+            let count = self.open_orders.load(Ordering::SeqCst) + new_orders;
+            if count > limit {
+                return Err(RiskViolation::OpenOrders { count, limit });
+            }
+        }
+
+        let mut positions = self
+            .positions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        for order in orders {
+            let coin = &order.asset;
+
+            if let Some(limit) = limits.max_order_notional {
+                let notional = order.limit_px * order.sz;
+                if notional > limit {
+                    return Err(RiskViolation::OrderNotional {
+                        coin: coin.clone(),
+                        notional,
+                        limit,
+                    });
+                }
+            }
+
+            let position = positions.entry(coin.clone()).or_default();
+            let current = *position;
+            *position += if order.is_buy { order.sz } else { -order.sz };
+            if let Some(&limit) = limits.max_position.get(coin) {
+                // Orders reducing an already oversized position are still allowed
+                if !order.reduce_only && position.abs() > limit && position.abs() > current.abs() {
+                    return Err(RiskViolation::PositionLimit {
+                        coin: coin.clone(),
+                        position: *position,
+                        limit,
+                    });
+                }
+            }
+
+            let rests =
+                matches!(&order.order_type, ClientOrder::Limit(limit) if limit.tif != "Ioc");
+            if let Some(limit_bps) = limits.max_price_deviation_bps.filter(|_| rests) {
+                let mid = mid(coin).ok_or_else(|| RiskViolation::NoMid(coin.clone()))?;
+                let deviation_bps = (order.limit_px - mid).abs() / mid * 10_000.0;
+                if deviation_bps > limit_bps as f64 {
+                    return Err(RiskViolation::PriceBand {
+                        coin: coin.clone(),
+                        px: order.limit_px,
+                        mid,
+                        deviation_bps,
+                        limit_bps,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientLimit, ClientTrigger};
+
+    fn order(is_buy: bool, limit_px: f64, sz: f64) -> ClientOrderRequest {
+        ClientOrderRequest {
+            asset: "ETH".to_string(),
+            is_buy,
+            reduce_only: false,
+            limit_px,
+            sz,
+            cloid: None,
+            order_type: ClientOrder::Limit(ClientLimit {
+                tif: "Gtc".to_string(),
+            }),
+        }
+    }
+
+    fn violation(manager: &RiskManager, orders: &[&ClientOrderRequest]) -> Option<RiskViolation> {
+        manager.check(orders, orders.len(), |_| Some(2000.0)).err()
+    }
+
+    #[test]
+    fn test_risk_checks() {
+        let manager = RiskManager::new(RiskLimits {
+            max_order_notional: Some(10_000.0),
+            max_position: HashMap::from([("ETH".to_string(), 3.0)]),
+            max_open_orders: Some(3),
+            max_price_deviation_bps: Some(500),
+        });
+
+        assert_eq!(violation(&manager, &[&order(true, 2000.0, 2.0)]), None);
+        assert!(matches!(
+            violation(&manager, &[&order(true, 2000.0, 6.0)]),
+            Some(RiskViolation::OrderNotional { .. })
+        ));
+        assert!(matches!(
+            violation(&manager, &[&order(true, 2200.0, 1.0)]),
+            Some(RiskViolation::PriceBand { .. })
+        ));
+        // Market orders cross at mid ± slippage, and triggers fire away from the mid
+        let mut market = order(true, 2200.0, 1.0);
+        market.order_type = ClientOrder::Limit(ClientLimit {
+            tif: "Ioc".to_string(),
+        });
+        assert_eq!(violation(&manager, &[&market]), None);
+        market.order_type = ClientOrder::Trigger(ClientTrigger {
+            is_market: true,
+            trigger_px: 2150.0,
+            tpsl: "tp".to_string(),
+        });
+        assert_eq!(violation(&manager, &[&market]), None);
+
+        manager.set_position("ETH", 2.5);
+        assert!(matches!(
+            violation(&manager, &[&order(true, 2000.0, 1.0)]),
+            Some(RiskViolation::PositionLimit { .. })
+        ));
+        assert_eq!(violation(&manager, &[&order(false, 2000.0, 1.0)]), None);
+
+        manager.set_open_orders(2);
+        let (first, second) = (order(false, 2000.0, 1.0), order(false, 2000.0, 1.0));
+        assert_eq!(
+            violation(&manager, &[&first, &second]),
+            Some(RiskViolation::OpenOrders { count: 4, limit: 3 })
+        );
+        assert!(manager
+            .check_modifies(&[&first, &second], |_| Some(2000.0))
+            .is_ok());
+
+        manager.kill();
+        assert_eq!(
+            violation(&manager, &[&order(false, 2000.0, 1.0)]),
+            Some(RiskViolation::KillSwitch)
+        );
+        let mut close = order(false, 2000.0, 1.0);
+        close.reduce_only = true;
+        assert_eq!(violation(&manager, &[&close]), None);
+        manager.resume();
+        assert_eq!(violation(&manager, &[&order(false, 2000.0, 1.0)]), None);
+    }
+
+    #[test]
+    fn test_missing_mid() {
+        let manager = RiskManager::new(RiskLimits {
+            max_price_deviation_bps: Some(100),
+            ..Default::default()
+        });
+        assert!(matches!(
+            manager.check_orders(&[&order(true, 2000.0, 1.0)], |_| None),
+            Err(Error::RiskRejected(RiskViolation::NoMid(_)))
+        ));
+    }
+}
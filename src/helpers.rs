@@ -1,17 +1,17 @@
 use crate::{consts::*, prelude::*, Error};
 use chrono::prelude::Utc;
-use ethers::core::utils::keccak256;
+use ethers::types::U256;
 use lazy_static::lazy_static;
 use log::info;
 use rand::{thread_rng, Rng};
 use std::sync::atomic::{AtomicU64, Ordering};
 
-fn now_timestamp_ms() -> u64 {
+pub(crate) fn now_timestamp_ms() -> u64 {
     let now = Utc::now();
     now.timestamp_millis() as u64
 }
 
-pub(crate) fn next_nonce() -> u64 {
+pub fn next_nonce() -> u64 {
     let nonce = CUR_NONCE.fetch_add(1, Ordering::Relaxed);
     let now_ms = now_timestamp_ms();
     if nonce > now_ms + 1000 {
@@ -41,19 +41,6 @@ pub(crate) fn float_to_string_for_hashing(x: f64) -> String {
     }
 }
 
-/// Converts a string to a deterministic 128-bit (16 bytes) hex string prefixed with "0x".
-/// Uses Keccak256 hashing.
-pub(crate) fn string_to_hex_string(input: &str) -> String {
-    let hash = keccak256(input.as_bytes());
-    // Take the first 16 bytes (128 bits) of the hash
-    let truncated_hash = &hash[0..16];
-    let hex_string = truncated_hash
-        .iter()
-        .map(|byte| format!("{:02x}", byte))
-        .collect::<String>();
-    format!("0x{}", hex_string)
-}
-
 pub(crate) fn generate_random_key() -> Result<[u8; 32]> {
     let mut arr = [0u8; 32];
     thread_rng()
@@ -96,6 +83,52 @@ impl BaseUrl {
     }
 }
 
+/// Endpoints and signing parameters of a Hyperliquid deployment.
+///
+/// [`BaseUrl`] converts into the configuration of the public networks; private deployments and
+/// local mock servers can be described directly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkConfig {
+    pub api_url: String,
+    pub ws_url: String,
+    /// Sent as `hyperliquidChain` in user-signed actions, e.g. "Mainnet" or "Testnet".
+    pub hyperliquid_chain: String,
+    /// Chain id of the EIP-712 domain used for user-signed actions.
+    pub signature_chain_id: U256,
+    /// Selects the source of L1 action signatures ("a" on mainnet, "b" elsewhere).
+    pub is_mainnet: bool,
+}
+
+impl NetworkConfig {
+    /// Configuration for an API at `api_url`, deriving the websocket url from it and using the
+    /// default signature chain id.
+    pub fn new(api_url: &str, is_mainnet: bool) -> NetworkConfig {
+        let api_url = api_url.trim_end_matches('/').to_string();
+        let ws_url = if let Some(host) = api_url.strip_prefix("https") {
+            format!("wss{host}/ws")
+        } else if let Some(host) = api_url.strip_prefix("http") {
+            format!("ws{host}/ws")
+        } else {
+            format!("{api_url}/ws")
+        };
+        let hyperliquid_chain = if is_mainnet { "Mainnet" } else { "Testnet" }.to_string();
+
+        NetworkConfig {
+            api_url,
+            ws_url,
+            hyperliquid_chain,
+            signature_chain_id: DEFAULT_SIGNATURE_CHAIN_ID.into(),
+            is_mainnet,
+        }
+    }
+}
+
+impl From<BaseUrl> for NetworkConfig {
+    fn from(base_url: BaseUrl) -> NetworkConfig {
+        NetworkConfig::new(&base_url.get_url(), matches!(base_url, BaseUrl::Mainnet))
+    }
+}
+
 lazy_static! {
     static ref CUR_NONCE: AtomicU64 = AtomicU64::new(now_timestamp_ms());
 }
@@ -146,26 +179,21 @@ mod tests {
     }
 
     #[test]
-    fn string_to_hex_string_test() {
-        // Basic test case
-        assert_eq!(
-            string_to_hex_string("test"),
-            "0x9c22ff5f21f0b81b113e63f7db6da94fedef11b2119b4088b89664fb9a3cb658"[0..34] // 0x + 16*2 hex chars
-        );
-        // Test with empty string
-        assert_eq!(
-            string_to_hex_string(""),
-            "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"[0..34]
-        );
-        // Test with a longer string
-        assert_eq!(
-            string_to_hex_string("a longer test string for hashing"),
-            "0xb2ae80682ae56e5e8a930a907beeb1460f768176176b60f677105e9e661e3aaa"[0..34]
-        );
-        // Test with unicode
-        assert_eq!(
-            string_to_hex_string("你好世界"), // "Hello World" in Chinese
-            "0xd3f05f6be06f8b0a91c1ab0ea49f4554921f35d01eb600778ca36273f6b6d2a8"[0..34]
-        );
+    fn network_config_test() {
+        let mainnet = NetworkConfig::from(BaseUrl::Mainnet);
+        assert_eq!(mainnet.api_url, "https://api.hyperliquid.xyz");
+        assert_eq!(mainnet.ws_url, "wss://api.hyperliquid.xyz/ws");
+        assert_eq!(mainnet.hyperliquid_chain, "Mainnet");
+        assert_eq!(mainnet.signature_chain_id, 421614.into());
+        assert!(mainnet.is_mainnet);
+
+        let testnet = NetworkConfig::from(BaseUrl::Testnet);
+        assert_eq!(testnet.ws_url, "wss://api.hyperliquid-testnet.xyz/ws");
+        assert_eq!(testnet.hyperliquid_chain, "Testnet");
+        assert!(!testnet.is_mainnet);
+
+        let local = NetworkConfig::new("http://127.0.0.1:8080/", false);
+        assert_eq!(local.api_url, "http://127.0.0.1:8080");
+        assert_eq!(local.ws_url, "ws://127.0.0.1:8080/ws");
     }
 }
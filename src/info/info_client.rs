@@ -1,14 +1,16 @@
 use crate::{
     info::{
-        CandlesSnapshotResponse, FundingHistoryResponse, L2SnapshotResponse, OpenOrdersResponse,
-        OrderInfo, RecentTradesResponse, UserFillsResponse, UserStateResponse,
+        BasicOrderInfo, CandlesSnapshotResponse, ExpectedOrder, ExtraAgent, FundingHistoryResponse,
+        L2SnapshotResponse, OpenOrdersResponse, OrderInfo, OrderReconciliation,
+        RecentTradesResponse, UserFillsResponse, UserStateResponse,
     },
     meta::{Meta, SpotMeta, SpotMetaAndAssetCtxs},
     prelude::*,
     req::HttpClient,
     ws::{Subscription, WsManager},
-    BaseUrl, Error, Message, OrderStatusResponse, ReferralResponse, UserFeesResponse,
-    UserFundingResponse, UserTokenBalanceResponse,
+    BaseUrl, CandleInterval, Cloid, Error, Message, NetworkConfig, OrderStatusResponse,
+    RecordedFrame, ReferralResponse, UserFeesResponse, UserFundingResponse,
+    UserTokenBalanceResponse,
 };
 
 use ethers::types::H160;
@@ -21,11 +23,19 @@ use tokio::sync::mpsc::UnboundedSender;
 #[serde(rename_all = "camelCase")]
 pub struct CandleSnapshotRequest {
     coin: String,
-    interval: String,
+    interval: CandleInterval,
     start_time: u64,
     end_time: u64,
 }
 
+/// Identifies an order either by exchange-assigned oid or by client order id.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum OrderId {
+    Oid(u64),
+    Cloid(Cloid),
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
@@ -48,9 +58,12 @@ pub enum InfoRequest {
     OpenOrders {
         user: H160,
     },
+    FrontendOpenOrders {
+        user: H160,
+    },
     OrderStatus {
         user: H160,
-        oid: u64,
+        oid: OrderId,
     },
     Meta,
     SpotMeta,
@@ -71,8 +84,13 @@ pub enum InfoRequest {
         start_time: u64,
         end_time: Option<u64>,
     },
+    #[serde(rename_all = "camelCase")]
     L2Book {
         coin: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        n_sig_figs: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mantissa: Option<u32>,
     },
     RecentTrades {
         coin: String,
@@ -87,11 +105,15 @@ pub enum InfoRequest {
     HistoricalOrders {
         user: H160,
     },
+    ExtraAgents {
+        user: H160,
+    },
 }
 
 #[derive(Debug)]
 pub struct InfoClient {
     pub http_client: HttpClient,
+    pub network: NetworkConfig,
     pub(crate) ws_manager: Option<WsManager>,
     reconnect: bool,
 }
@@ -112,12 +134,25 @@ impl InfoClient {
         client: Option<Client>,
         base_url: Option<BaseUrl>,
         reconnect: bool,
+    ) -> Result<InfoClient> {
+        let network = base_url.unwrap_or(BaseUrl::Mainnet).into();
+        Self::from_network_config(client, network, reconnect).await
+    }
+
+    /// Creates a client for an arbitrary deployment, e.g. a private network or a local mock.
+    pub async fn from_network_config(
+        client: Option<Client>,
+        network: NetworkConfig,
+        reconnect: bool,
     ) -> Result<InfoClient> {
         let client = client.unwrap_or_default();
-        let base_url = base_url.unwrap_or(BaseUrl::Mainnet).get_url();
 
         Ok(InfoClient {
-            http_client: HttpClient { client, base_url },
+            http_client: HttpClient {
+                client,
+                base_url: network.api_url.clone(),
+            },
+            network,
             ws_manager: None,
             reconnect,
         })
@@ -128,12 +163,17 @@ impl InfoClient {
         subscription: Subscription,
         sender_channel: UnboundedSender<Message>,
     ) -> Result<u32> {
+        if let Subscription::L2Book {
+            n_sig_figs,
+            mantissa,
+            ..
+        } = &subscription
+        {
+            check_book_aggregation(*n_sig_figs, *mantissa)?;
+        }
+
         if self.ws_manager.is_none() {
-            let ws_manager = WsManager::new(
-                format!("ws{}/ws", &self.http_client.base_url[4..]),
-                self.reconnect,
-            )
-            .await?;
+            let ws_manager = WsManager::new(self.network.ws_url.clone(), self.reconnect).await?;
             self.ws_manager = Some(ws_manager);
         }
 
@@ -147,13 +187,27 @@ impl InfoClient {
             .await
     }
 
+    /// Sends every text frame received on the websocket to `sender` as is, for all subscriptions.
+    pub async fn subscribe_raw_frames(
+        &mut self,
+        sender: UnboundedSender<RecordedFrame>,
+    ) -> Result<()> {
+        if self.ws_manager.is_none() {
+            let ws_manager = WsManager::new(self.network.ws_url.clone(), self.reconnect).await?;
+            self.ws_manager = Some(ws_manager);
+        }
+
+        self.ws_manager
+            .as_ref()
+            .ok_or(Error::WsManagerNotFound)?
+            .add_raw_frame_sender(sender)
+            .await;
+        Ok(())
+    }
+
     pub async fn unsubscribe(&mut self, subscription_id: u32) -> Result<()> {
         if self.ws_manager.is_none() {
-            let ws_manager = WsManager::new(
-                format!("ws{}/ws", &self.http_client.base_url[4..]),
-                self.reconnect,
-            )
-            .await?;
+            let ws_manager = WsManager::new(self.network.ws_url.clone(), self.reconnect).await?;
             self.ws_manager = Some(ws_manager);
         }
 
@@ -180,6 +234,22 @@ impl InfoClient {
         self.send_info_request(input).await
     }
 
+    /// Open orders including trigger, reduce-only, TIF, cloid and attached TP/SL details.
+    pub async fn frontend_open_orders(&self, address: H160) -> Result<Vec<BasicOrderInfo>> {
+        let input = InfoRequest::FrontendOpenOrders { user: address };
+        self.send_info_request(input).await
+    }
+
+    /// Compares the orders we expect to be resting against the exchange's open orders.
+    pub async fn reconcile_open_orders(
+        &self,
+        address: H160,
+        expected: &[ExpectedOrder],
+    ) -> Result<OrderReconciliation> {
+        let open_orders = self.frontend_open_orders(address).await?;
+        Ok(OrderReconciliation::new(expected, open_orders))
+    }
+
     pub async fn user_state(&self, address: H160) -> Result<UserStateResponse> {
         let input = InfoRequest::UserState { user: address };
         self.send_info_request(input).await
@@ -259,14 +329,31 @@ impl InfoClient {
     }
 
     pub async fn l2_snapshot(&self, coin: String) -> Result<L2SnapshotResponse> {
-        let input = InfoRequest::L2Book { coin };
+        self.l2_snapshot_aggregated(coin, None, None).await
+    }
+
+    /// Book with levels aggregated to `n_sig_figs` significant figures (2 to 5). `mantissa` (1, 2
+    /// or 5) further coarsens the last figure and is only allowed with 5 significant figures.
+    /// Other values are rejected with [`Error::InvalidBookAggregation`].
+    pub async fn l2_snapshot_aggregated(
+        &self,
+        coin: String,
+        n_sig_figs: Option<u32>,
+        mantissa: Option<u32>,
+    ) -> Result<L2SnapshotResponse> {
+        check_book_aggregation(n_sig_figs, mantissa)?;
+        let input = InfoRequest::L2Book {
+            coin,
+            n_sig_figs,
+            mantissa,
+        };
         self.send_info_request(input).await
     }
 
     pub async fn candles_snapshot(
         &self,
         coin: String,
-        interval: String,
+        interval: CandleInterval,
         start_time: u64,
         end_time: u64,
     ) -> Result<Vec<CandlesSnapshotResponse>> {
@@ -282,7 +369,23 @@ impl InfoClient {
     }
 
     pub async fn query_order_by_oid(&self, address: H160, oid: u64) -> Result<OrderStatusResponse> {
-        let input = InfoRequest::OrderStatus { user: address, oid };
+        let input = InfoRequest::OrderStatus {
+            user: address,
+            oid: OrderId::Oid(oid),
+        };
+        self.send_info_request(input).await
+    }
+
+    /// Looks up an order by the cloid it was placed with, e.g. after a placement request timed out.
+    pub async fn query_order_by_cloid(
+        &self,
+        address: H160,
+        cloid: Cloid,
+    ) -> Result<OrderStatusResponse> {
+        let input = InfoRequest::OrderStatus {
+            user: address,
+            oid: OrderId::Cloid(cloid),
+        };
         self.send_info_request(input).await
     }
 
@@ -295,4 +398,87 @@ impl InfoClient {
         let input = InfoRequest::HistoricalOrders { user: address };
         self.send_info_request(input).await
     }
+
+    /// Agents approved by `address`, with their names and expiry.
+    pub async fn extra_agents(&self, address: H160) -> Result<Vec<ExtraAgent>> {
+        let input = InfoRequest::ExtraAgents { user: address };
+        self.send_info_request(input).await
+    }
+}
+
+fn check_book_aggregation(n_sig_figs: Option<u32>, mantissa: Option<u32>) -> Result<()> {
+    let valid = match (n_sig_figs, mantissa) {
+        (Some(5), Some(mantissa)) => [1, 2, 5].contains(&mantissa),
+        (_, Some(_)) => false,
+        (Some(n_sig_figs), None) => (2..=5).contains(&n_sig_figs),
+        (None, None) => true,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidBookAggregation {
+            n_sig_figs,
+            mantissa,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_status_request() -> Result<()> {
+        let user = H160::zero();
+        let by_oid = serde_json::to_value(InfoRequest::OrderStatus {
+            user,
+            oid: OrderId::Oid(26342632321),
+        })
+        .map_err(|e| Error::JsonParse(e.to_string()))?;
+        assert_eq!(by_oid["type"], "orderStatus");
+        assert_eq!(by_oid["oid"], 26342632321u64);
+
+        let by_cloid = serde_json::to_value(InfoRequest::OrderStatus {
+            user,
+            oid: OrderId::Cloid(Cloid::from_label("my-order")),
+        })
+        .map_err(|e| Error::JsonParse(e.to_string()))?;
+        assert_eq!(by_cloid["oid"], Cloid::from_label("my-order").to_string());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_book_aggregation_validation() -> Result<()> {
+        for (n_sig_figs, mantissa) in [(None, None), (Some(2), None), (Some(5), Some(2))] {
+            assert!(check_book_aggregation(n_sig_figs, mantissa).is_ok());
+        }
+        // Rejected before anything is sent
+        let mut info_client =
+            InfoClient::from_network_config(None, BaseUrl::Localhost.into(), false).await?;
+        for (n_sig_figs, mantissa) in [
+            (Some(1), None),
+            (Some(6), None),
+            (Some(4), Some(2)),
+            (Some(5), Some(3)),
+            (None, Some(1)),
+        ] {
+            assert!(matches!(
+                info_client
+                    .l2_snapshot_aggregated("BTC".to_string(), n_sig_figs, mantissa)
+                    .await,
+                Err(Error::InvalidBookAggregation { .. })
+            ));
+            let (sender, _) = tokio::sync::mpsc::unbounded_channel();
+            let subscription = Subscription::L2Book {
+                coin: "BTC".to_string(),
+                n_sig_figs,
+                mantissa,
+            };
+            assert!(matches!(
+                info_client.subscribe(subscription, sender).await,
+                Err(Error::InvalidBookAggregation { .. })
+            ));
+        }
+        Ok(())
+    }
 }
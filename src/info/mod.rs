@@ -1,6 +1,8 @@
 pub(super) mod info_client;
+mod order_reconciliation;
 mod response_structs;
 mod sub_structs;
 
+pub use order_reconciliation::{ExpectedOrder, OrderReconciliation};
 pub use response_structs::*;
 pub use sub_structs::*;
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use crate::info::BasicOrderInfo;
+
+/// An order we believe to be resting on the book, e.g. from our own placement records.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpectedOrder {
+    pub oid: u64,
+    pub coin: String,
+    pub is_buy: bool,
+    pub limit_px: f64,
+    pub sz: f64,
+}
+
+/// Differences between locally expected orders and the exchange's open orders.
+#[derive(Debug, Default)]
+pub struct OrderReconciliation {
+    /// Resting on the exchange but not expected locally
+    pub unknown: Vec<BasicOrderInfo>,
+    /// Expected locally but no longer open, i.e. filled or canceled
+    pub missing: Vec<ExpectedOrder>,
+    /// Open on both sides but with a different price or remaining size, e.g. partially filled
+    pub changed: Vec<(ExpectedOrder, BasicOrderInfo)>,
+}
+
+impl OrderReconciliation {
+    pub fn new(expected: &[ExpectedOrder], open_orders: Vec<BasicOrderInfo>) -> Self {
+        let mut expected_by_oid: HashMap<u64, &ExpectedOrder> =
+            expected.iter().map(|order| (order.oid, order)).collect();
+        let mut reconciliation = OrderReconciliation::default();
+
+        for open_order in open_orders {
+            match expected_by_oid.remove(&open_order.oid) {
+                Some(expected_order) => {
+                    if !matches(expected_order, &open_order) {
+                        reconciliation
+                            .changed
+                            .push((expected_order.clone(), open_order));
+                    }
+                }
+                None => reconciliation.unknown.push(open_order),
+            }
+        }
+
+        reconciliation.missing = expected
+            .iter()
+            .filter(|order| expected_by_oid.contains_key(&order.oid))
+            .cloned()
+            .collect();
+        reconciliation
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        self.unknown.is_empty() && self.missing.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn matches(expected: &ExpectedOrder, open_order: &BasicOrderInfo) -> bool {
+    let same = |a: f64, b: &str| {
+        b.parse::<f64>()
+            .map(|b| (a - b).abs() <= f64::EPSILON * a.abs().max(1.0))
+            .unwrap_or(false)
+    };
+
+    expected.coin == open_order.coin
+        && expected.is_buy == (open_order.side == "B")
+        && same(expected.limit_px, &open_order.limit_px)
+        && same(expected.sz, &open_order.sz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_order(oid: u64, sz: &str) -> BasicOrderInfo {
+        let json = format!(
+            r#"{{"coin":"ETH","side":"B","limitPx":"1800.0","sz":"{sz}","oid":{oid},
+            "timestamp":1700000000000,"triggerCondition":"N/A","isTrigger":false,"triggerPx":"0.0",
+            "children":[{{"coin":"ETH","side":"A","limitPx":"1700.0","sz":"0.0","oid":{},
+            "timestamp":1700000000000,"triggerCondition":"Price below 1710","isTrigger":true,
+            "triggerPx":"1710.0","children":[],"isPositionTpsl":false,"reduceOnly":true,
+            "orderType":"Stop Market","origSz":"0.0","tif":null,"cloid":null}}],
+            "isPositionTpsl":false,"reduceOnly":false,"orderType":"Limit","origSz":"0.1",
+            "tif":"Gtc","cloid":"0x00000000000000000000000000000001"}}"#,
+            oid + 1
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn expected_order(oid: u64) -> ExpectedOrder {
+        ExpectedOrder {
+            oid,
+            coin: "ETH".to_string(),
+            is_buy: true,
+            limit_px: 1800.0,
+            sz: 0.1,
+        }
+    }
+
+    #[test]
+    fn test_frontend_open_order_parsing() {
+        let order = open_order(1, "0.1");
+        assert_eq!(order.tif.as_deref(), Some("Gtc"));
+        assert_eq!(order.children.len(), 1);
+        assert!(order.children[0].is_trigger);
+        assert_eq!(order.children[0].tif, None);
+    }
+
+    #[test]
+    fn test_reconcile_open_orders() {
+        let expected = vec![expected_order(1), expected_order(2), expected_order(3)];
+        let open_orders = vec![
+            open_order(1, "0.1"),
+            open_order(2, "0.05"),
+            open_order(9, "1"),
+        ];
+
+        let reconciliation = OrderReconciliation::new(&expected, open_orders);
+        assert!(!reconciliation.is_consistent());
+        assert_eq!(reconciliation.unknown.len(), 1);
+        assert_eq!(reconciliation.unknown[0].oid, 9);
+        assert_eq!(reconciliation.missing, vec![expected_order(3)]);
+        assert_eq!(reconciliation.changed.len(), 1);
+        assert_eq!(reconciliation.changed[0].0.oid, 2);
+
+        let reconciliation = OrderReconciliation::new(&expected[..1], vec![open_order(1, "0.1")]);
+        assert!(reconciliation.is_consistent());
+    }
+}
@@ -4,7 +4,7 @@ use crate::{
 };
 use serde::Deserialize;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct UserStateResponse {
     pub asset_positions: Vec<AssetPosition>,
@@ -13,7 +13,7 @@ pub struct UserStateResponse {
     pub withdrawable: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Clone, Debug)]
 pub struct UserTokenBalanceResponse {
     pub balances: Vec<UserTokenBalance>,
 }
@@ -54,6 +54,8 @@ pub struct UserFillsResponse {
     pub sz: String,
     pub time: u64,
     pub fee: String,
+    pub fee_token: String,
+    pub tid: u64,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -1,7 +1,8 @@
+use crate::Cloid;
 use ethers::types::H160;
 use serde::Deserialize;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Leverage {
     #[serde(rename = "type")]
@@ -10,7 +11,7 @@ pub struct Leverage {
     pub raw_usd: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct CumulativeFunding {
     pub all_time: String,
@@ -18,7 +19,7 @@ pub struct CumulativeFunding {
     pub since_change: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct PositionData {
     pub coin: String,
@@ -34,14 +35,14 @@ pub struct PositionData {
     pub cum_funding: CumulativeFunding,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Clone, Debug)]
 pub struct AssetPosition {
     pub position: PositionData,
     #[serde(rename = "type")]
     pub type_string: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct MarginSummary {
     pub account_value: String,
@@ -108,7 +109,7 @@ pub struct Vip {
     pub ntl_cutoff: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct UserTokenBalance {
     pub coin: String,
@@ -137,12 +138,16 @@ pub struct BasicOrderInfo {
     pub trigger_condition: String,
     pub is_trigger: bool,
     pub trigger_px: String,
+    /// TP/SL orders attached to this order, placed once it fills
+    #[serde(default)]
+    pub children: Vec<BasicOrderInfo>,
     pub is_position_tpsl: bool,
     pub reduce_only: bool,
     pub order_type: String,
     pub orig_sz: String,
-    pub tif: String,
-    pub cloid: Option<String>,
+    /// `None` for trigger orders
+    pub tif: Option<String>,
+    pub cloid: Option<Cloid>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -164,3 +169,12 @@ pub struct ReferrerState {
 pub struct ReferrerData {
     pub required: String,
 }
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtraAgent {
+    pub address: H160,
+    pub name: String,
+    /// Expiry in milliseconds since the epoch
+    pub valid_until: u64,
+}
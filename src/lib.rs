@@ -1,21 +1,49 @@
 #![deny(unreachable_pub)]
+mod backtest;
+mod candles;
 mod consts;
 mod errors;
 mod exchange;
 mod helpers;
 mod info;
+mod margin;
 mod market_maker;
 mod meta;
+mod metadata_cache;
+mod order_tracker;
+mod paper;
+mod position_tracker;
 mod prelude;
 mod proxy_digest;
+mod recorder;
 mod req;
 mod signature;
+mod spot_balances;
+mod strategy;
 mod ws;
-pub use consts::{EPSILON, LOCAL_API_URL, MAINNET_API_URL, TESTNET_API_URL};
+pub use backtest::{Backtest, BacktestConfig, BacktestReport, BacktestStrategy, InventoryStats};
+pub use candles::{CandleAggregator, CandleInterval};
+pub use consts::{
+    DEFAULT_SIGNATURE_CHAIN_ID, EPSILON, LOCAL_API_URL, MAINNET_API_URL, TESTNET_API_URL,
+};
 pub use errors::Error;
 pub use exchange::*;
-pub use helpers::{bps_diff, truncate_float, BaseUrl};
+pub use helpers::{bps_diff, next_nonce, truncate_float, BaseUrl, NetworkConfig};
 pub use info::{info_client::*, *};
+pub use margin::{
+    MarginCalculator, MarginImpact, MarginSnapshot, PositionMargin, DEFAULT_LEVERAGE,
+};
 pub use market_maker::{MarketMaker, MarketMakerInput, MarketMakerRestingOrder};
-pub use meta::{AssetMeta, Meta};
+pub use meta::{AssetMeta, Meta, SpotAssetMeta, SpotMeta, TokenInfo};
+pub use metadata_cache::{AssetMetadata, MetadataCache, MetadataEvent};
+pub use order_tracker::{OrderState, OrderTracker, TrackedOrder};
+pub use paper::{
+    FeeRates, MatchingEngine, PaperExchange, Placement, PlacementStatus, SimFill, SimOrder,
+    TimeInForce,
+};
+pub use position_tracker::{Position, PositionDiscrepancy, PositionTracker};
+pub use recorder::{RecordedFrame, Recorder, RecorderHandle, Replayer};
+pub use signature::{verify_l1_action, verify_typed_data};
+pub use spot_balances::{Portfolio, SpotBalance, SpotBalances, SpotHold, SpotHolding};
+pub use strategy::{Strategy, StrategyContext, StrategyRuntime};
 pub use ws::*;
@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+
+use crate::{prelude::*, ClientOrderRequest, Error, Meta, UserStateResponse, EPSILON};
+
+/// Leverage assumed for a new position on a coin without an existing leverage setting.
+pub const DEFAULT_LEVERAGE: u32 = 20;
+
+/// Margin inputs for a single perp position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionMargin {
+    pub coin: String,
+    /// Signed size, negative when short
+    pub szi: f64,
+    pub mark_px: f64,
+    pub leverage: u32,
+    pub max_leverage: u32,
+    pub is_cross: bool,
+    /// Margin allocated to an isolated position, including its unrealized PnL
+    pub isolated_margin: f64,
+}
+
+impl PositionMargin {
+    pub fn notional(&self) -> f64 {
+        self.szi.abs() * self.mark_px
+    }
+
+    pub fn initial_margin(&self) -> f64 {
+        self.notional() / self.leverage as f64
+    }
+
+    /// Maintenance margin is half of the initial margin at max leverage.
+    pub fn maintenance_margin(&self) -> f64 {
+        self.notional() * maintenance_margin_rate(self.max_leverage)
+    }
+}
+
+/// Account-level margin figures, with the liquidation price of the coin in question.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarginSnapshot {
+    pub account_value: f64,
+    pub initial_margin: f64,
+    pub maintenance_margin: f64,
+    /// Account value not used as initial margin
+    pub free_margin: f64,
+    pub liquidation_px: Option<f64>,
+}
+
+/// Margin figures before and after a hypothetical order is filled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarginImpact {
+    pub before: MarginSnapshot,
+    pub after: MarginSnapshot,
+}
+
+impl MarginImpact {
+    /// Whether the account would still have non-negative free margin, or the order reduces the
+    /// margin requirement.
+    pub fn is_allowed(&self) -> bool {
+        self.after.free_margin >= -EPSILON
+            || self.after.initial_margin <= self.before.initial_margin + EPSILON
+    }
+}
+
+/// Reimplements the exchange's margin math on top of a clearinghouse state.
+///
+/// Positions are valued at their mark price, derived from `position_value / |szi|`.
+#[derive(Debug, Clone)]
+pub struct MarginCalculator {
+    /// Account value excluding isolated positions
+    pub cross_account_value: f64,
+    pub positions: Vec<PositionMargin>,
+    max_leverage: HashMap<String, u32>,
+}
+
+impl MarginCalculator {
+    pub fn new(user_state: &UserStateResponse, meta: &Meta) -> Result<MarginCalculator> {
+        let max_leverage: HashMap<String, u32> = meta
+            .universe
+            .iter()
+            .map(|asset| (asset.name.clone(), asset.max_leverage))
+            .collect();
+
+        let positions = user_state
+            .asset_positions
+            .iter()
+            .map(|asset_position| {
+                let position = &asset_position.position;
+                let szi = parse(&position.szi)?;
+                let position_value = parse(&position.position_value)?;
+                let is_cross = position.leverage.type_string == "cross";
+                Ok(PositionMargin {
+                    coin: position.coin.clone(),
+                    szi,
+                    mark_px: if szi.abs() > EPSILON {
+                        position_value / szi.abs()
+                    } else {
+                        0.0
+                    },
+                    leverage: position.leverage.value,
+                    max_leverage: max_leverage
+                        .get(&position.coin)
+                        .copied()
+                        .unwrap_or(position.max_leverage),
+                    is_cross,
+                    isolated_margin: if is_cross {
+                        0.0
+                    } else {
+                        parse(&position.margin_used)?
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(MarginCalculator {
+            cross_account_value: parse(&user_state.cross_margin_summary.account_value)?,
+            positions,
+            max_leverage,
+        })
+    }
+
+    pub fn position(&self, coin: &str) -> Option<&PositionMargin> {
+        self.positions.iter().find(|position| position.coin == coin)
+    }
+
+    /// Total account value including isolated margin.
+    pub fn account_value(&self) -> f64 {
+        self.cross_account_value
+            + self
+                .positions
+                .iter()
+                .filter(|position| !position.is_cross)
+                .map(|position| position.isolated_margin)
+                .sum::<f64>()
+    }
+
+    pub fn initial_margin(&self) -> f64 {
+        self.positions
+            .iter()
+            .map(PositionMargin::initial_margin)
+            .sum()
+    }
+
+    pub fn maintenance_margin(&self) -> f64 {
+        self.positions
+            .iter()
+            .map(PositionMargin::maintenance_margin)
+            .sum()
+    }
+
+    fn cross_maintenance_margin(&self) -> f64 {
+        self.positions
+            .iter()
+            .filter(|position| position.is_cross)
+            .map(PositionMargin::maintenance_margin)
+            .sum()
+    }
+
+    /// Price at which the position in `coin` would be liquidated, all else being equal. `None` if
+    /// there is no position or it can't be liquidated.
+    pub fn liquidation_price(&self, coin: &str) -> Option<f64> {
+        let position = self.position(coin)?;
+        if position.szi.abs() < EPSILON {
+            return None;
+        }
+
+        let side = position.szi.signum();
+        let rate = maintenance_margin_rate(position.max_leverage);
+        let margin_available = if position.is_cross {
+            self.cross_account_value - self.cross_maintenance_margin()
+        } else {
+            position.isolated_margin - position.maintenance_margin()
+        };
+
+        let liquidation_px =
+            position.mark_px - side * margin_available / position.szi.abs() / (1.0 - rate * side);
+        (liquidation_px > 0.0).then_some(liquidation_px)
+    }
+
+    pub fn snapshot(&self, coin: &str) -> MarginSnapshot {
+        let account_value = self.account_value();
+        let initial_margin = self.initial_margin();
+        MarginSnapshot {
+            account_value,
+            initial_margin,
+            maintenance_margin: self.maintenance_margin(),
+            free_margin: account_value - initial_margin,
+            liquidation_px: self.liquidation_price(coin),
+        }
+    }
+
+    /// Margin impact of `order` if it were fully filled at its limit price.
+    ///
+    /// New positions are opened cross at [`DEFAULT_LEVERAGE`], capped at the coin's max
+    /// leverage. Margin for isolated positions is moved from and to the cross account.
+    pub fn margin_impact(&self, order: &ClientOrderRequest) -> Result<MarginImpact> {
+        let &max_leverage = self
+            .max_leverage
+            .get(&order.asset)
+            .ok_or(Error::AssetNotFound)?;
+
+        let mut after = self.clone();
+        let index = match after
+            .positions
+            .iter()
+            .position(|position| position.coin == order.asset)
+        {
+            Some(index) => index,
+            None => {
+                after.positions.push(PositionMargin {
+                    coin: order.asset.clone(),
+                    szi: 0.0,
+                    mark_px: order.limit_px,
+                    leverage: DEFAULT_LEVERAGE.min(max_leverage),
+                    max_leverage,
+                    is_cross: true,
+                    isolated_margin: 0.0,
+                });
+                after.positions.len() - 1
+            }
+        };
+
+        let position = &mut after.positions[index];
+        let signed_sz = if order.is_buy { order.sz } else { -order.sz };
+        let initial_margin_before = position.initial_margin();
+        // Filling away from the mark is an immediate unrealized gain or loss
+        let pnl = (position.mark_px - order.limit_px) * signed_sz;
+        position.szi += signed_sz;
+
+        if position.is_cross {
+            after.cross_account_value += pnl;
+        } else {
+            let margin_delta = position.initial_margin() - initial_margin_before;
+            position.isolated_margin += pnl + margin_delta;
+            after.cross_account_value -= margin_delta;
+        }
+
+        Ok(MarginImpact {
+            before: self.snapshot(&order.asset),
+            after: after.snapshot(&order.asset),
+        })
+    }
+}
+
+fn maintenance_margin_rate(max_leverage: u32) -> f64 {
+    1.0 / (2.0 * max_leverage as f64)
+}
+
+fn parse(value: &str) -> Result<f64> {
+    value.parse().map_err(|_| Error::FloatStringParse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientLimit, ClientOrder};
+
+    fn user_state(leverage_type: &str, margin_used: &str) -> UserStateResponse {
+        serde_json::from_value(serde_json::json!({
+            "assetPositions": [{
+                "type": "oneWay",
+                "position": {
+                    "coin": "ETH", "entryPx": "2000.0",
+                    "leverage": {"type": leverage_type, "value": 10},
+                    "liquidationPx": null, "marginUsed": margin_used, "positionValue": "2000.0",
+                    "returnOnEquity": "0", "szi": "1.0", "unrealizedPnl": "0", "maxLeverage": 50,
+                    "cumFunding": {"allTime": "0", "sinceOpen": "0", "sinceChange": "0"}
+                }
+            }],
+            "crossMarginSummary": {
+                "accountValue": "1000.0", "totalMarginUsed": "200.0", "totalNtlPos": "2000.0",
+                "totalRawUsd": "-1000.0"
+            },
+            "marginSummary": {
+                "accountValue": "1000.0", "totalMarginUsed": "200.0", "totalNtlPos": "2000.0",
+                "totalRawUsd": "-1000.0"
+            },
+            "withdrawable": "800.0"
+        }))
+        .unwrap()
+    }
+
+    fn meta() -> Meta {
+        serde_json::from_str(
+            r#"{"universe": [
+                {"name": "BTC", "szDecimals": 5, "maxLeverage": 50},
+                {"name": "ETH", "szDecimals": 4, "maxLeverage": 50}
+            ]}"#,
+        )
+        .unwrap()
+    }
+
+    fn order(asset: &str, is_buy: bool, limit_px: f64, sz: f64) -> ClientOrderRequest {
+        ClientOrderRequest {
+            asset: asset.to_string(),
+            is_buy,
+            reduce_only: false,
+            limit_px,
+            sz,
+            cloid: None,
+            order_type: ClientOrder::Limit(ClientLimit {
+                tif: "Gtc".to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_cross_liquidation_price() -> Result<()> {
+        let calculator = MarginCalculator::new(&user_state("cross", "200.0"), &meta())?;
+        assert!((calculator.initial_margin() - 200.0).abs() < EPSILON);
+        assert!((calculator.maintenance_margin() - 20.0).abs() < EPSILON);
+
+        // At the liquidation price the account value equals the maintenance margin
+        let liquidation_px = calculator.liquidation_price("ETH").unwrap();
+        let account_value = 1000.0 + (liquidation_px - 2000.0);
+        assert!((account_value - liquidation_px / 100.0).abs() < 1e-6);
+        assert_eq!(calculator.liquidation_price("BTC"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_isolated_liquidation_price() -> Result<()> {
+        let calculator = MarginCalculator::new(&user_state("isolated", "200.0"), &meta())?;
+        assert!((calculator.account_value() - 1200.0).abs() < EPSILON);
+
+        let liquidation_px = calculator.liquidation_price("ETH").unwrap();
+        let isolated_margin = 200.0 + (liquidation_px - 2000.0);
+        assert!((isolated_margin - liquidation_px / 100.0).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_margin_impact() -> Result<()> {
+        let calculator = MarginCalculator::new(&user_state("cross", "200.0"), &meta())?;
+
+        let impact = calculator.margin_impact(&order("ETH", true, 2000.0, 1.0))?;
+        assert!((impact.after.initial_margin - 400.0).abs() < EPSILON);
+        assert!(impact.after.liquidation_px.unwrap() > impact.before.liquidation_px.unwrap());
+        assert!(impact.is_allowed());
+
+        let impact = calculator.margin_impact(&order("BTC", false, 50000.0, 1.0))?;
+        assert!((impact.after.initial_margin - 2700.0).abs() < EPSILON);
+        assert!(!impact.is_allowed());
+
+        let impact = calculator.margin_impact(&order("ETH", false, 2000.0, 1.0))?;
+        assert_eq!(impact.after.liquidation_px, None);
+        assert!(impact.is_allowed());
+
+        assert!(calculator
+            .margin_impact(&order("SOL", true, 100.0, 1.0))
+            .is_err());
+        Ok(())
+    }
+}
@@ -2,14 +2,17 @@ use ethers::{
     signers::{LocalWallet, Signer},
     types::H160,
 };
-use log::{error, info};
+use log::{error, info, warn};
 
-use tokio::sync::mpsc::unbounded_channel;
+use std::collections::HashMap;
+
+use tokio::signal;
 
 use crate::{
-    bps_diff, truncate_float, BaseUrl, ClientCancelRequest, ClientLimit, ClientOrder,
-    ClientOrderRequest, ExchangeClient, ExchangeDataStatus, ExchangeResponseStatus, InfoClient,
-    Message, Subscription, UserData, EPSILON,
+    bps_diff, prelude::*, truncate_float, ClientCancelRequest, ClientLimit, ClientModifyRequest,
+    ClientOrder, ClientOrderRequest, Error, ExchangeClient, ExchangeDataStatus,
+    ExchangeResponseStatus, InfoClient, NetworkConfig, OpenOrdersResponse, Strategy,
+    StrategyContext, StrategyRuntime, Subscription, TradeInfo, EPSILON,
 };
 #[derive(Debug)]
 pub struct MarketMakerRestingOrder {
@@ -18,15 +21,34 @@ pub struct MarketMakerRestingOrder {
     pub price: f64,
 }
 
+impl MarketMakerRestingOrder {
+    fn empty() -> MarketMakerRestingOrder {
+        MarketMakerRestingOrder {
+            oid: 0,
+            position: 0.0,
+            price: -1.0,
+        }
+    }
+
+    fn is_resting(&self) -> bool {
+        self.oid != 0 && self.position > EPSILON
+    }
+}
+
 #[derive(Debug)]
 pub struct MarketMakerInput {
     pub asset: String,
-    pub target_liquidity: f64, // Amount of liquidity on both sides to target
+    pub target_liquidity: f64, // Amount of liquidity to target on the innermost level of both sides
     pub half_spread: u16,      // Half of the spread for our market making (in BPS)
-    pub max_bps_diff: u16, // Max deviation before we cancel and put new orders on the book (in BPS)
+    pub max_bps_diff: u16,     // Max deviation before we modify an order on the book (in BPS)
     pub max_absolute_position_size: f64, // Absolute value of the max position we can take on
-    pub decimals: u32,     // Decimals to round to for pricing
-    pub wallet: LocalWallet, // Wallet containing private key
+    pub levels: usize,         // Number of orders to quote on each side
+    pub level_spacing: u16,    // Extra distance from the mid of each level past the first (in BPS)
+    pub size_multiplier: f64,  // Size of each level relative to the one inside it
+    pub inventory_skew: u16,   // Shift of our market away from a max size position (in BPS)
+    pub owns_asset: bool,      // Nothing else trades the asset, so stray orders can be cancelled
+    pub wallet: LocalWallet,   // Wallet containing private key
+    pub network: NetworkConfig, // Network to quote on, e.g. `BaseUrl::Testnet.into()`
 }
 
 #[derive(Debug)]
@@ -36,299 +58,602 @@ pub struct MarketMaker {
     pub half_spread: u16,
     pub max_bps_diff: u16,
     pub max_absolute_position_size: f64,
+    pub levels: usize,
+    pub level_spacing: u16,
+    pub size_multiplier: f64,
+    pub inventory_skew: u16,
+    /// Cancel open orders on the asset that don't fit the ladder when resyncing
+    pub owns_asset: bool,
+    /// Max price decimals of the asset, derived from its metadata on start
     pub decimals: u32,
-    pub lower_resting: MarketMakerRestingOrder,
-    pub upper_resting: MarketMakerRestingOrder,
+    /// Size decimals of the asset, derived from its metadata on start
+    pub sz_decimals: u32,
+    /// Resting buy orders, innermost level first
+    pub lower_resting: Vec<MarketMakerRestingOrder>,
+    /// Resting sell orders, innermost level first
+    pub upper_resting: Vec<MarketMakerRestingOrder>,
     pub cur_position: f64,
     pub latest_mid_price: f64,
-    pub info_client: InfoClient,
-    pub exchange_client: ExchangeClient,
     pub user_address: H160,
+    pub wallet: LocalWallet,
+    pub network: NetworkConfig,
+    // Set until open orders and the position have been read from the exchange
+    needs_resync: bool,
 }
 
+// Price and size to quote on one level
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Quote {
+    price: f64,
+    size: f64,
+}
+
+// Position of an order in the ladder: buy side or not, and level
+type Slot = (bool, usize);
+
 impl MarketMaker {
-    pub async fn new(input: MarketMakerInput) -> MarketMaker {
+    pub fn new(input: MarketMakerInput) -> MarketMaker {
         let user_address = input.wallet.address();
 
-        let info_client = InfoClient::new(None, Some(BaseUrl::Testnet)).await.unwrap();
-        let exchange_client =
-            ExchangeClient::new(None, input.wallet, Some(BaseUrl::Testnet), None, None)
-                .await
-                .unwrap();
-
         MarketMaker {
             asset: input.asset,
             target_liquidity: input.target_liquidity,
             half_spread: input.half_spread,
             max_bps_diff: input.max_bps_diff,
             max_absolute_position_size: input.max_absolute_position_size,
-            decimals: input.decimals,
-            lower_resting: MarketMakerRestingOrder {
-                oid: 0,
-                position: 0.0,
-                price: -1.0,
-            },
-            upper_resting: MarketMakerRestingOrder {
-                oid: 0,
-                position: 0.0,
-                price: -1.0,
-            },
+            levels: input.levels,
+            level_spacing: input.level_spacing,
+            size_multiplier: input.size_multiplier,
+            inventory_skew: input.inventory_skew,
+            owns_asset: input.owns_asset,
+            decimals: 0,
+            sz_decimals: 0,
+            lower_resting: empty_levels(input.levels),
+            upper_resting: empty_levels(input.levels),
             cur_position: 0.0,
             latest_mid_price: -1.0,
-            info_client,
-            exchange_client,
             user_address,
+            wallet: input.wallet,
+            network: input.network,
+            needs_resync: true,
         }
     }
 
-    pub async fn start(&mut self) {
-        let (sender, mut receiver) = unbounded_channel();
-
-        // Subscribe to UserEvents for fills
-        self.info_client
-            .subscribe(
-                Subscription::UserEvents {
-                    user: self.user_address,
-                },
-                sender.clone(),
-            )
-            .await
-            .unwrap();
+    /// Quotes until interrupted with ctrl-c, then cancels the resting quotes.
+    pub async fn start(&mut self) -> Result<()> {
+        let info_client = InfoClient::from_network_config(None, self.network.clone(), true).await?;
+        let exchange_client = ExchangeClient::from_network_config(
+            None,
+            self.wallet.clone(),
+            self.network.clone(),
+            None,
+            None,
+        )
+        .await?;
 
-        // Subscribe to AllMids so we can market make around the mid price
-        self.info_client
-            .subscribe(Subscription::AllMids, sender)
-            .await
-            .unwrap();
-
-        loop {
-            let message = receiver.recv().await.unwrap();
-            match message {
-                Message::AllMids(all_mids) => {
-                    let all_mids = all_mids.data.mids;
-                    let mid = all_mids.get(&self.asset);
-                    if let Some(mid) = mid {
-                        let mid: f64 = mid.parse().unwrap();
-                        self.latest_mid_price = mid;
-                        // Check to see if we need to cancel or place any new orders
-                        self.potentially_update().await;
-                    } else {
-                        error!(
-                            "could not get mid for asset {}: {all_mids:?}",
-                            self.asset.clone()
-                        );
-                    }
-                }
-                Message::User(user_events) => {
-                    // We haven't seen the first mid price event yet, so just continue
-                    if self.latest_mid_price < 0.0 {
-                        continue;
-                    }
-                    let user_events = user_events.data;
-                    if let UserData::Fills(fills) = user_events {
-                        for fill in fills {
-                            let amount: f64 = fill.sz.parse().unwrap();
-                            // Update our resting positions whenever we see a fill
-                            if fill.side.eq("B") {
-                                self.cur_position += amount;
-                                self.lower_resting.position -= amount;
-                                info!("Fill: bought {amount} {}", self.asset.clone());
-                            } else {
-                                self.cur_position -= amount;
-                                self.upper_resting.position -= amount;
-                                info!("Fill: sold {amount} {}", self.asset.clone());
-                            }
-                        }
-                    }
-                    // Check to see if we need to cancel or place any new orders
-                    self.potentially_update().await;
-                }
-                _ => {
-                    panic!("Unsupported message type");
+        let assets = exchange_client
+            .metadata
+            .ensure_coins([self.asset.as_str()])
+            .await?;
+        let asset_index = assets
+            .asset_index(&self.asset)
+            .ok_or(Error::AssetNotFound)?;
+        let sz_decimals = assets
+            .sz_decimals(&self.asset)
+            .ok_or(Error::AssetNotFound)?;
+        let max_decimals: u32 = if asset_index < 10000 { 6 } else { 8 };
+        self.decimals = max_decimals.saturating_sub(sz_decimals);
+        self.sz_decimals = sz_decimals;
+
+        StrategyRuntime::new(info_client, exchange_client)
+            .run(self, async {
+                if let Err(err) = signal::ctrl_c().await {
+                    error!("Could not listen for ctrl-c: {err}");
                 }
+            })
+            .await
+    }
+
+    // Prices are limited to 5 significant figures and the asset's max decimals. Prices that
+    // aren't positive can't be quoted and come out as 0.
+    fn round_price(&self, price: f64, round_up: bool) -> f64 {
+        if price.is_nan() || price <= 0.0 {
+            return 0.0;
+        }
+        let magnitude = price.abs().log10().floor() as i32;
+        let decimals = self.decimals.min((4 - magnitude).max(0) as u32);
+        truncate_float(price, decimals, round_up)
+    }
+
+    fn resting_mut(&mut self, (is_buy, level): Slot) -> &mut MarketMakerRestingOrder {
+        if is_buy {
+            &mut self.lower_resting[level]
+        } else {
+            &mut self.upper_resting[level]
+        }
+    }
+
+    // Re-reads the position and adopts the open orders on the asset that fit the ladder, e.g.
+    // after a reconnect. Orders that don't fit are cancelled if we own the asset, and otherwise
+    // left alone as they may not be ours.
+    async fn resync(&mut self, exchange_client: &ExchangeClient) -> Result<()> {
+        let info_client = exchange_client.metadata.info_client();
+        self.cur_position = info_client
+            .user_state(self.user_address)
+            .await?
+            .asset_positions
+            .iter()
+            .find(|asset_position| asset_position.position.coin == self.asset)
+            .map(|asset_position| asset_position.position.szi.parse::<f64>())
+            .transpose()
+            .map_err(|_| Error::FloatStringParse)?
+            .unwrap_or(0.0);
+        info!("Resynced {} position: {}", self.asset, self.cur_position);
+
+        let open_orders: Vec<OpenOrdersResponse> = info_client
+            .open_orders(self.user_address)
+            .await?
+            .into_iter()
+            .filter(|order| order.coin == self.asset)
+            .collect();
+        let unmatched = self.adopt(&open_orders)?;
+        if !unmatched.is_empty() {
+            if self.owns_asset {
+                info!("Cancelling {} stray {} orders", unmatched.len(), self.asset);
+                let cancels = unmatched
+                    .into_iter()
+                    .map(|oid| ClientCancelRequest {
+                        asset: self.asset.clone(),
+                        oid,
+                    })
+                    .collect();
+                exchange_client.bulk_cancel(cancels, None).await?;
+            } else {
+                info!(
+                    "Leaving {} open {} orders outside our ladder",
+                    unmatched.len(),
+                    self.asset
+                );
             }
         }
+        self.needs_resync = false;
+        Ok(())
     }
 
-    async fn attempt_cancel(&self, asset: String, oid: u64) -> bool {
-        let cancel = self
-            .exchange_client
-            .cancel(ClientCancelRequest { asset, oid }, None)
-            .await;
-
-        match cancel {
-            Ok(cancel) => match cancel {
-                ExchangeResponseStatus::Ok(cancel) => {
-                    if let Some(cancel) = cancel.data {
-                        if !cancel.statuses.is_empty() {
-                            match cancel.statuses[0].clone() {
-                                ExchangeDataStatus::Success => {
-                                    return true;
-                                }
-                                ExchangeDataStatus::Error(e) => {
-                                    error!("Error with cancelling: {e}")
-                                }
-                                _ => unreachable!(),
-                            }
-                        } else {
-                            error!("Exchange data statuses is empty when cancelling: {cancel:?}")
-                        }
-                    } else {
-                        error!("Exchange response data is empty when cancelling: {cancel:?}")
-                    }
+    // Replaces the ladder with the open orders within `max_bps_diff` of a level's target price,
+    // returning the oids of those that fit no level
+    fn adopt(&mut self, open_orders: &[OpenOrdersResponse]) -> Result<Vec<u64>> {
+        self.lower_resting = empty_levels(self.levels);
+        self.upper_resting = empty_levels(self.levels);
+        let (lower_quotes, upper_quotes) = self.quotes();
+
+        let mut unmatched = Vec::new();
+        for order in open_orders {
+            let price: f64 = order
+                .limit_px
+                .parse()
+                .map_err(|_| Error::FloatStringParse)?;
+            let size: f64 = order.sz.parse().map_err(|_| Error::FloatStringParse)?;
+            let (quotes, resting) = if order.side == "B" {
+                (&lower_quotes, &mut self.lower_resting)
+            } else {
+                (&upper_quotes, &mut self.upper_resting)
+            };
+            let slot = quotes
+                .iter()
+                .zip(resting.iter_mut())
+                .find(|(quote, resting)| {
+                    !resting.is_resting()
+                        && quote.size > EPSILON
+                        && bps_diff(quote.price, price) <= self.max_bps_diff
+                });
+            match slot {
+                Some((_, resting)) => {
+                    info!("Adopted order {} at {price}", order.oid);
+                    *resting = MarketMakerRestingOrder {
+                        oid: order.oid,
+                        position: size,
+                        price,
+                    };
                 }
-                ExchangeResponseStatus::Err(e) => error!("Error with cancelling: {e}"),
-            },
-            Err(e) => error!("Error with cancelling: {e}"),
+                None => unmatched.push(order.oid),
+            }
         }
-        false
+        Ok(unmatched)
     }
 
-    async fn place_order(
-        &self,
-        asset: String,
-        amount: f64,
-        price: f64,
-        is_buy: bool,
-    ) -> (f64, u64) {
-        let order = self
-            .exchange_client
-            .order(
-                ClientOrderRequest {
-                    asset,
-                    is_buy,
-                    reduce_only: false,
-                    limit_px: price,
-                    sz: amount,
-                    cloid: None,
-                    order_type: ClientOrder::Limit(ClientLimit {
-                        tif: "Gtc".to_string(),
-                    }),
-                },
-                None,
-            )
-            .await;
-        match order {
-            Ok(order) => match order {
-                ExchangeResponseStatus::Ok(order) => {
-                    if let Some(order) = order.data {
-                        if !order.statuses.is_empty() {
-                            match order.statuses[0].clone() {
-                                ExchangeDataStatus::Filled(order) => {
-                                    return (amount, order.oid);
-                                }
-                                ExchangeDataStatus::Resting(order) => {
-                                    return (amount, order.oid);
-                                }
-                                ExchangeDataStatus::Error(e) => {
-                                    error!("Error with placing order: {e}")
-                                }
-                                _ => unreachable!(),
-                            }
-                        } else {
-                            error!("Exchange data statuses is empty when placing order: {order:?}")
-                        }
-                    } else {
-                        error!("Exchange response data is empty when placing order: {order:?}")
-                    }
-                }
-                ExchangeResponseStatus::Err(e) => {
-                    error!("Error with placing order: {e}")
+    async fn cancel_quotes(&mut self, exchange_client: &ExchangeClient) -> Result<()> {
+        let cancels: Vec<ClientCancelRequest> = self
+            .lower_resting
+            .iter()
+            .chain(&self.upper_resting)
+            .filter(|resting| resting.is_resting())
+            .map(|resting| ClientCancelRequest {
+                asset: self.asset.clone(),
+                oid: resting.oid,
+            })
+            .collect();
+        self.lower_resting = empty_levels(self.levels);
+        self.upper_resting = empty_levels(self.levels);
+        if !cancels.is_empty() {
+            info!("Cancelling {} quotes", cancels.len());
+            exchange_client.bulk_cancel(cancels, None).await?;
+        }
+        Ok(())
+    }
+
+    // Target quotes of each level for both sides, innermost level first
+    fn quotes(&self) -> (Vec<Quote>, Vec<Quote>) {
+        // Shift our market away from our position so that it is more likely to be reduced
+        let inventory = if self.max_absolute_position_size > EPSILON {
+            (self.cur_position / self.max_absolute_position_size).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        let center =
+            self.latest_mid_price * (1.0 - inventory * self.inventory_skew as f64 / 10000.0);
+
+        // Amounts we can put on the book without exceeding the max absolute position size
+        let mut lower_capacity = (self.max_absolute_position_size - self.cur_position).max(0.0);
+        let mut upper_capacity = (self.max_absolute_position_size + self.cur_position).max(0.0);
+
+        let mut lower_quotes = Vec::with_capacity(self.levels);
+        let mut upper_quotes = Vec::with_capacity(self.levels);
+        for level in 0..self.levels {
+            let half_spread_bps =
+                self.half_spread as f64 + (level as f64 * self.level_spacing as f64);
+            let half_spread = (center * half_spread_bps) / 10000.0;
+            let (lower_price, upper_price) = (center - half_spread, center + half_spread);
+            let (mut lower_rounded, mut upper_rounded) = (
+                self.round_price(lower_price, true),
+                self.round_price(upper_price, false),
+            );
+
+            // Rounding optimistically to make our market tighter might cause a weird edge case, so account for that
+            if (lower_rounded - upper_rounded).abs() < EPSILON {
+                lower_rounded = self.round_price(lower_price, false);
+                upper_rounded = self.round_price(upper_price, true);
+            }
+
+            // Levels far enough out for the price to reach 0 are left empty
+            let size = self.target_liquidity * self.size_multiplier.powi(level as i32);
+            let side_size = |price: f64, capacity: f64| {
+                if price > 0.0 {
+                    truncate_float(size.min(capacity) + EPSILON, self.sz_decimals, false)
+                } else {
+                    0.0
                 }
-            },
-            Err(e) => error!("Error with placing order: {e}"),
+            };
+            let lower_size = side_size(lower_rounded, lower_capacity);
+            let upper_size = side_size(upper_rounded, upper_capacity);
+            lower_capacity -= lower_size;
+            upper_capacity -= upper_size;
+
+            lower_quotes.push(Quote {
+                price: lower_rounded,
+                size: lower_size,
+            });
+            upper_quotes.push(Quote {
+                price: upper_rounded,
+                size: upper_size,
+            });
         }
-        (0.0, 0)
+        (lower_quotes, upper_quotes)
     }
 
-    async fn potentially_update(&mut self) {
-        let half_spread = (self.latest_mid_price * self.half_spread as f64) / 10000.0;
-        // Determine prices to target from the half spread
-        let (lower_price, upper_price) = (
-            self.latest_mid_price - half_spread,
-            self.latest_mid_price + half_spread,
-        );
-        let (mut lower_price, mut upper_price) = (
-            truncate_float(lower_price, self.decimals, true),
-            truncate_float(upper_price, self.decimals, false),
-        );
-
-        // Rounding optimistically to make our market tighter might cause a weird edge case, so account for that
-        if (lower_price - upper_price).abs() < EPSILON {
-            lower_price = truncate_float(lower_price, self.decimals, false);
-            upper_price = truncate_float(upper_price, self.decimals, true);
+    fn limit_order(&self, is_buy: bool, quote: Quote) -> ClientOrderRequest {
+        ClientOrderRequest {
+            asset: self.asset.clone(),
+            is_buy,
+            reduce_only: false,
+            limit_px: quote.price,
+            sz: quote.size,
+            cloid: None,
+            order_type: ClientOrder::Limit(ClientLimit {
+                tif: "Gtc".to_string(),
+            }),
         }
+    }
 
-        // Determine amounts we can put on the book without exceeding the max absolute position size
-        let lower_order_amount = (self.max_absolute_position_size - self.cur_position)
-            .min(self.target_liquidity)
-            .max(0.0);
-
-        let upper_order_amount = (self.max_absolute_position_size + self.cur_position)
-            .min(self.target_liquidity)
-            .max(0.0);
-
-        // Determine if we need to cancel the resting order and put a new order up due to deviation
-        let lower_change = (lower_order_amount - self.lower_resting.position).abs() > EPSILON
-            || bps_diff(lower_price, self.lower_resting.price) > self.max_bps_diff;
-        let upper_change = (upper_order_amount - self.upper_resting.position).abs() > EPSILON
-            || bps_diff(upper_price, self.upper_resting.price) > self.max_bps_diff;
-
-        // Consider cancelling
-        // TODO: Don't block on cancels
-        if self.lower_resting.oid != 0 && self.lower_resting.position > EPSILON && lower_change {
-            let cancel = self
-                .attempt_cancel(self.asset.clone(), self.lower_resting.oid)
-                .await;
-            // If we were unable to cancel, it means we got a fill, so wait until we receive that event to do anything
-            if !cancel {
-                return;
+    // Records the outcome of placing `quote` in `slot`, either as a new order or a modify
+    fn record_placement(&mut self, slot: Slot, quote: Quote, status: Option<ExchangeDataStatus>) {
+        let side = if slot.0 { "Buy" } else { "Sell" };
+        let asset = self.asset.clone();
+        let resting = self.resting_mut(slot);
+        match status {
+            Some(ExchangeDataStatus::Resting(order)) => {
+                *resting = MarketMakerRestingOrder {
+                    oid: order.oid,
+                    position: quote.size,
+                    price: quote.price,
+                };
+                info!(
+                    "{side} for {} {asset} resting at {}",
+                    quote.size, quote.price
+                );
             }
-            info!("Cancelled buy order: {:?}", self.lower_resting);
+            // Crossed the book, so the fill event updates our position
+            Some(ExchangeDataStatus::Filled(_)) => *resting = MarketMakerRestingOrder::empty(),
+            // A modified order that can't be found was most likely filled, which we see as a fill event
+            Some(ExchangeDataStatus::Error(e)) => error!("Error with placing {side} order: {e}"),
+            Some(status) => error!("Unexpected order status: {status:?}"),
+            None => {}
         }
+    }
+
+    async fn potentially_update(&mut self, exchange_client: &ExchangeClient) {
+        let (lower_quotes, upper_quotes) = self.quotes();
 
-        if self.upper_resting.oid != 0 && self.upper_resting.position > EPSILON && upper_change {
-            let cancel = self
-                .attempt_cancel(self.asset.clone(), self.upper_resting.oid)
-                .await;
-            if !cancel {
-                return;
+        let mut cancels: Vec<(Slot, u64)> = Vec::new();
+        let mut modifies: Vec<(Slot, Quote, u64)> = Vec::new();
+        let mut orders: Vec<(Slot, Quote)> = Vec::new();
+        for (is_buy, quotes) in [(true, lower_quotes), (false, upper_quotes)] {
+            for (level, quote) in quotes.into_iter().enumerate() {
+                let resting = if is_buy {
+                    &self.lower_resting[level]
+                } else {
+                    &self.upper_resting[level]
+                };
+                // Determine if we need to update the order due to deviation
+                let change = (quote.size - resting.position).abs() > EPSILON
+                    || bps_diff(quote.price, resting.price) > self.max_bps_diff;
+                if !change {
+                    continue;
+                }
+                let slot = (is_buy, level);
+                match (resting.is_resting(), quote.size > EPSILON) {
+                    (true, true) => modifies.push((slot, quote, resting.oid)),
+                    (true, false) => cancels.push((slot, resting.oid)),
+                    (false, true) => orders.push((slot, quote)),
+                    (false, false) => {}
+                }
             }
-            info!("Cancelled sell order: {:?}", self.upper_resting);
         }
 
-        // Consider putting a new order up
-        if lower_order_amount > EPSILON && lower_change {
-            let (amount_resting, oid) = self
-                .place_order(self.asset.clone(), lower_order_amount, lower_price, true)
-                .await;
+        if !cancels.is_empty() {
+            let requests = cancels
+                .iter()
+                .map(|&(_, oid)| ClientCancelRequest {
+                    asset: self.asset.clone(),
+                    oid,
+                })
+                .collect();
+            let response = exchange_client.bulk_cancel(requests, None).await;
+            let statuses = bulk_statuses("cancelling", response, cancels.len());
+            for ((slot, oid), status) in cancels.into_iter().zip(statuses) {
+                match status {
+                    Some(ExchangeDataStatus::Success) => {
+                        info!("Cancelled order {oid}");
+                        *self.resting_mut(slot) = MarketMakerRestingOrder::empty();
+                    }
+                    // If we were unable to cancel, it means we got a fill, so wait until we receive that event
+                    Some(ExchangeDataStatus::Error(e)) => error!("Error with cancelling: {e}"),
+                    Some(status) => error!("Unexpected cancel status: {status:?}"),
+                    None => {}
+                }
+            }
+        }
 
-            self.lower_resting.oid = oid;
-            self.lower_resting.position = amount_resting;
-            self.lower_resting.price = lower_price;
+        // Modifying keeps the number of messages down compared to cancelling and placing again
+        if !modifies.is_empty() {
+            let requests = modifies
+                .iter()
+                .map(|&((is_buy, _), quote, oid)| ClientModifyRequest {
+                    oid,
+                    order: self.limit_order(is_buy, quote),
+                })
+                .collect();
+            let response = exchange_client.bulk_modify(requests, None).await;
+            let statuses = bulk_statuses("modifying orders", response, modifies.len());
+            for ((slot, quote, _), status) in modifies.into_iter().zip(statuses) {
+                self.record_placement(slot, quote, status);
+            }
+        }
 
-            if amount_resting > EPSILON {
-                info!(
-                    "Buy for {amount_resting} {} resting at {lower_price}",
-                    self.asset.clone()
-                );
+        if !orders.is_empty() {
+            let requests = orders
+                .iter()
+                .map(|&((is_buy, _), quote)| self.limit_order(is_buy, quote))
+                .collect();
+            let response = exchange_client.bulk_order(requests, None).await;
+            let statuses = bulk_statuses("placing orders", response, orders.len());
+            for ((slot, quote), status) in orders.into_iter().zip(statuses) {
+                self.record_placement(slot, quote, status);
             }
         }
+    }
+}
 
-        if upper_order_amount > EPSILON && upper_change {
-            let (amount_resting, oid) = self
-                .place_order(self.asset.clone(), upper_order_amount, upper_price, false)
-                .await;
-            self.upper_resting.oid = oid;
-            self.upper_resting.position = amount_resting;
-            self.upper_resting.price = upper_price;
+fn empty_levels(levels: usize) -> Vec<MarketMakerRestingOrder> {
+    (0..levels)
+        .map(|_| MarketMakerRestingOrder::empty())
+        .collect()
+}
 
-            if amount_resting > EPSILON {
-                info!(
-                    "Sell for {amount_resting} {} resting at {upper_price}",
-                    self.asset.clone()
-                );
+// Statuses of a bulk request in request order, `None` for requests the exchange didn't report on
+fn bulk_statuses(
+    action: &str,
+    response: Result<ExchangeResponseStatus>,
+    count: usize,
+) -> Vec<Option<ExchangeDataStatus>> {
+    let mut statuses: Vec<Option<ExchangeDataStatus>> = match response {
+        Ok(ExchangeResponseStatus::Ok(response)) => match response.data {
+            Some(data) => data.statuses.into_iter().map(Some).collect(),
+            None => {
+                error!("Exchange response data is empty when {action}: {response:?}");
+                Vec::new()
             }
+        },
+        Ok(ExchangeResponseStatus::Err(e)) => {
+            error!("Error with {action}: {e}");
+            Vec::new()
+        }
+        Err(e) => {
+            error!("Error with {action}: {e}");
+            Vec::new()
+        }
+    };
+    statuses.resize(count, None);
+    statuses
+}
+
+impl Strategy for MarketMaker {
+    fn subscriptions(&self) -> Vec<Subscription> {
+        // Subscribe to AllMids so we can market make around the mid price
+        vec![Subscription::AllMids]
+    }
+
+    async fn on_mids(
+        &mut self,
+        mids: &HashMap<String, String>,
+        context: &StrategyContext,
+    ) -> Result<()> {
+        let Some(mid) = mids.get(&self.asset) else {
+            error!("could not get mid for asset {}: {mids:?}", self.asset);
+            return Ok(());
+        };
+        self.latest_mid_price = mid.parse().map_err(|_| Error::FloatStringParse)?;
+        if self.needs_resync {
+            self.resync(&context.exchange_client).await?;
         }
+        // Check to see if we need to modify, cancel or place any orders
+        self.potentially_update(&context.exchange_client).await;
+        Ok(())
+    }
+
+    async fn on_fill(&mut self, fill: &TradeInfo, context: &StrategyContext) -> Result<()> {
+        // Fills missed or seen while out of sync are covered by re-reading the position
+        if fill.coin != self.asset || self.needs_resync {
+            return Ok(());
+        }
+        let amount: f64 = fill.sz.parse().map_err(|_| Error::FloatStringParse)?;
+        // Update our resting positions whenever we see a fill
+        let resting = if fill.side.eq("B") {
+            self.cur_position += amount;
+            info!("Fill: bought {amount} {}", self.asset);
+            &mut self.lower_resting
+        } else {
+            self.cur_position -= amount;
+            info!("Fill: sold {amount} {}", self.asset);
+            &mut self.upper_resting
+        };
+        if let Some(resting) = resting.iter_mut().find(|resting| resting.oid == fill.oid) {
+            resting.position -= amount;
+        }
+
+        // We haven't seen the first mid price event yet, so wait for it before quoting
+        if self.latest_mid_price > 0.0 {
+            self.potentially_update(&context.exchange_client).await;
+        }
+        Ok(())
+    }
+
+    async fn on_disconnect(&mut self, context: &StrategyContext) -> Result<()> {
+        warn!("Disconnected, pulling quotes until resynced");
+        self.needs_resync = true;
+        self.cancel_quotes(&context.exchange_client).await
+    }
+
+    async fn on_shutdown(&mut self, context: &StrategyContext) -> Result<()> {
+        self.cancel_quotes(&context.exchange_client).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BaseUrl;
+
+    fn market_maker() -> MarketMaker {
+        let mut market_maker = MarketMaker::new(MarketMakerInput {
+            asset: "ETH".to_string(),
+            target_liquidity: 0.25,
+            half_spread: 1,
+            max_bps_diff: 2,
+            max_absolute_position_size: 1.0,
+            levels: 3,
+            level_spacing: 10,
+            size_multiplier: 2.0,
+            inventory_skew: 10,
+            owns_asset: true,
+            wallet: "e908f86dbb4d55ac876378565aafeabc187f6690f046459397b17d9b9a19688e"
+                .parse()
+                .unwrap(),
+            network: BaseUrl::Testnet.into(),
+        });
+        market_maker.decimals = 2;
+        market_maker.sz_decimals = 4;
+        market_maker
+    }
+
+    #[test]
+    fn test_round_price() {
+        let market_maker = market_maker();
+
+        assert_eq!(market_maker.round_price(2345.678, false), 2345.6);
+        assert_eq!(market_maker.round_price(2345.678, true), 2345.7);
+        assert_eq!(market_maker.round_price(123456.7, false), 123456.0);
+        assert_eq!(market_maker.round_price(1.23456, false), 1.23);
+        assert_eq!(market_maker.round_price(0.0, true), 0.0);
+        assert_eq!(market_maker.round_price(-5.0, false), 0.0);
+    }
+
+    #[test]
+    fn test_quote_ladder() {
+        let mut market_maker = market_maker();
+        market_maker.latest_mid_price = 2000.0;
+
+        let (lower, upper) = market_maker.quotes();
+        let prices = |quotes: &[Quote]| quotes.iter().map(|quote| quote.price).collect::<Vec<_>>();
+        let sizes = |quotes: &[Quote]| quotes.iter().map(|quote| quote.size).collect::<Vec<_>>();
+        assert_eq!(prices(&lower), [1999.9, 1997.9, 1995.9]);
+        assert_eq!(prices(&upper), [2000.2, 2002.2, 2004.2]);
+        assert_eq!(sizes(&lower), [0.25, 0.5, 0.25]);
+        assert_eq!(sizes(&upper), [0.25, 0.5, 0.25]);
+
+        // Long half the max position: quotes move down and bids are capped by the position limit
+        market_maker.cur_position = 0.5;
+        let (lower, upper) = market_maker.quotes();
+        assert_eq!(prices(&lower), [1998.9, 1996.9, 1994.9]);
+        assert_eq!(prices(&upper), [1999.1, 2001.1, 2003.1]);
+        assert_eq!(sizes(&lower), [0.25, 0.25, 0.0]);
+        assert_eq!(sizes(&upper), [0.25, 0.5, 0.75]);
+
+        // Bids that would be at or below 0 are not quoted
+        market_maker.cur_position = 0.0;
+        market_maker.level_spacing = 6000;
+        let (lower, upper) = market_maker.quotes();
+        assert_eq!(prices(&lower), [1999.9, 799.81, 0.0]);
+        assert_eq!(sizes(&lower), [0.25, 0.5, 0.0]);
+        assert_eq!(sizes(&upper), [0.25, 0.5, 0.25]);
+    }
+
+    #[test]
+    fn test_adopt_open_orders() -> Result<()> {
+        let mut market_maker = market_maker();
+        market_maker.latest_mid_price = 2000.0;
+        let order = |oid: u64, side: &str, px: &str| -> OpenOrdersResponse {
+            serde_json::from_value(serde_json::json!({
+                "coin": "ETH", "limitPx": px, "oid": oid, "side": side, "sz": "0.25",
+                "timestamp": 0
+            }))
+            .unwrap()
+        };
+
+        let unmatched = market_maker.adopt(&[
+            order(1, "B", "1997.9"),
+            order(2, "B", "1999.9"),
+            order(3, "A", "2500"),
+            order(4, "B", "1999.8"),
+        ])?;
+        assert_eq!(market_maker.lower_resting[0].oid, 2);
+        assert_eq!(market_maker.lower_resting[1].oid, 1);
+        assert!(!market_maker.lower_resting[2].is_resting());
+        assert!(market_maker
+            .upper_resting
+            .iter()
+            .all(|resting| !resting.is_resting()));
+        assert_eq!(unmatched, [3, 4]);
+        Ok(())
     }
 }
@@ -0,0 +1,497 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+use log::{error, info, warn};
+use tokio::{
+    spawn,
+    sync::{broadcast, mpsc::unbounded_channel, Mutex},
+    task::JoinHandle,
+    time,
+};
+
+use crate::{
+    meta::{Meta, SpotMeta},
+    prelude::*,
+    Error, InfoClient, Message, NetworkConfig, Subscription,
+};
+
+/// Perp and spot metadata together with the coin to asset index mapping derived from them.
+#[derive(Debug, Clone)]
+pub struct AssetMetadata {
+    pub meta: Meta,
+    pub spot_meta: SpotMeta,
+    pub coin_to_asset: HashMap<String, u32>,
+}
+
+impl AssetMetadata {
+    pub fn new(meta: Meta, spot_meta: SpotMeta) -> AssetMetadata {
+        let mut coin_to_asset = HashMap::new();
+        for (asset_ind, asset) in meta.universe.iter().enumerate() {
+            coin_to_asset.insert(asset.name.clone(), asset_ind as u32);
+        }
+        let coin_to_asset = spot_meta.add_pair_and_name_to_index_map(coin_to_asset);
+
+        AssetMetadata {
+            meta,
+            spot_meta,
+            coin_to_asset,
+        }
+    }
+
+    pub fn asset_index(&self, coin: &str) -> Option<u32> {
+        self.coin_to_asset.get(coin).copied()
+    }
+
+    /// Coin name of an asset index as used in websocket feeds, e.g. `ETH` or `@107`.
+    pub fn coin(&self, asset: u32) -> Option<&str> {
+        if asset < 10000 {
+            return self
+                .meta
+                .universe
+                .get(asset as usize)
+                .map(|asset_meta| asset_meta.name.as_str());
+        }
+        self.spot_meta
+            .universe
+            .iter()
+            .find(|pair| pair.index as u32 == asset - 10000)
+            .map(|pair| pair.name.as_str())
+    }
+
+    /// Coins that can currently be traded, i.e. excluding delisted perps.
+    fn active_coins(&self) -> HashMap<&str, u32> {
+        self.coin_to_asset
+            .iter()
+            .filter(|(_, &asset)| {
+                asset >= 10000
+                    || !self
+                        .meta
+                        .universe
+                        .get(asset as usize)
+                        .is_some_and(|asset_meta| asset_meta.is_delisted)
+            })
+            .map(|(coin, &asset)| (coin.as_str(), asset))
+            .collect()
+    }
+
+    /// Listings and delistings between `self` and a newer snapshot.
+    ///
+    /// Spot pairs are reported under every name they can be looked up by.
+    pub fn changes_to(&self, newer: &AssetMetadata) -> Vec<MetadataEvent> {
+        let old = self.active_coins();
+        let new = newer.active_coins();
+
+        let mut events: Vec<MetadataEvent> =
+            new.iter()
+                .filter(|(coin, _)| !old.contains_key(*coin))
+                .map(|(coin, &asset)| MetadataEvent::Listed {
+                    coin: coin.to_string(),
+                    asset,
+                })
+                .chain(old.iter().filter(|(coin, _)| !new.contains_key(*coin)).map(
+                    |(coin, &asset)| MetadataEvent::Delisted {
+                        coin: coin.to_string(),
+                        asset,
+                    },
+                ))
+                .collect();
+        events.sort_by_key(|event| match event {
+            MetadataEvent::Listed { coin, asset } | MetadataEvent::Delisted { coin, asset } => {
+                (*asset, coin.clone())
+            }
+        });
+        events
+    }
+
+    /// Size decimals of a perp, or of the base token of a spot pair.
+    pub fn sz_decimals(&self, coin: &str) -> Option<u32> {
+        let asset = self.asset_index(coin)?;
+        if asset < 10000 {
+            return self
+                .meta
+                .universe
+                .get(asset as usize)
+                .map(|asset_meta| asset_meta.sz_decimals);
+        }
+
+        let pair = self
+            .spot_meta
+            .universe
+            .iter()
+            .find(|pair| pair.index as u32 == asset - 10000)?;
+        self.spot_meta
+            .tokens
+            .iter()
+            .find(|token| token.index == pair.tokens[0])
+            .map(|token| token.sz_decimals.into())
+    }
+}
+
+/// Change in the tradable assets noticed while refreshing metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataEvent {
+    Listed { coin: String, asset: u32 },
+    Delisted { coin: String, asset: u32 },
+}
+
+#[derive(Debug, Default)]
+struct Mids {
+    prices: HashMap<String, f64>,
+    updated_at: Option<Instant>,
+}
+
+/// Shared cache of exchange metadata and mid prices.
+///
+/// Metadata is re-fetched on [`MetadataCache::refresh`], periodically once
+/// [`MetadataCache::start_auto_refresh`] is running, and when a lookup through
+/// [`MetadataCache::ensure_coins`] misses. Each refresh swaps in a new snapshot atomically and
+/// announces listings and delistings to [`MetadataCache::subscribe`]rs.
+///
+/// Mids are served from the cache while younger than the configured maximum age and re-fetched
+/// over HTTP otherwise; [`MetadataCache::subscribe_mids`] keeps them current from the `allMids`
+/// channel.
+#[derive(Debug)]
+pub struct MetadataCache {
+    info_client: InfoClient,
+    assets: RwLock<Arc<AssetMetadata>>,
+    // Unset until the first refresh, so that misses right after creation can refresh
+    last_refresh: Mutex<Option<Instant>>,
+    events: broadcast::Sender<MetadataEvent>,
+    mids: RwLock<Mids>,
+    max_mid_age: Duration,
+}
+
+impl MetadataCache {
+    const DEFAULT_MAX_MID_AGE: Duration = Duration::from_secs(2);
+    // Bounds how often lookups of unknown coins (e.g. typos) can trigger a refresh
+    const MIN_MISS_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+    const EVENTS_CAPACITY: usize = 256;
+
+    /// Fetches metadata from `network`, using `meta` instead of fetching perp metadata if given.
+    pub async fn new(network: NetworkConfig, meta: Option<Meta>) -> Result<MetadataCache> {
+        let info_client = InfoClient::from_network_config(None, network, false).await?;
+        let meta = if let Some(meta) = meta {
+            meta
+        } else {
+            info_client.meta().await?
+        };
+        let spot_meta = info_client.spot_meta().await?;
+
+        Ok(Self::with_info_client(
+            info_client,
+            AssetMetadata::new(meta, spot_meta),
+        ))
+    }
+
+    /// Creates a cache from already known metadata without any network access.
+    pub async fn from_metadata(
+        network: NetworkConfig,
+        assets: AssetMetadata,
+    ) -> Result<MetadataCache> {
+        let info_client = InfoClient::from_network_config(None, network, false).await?;
+        Ok(Self::with_info_client(info_client, assets))
+    }
+
+    fn with_info_client(info_client: InfoClient, assets: AssetMetadata) -> MetadataCache {
+        let (events, _) = broadcast::channel(Self::EVENTS_CAPACITY);
+        MetadataCache {
+            info_client,
+            assets: RwLock::new(Arc::new(assets)),
+            last_refresh: Mutex::new(None),
+            events,
+            mids: RwLock::new(Mids::default()),
+            max_mid_age: Self::DEFAULT_MAX_MID_AGE,
+        }
+    }
+
+    /// Sets how old cached mids may be before they are re-fetched.
+    pub fn with_max_mid_age(mut self, max_mid_age: Duration) -> MetadataCache {
+        self.max_mid_age = max_mid_age;
+        self
+    }
+
+    pub fn info_client(&self) -> &InfoClient {
+        &self.info_client
+    }
+
+    /// The current metadata snapshot.
+    pub fn assets(&self) -> Arc<AssetMetadata> {
+        self.assets
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Receives listings and delistings found by subsequent refreshes.
+    pub fn subscribe(&self) -> broadcast::Receiver<MetadataEvent> {
+        self.events.subscribe()
+    }
+
+    /// Re-fetches perp and spot metadata and replaces the current snapshot.
+    pub async fn refresh(&self) -> Result<Arc<AssetMetadata>> {
+        let mut last_refresh = self.last_refresh.lock().await;
+        let assets = self.refresh_locked().await?;
+        *last_refresh = Some(Instant::now());
+        Ok(assets)
+    }
+
+    async fn refresh_locked(&self) -> Result<Arc<AssetMetadata>> {
+        let meta = self.info_client.meta().await?;
+        let spot_meta = self.info_client.spot_meta().await?;
+        let assets = Arc::new(AssetMetadata::new(meta, spot_meta));
+
+        let previous = std::mem::replace(
+            &mut *self.assets.write().unwrap_or_else(PoisonError::into_inner),
+            assets.clone(),
+        );
+        for event in previous.changes_to(&assets) {
+            info!("Metadata changed: {event:?}");
+            // Sending only fails when nobody is subscribed
+            let _ = self.events.send(event);
+        }
+        Ok(assets)
+    }
+
+    /// Makes sure every coin in `coins` is known, refreshing the metadata once if one is not.
+    ///
+    /// Coins that are still unknown afterwards are left for the caller to report.
+    pub async fn ensure_coins<'a>(
+        &self,
+        coins: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Arc<AssetMetadata>> {
+        let assets = self.assets();
+        let missing: Vec<&str> = coins
+            .into_iter()
+            .filter(|coin| assets.asset_index(coin).is_none())
+            .collect();
+        if missing.is_empty() {
+            return Ok(assets);
+        }
+
+        let mut last_refresh = self.last_refresh.lock().await;
+        // Another caller may have refreshed while we were waiting for the lock
+        let assets = self.assets();
+        if missing
+            .iter()
+            .all(|coin| assets.asset_index(coin).is_some())
+            || last_refresh.is_some_and(|at| at.elapsed() < Self::MIN_MISS_REFRESH_INTERVAL)
+        {
+            return Ok(assets);
+        }
+
+        info!("Refreshing metadata for unknown coins {missing:?}");
+        let assets = self.refresh_locked().await?;
+        *last_refresh = Some(Instant::now());
+        Ok(assets)
+    }
+
+    /// Refreshes the metadata every `interval` until the cache is dropped.
+    pub fn start_auto_refresh(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let cache = Arc::downgrade(self);
+        spawn(async move {
+            let mut interval = time::interval(interval);
+            // The first tick completes immediately and the metadata was just fetched
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(cache) = cache.upgrade() else {
+                    break;
+                };
+                if let Err(err) = cache.refresh().await {
+                    error!("Error refreshing metadata: {err}");
+                }
+            }
+        })
+    }
+
+    /// Re-fetches all mids over HTTP.
+    pub async fn refresh_mids(&self) -> Result<()> {
+        let mids = self.info_client.all_mids().await?;
+        self.update_mids(&mids);
+        Ok(())
+    }
+
+    /// Updates cached mids, e.g. from an `allMids` websocket message.
+    pub fn update_mids(&self, mids: &HashMap<String, String>) {
+        let mut cached = self.mids.write().unwrap_or_else(PoisonError::into_inner);
+        for (coin, mid) in mids {
+            match mid.parse::<f64>() {
+                Ok(mid) => {
+                    cached.prices.insert(coin.clone(), mid);
+                }
+                Err(_) => warn!("Could not parse mid for {coin}: {mid}"),
+            }
+        }
+        cached.updated_at = Some(Instant::now());
+    }
+
+    /// Cached mid of `coin` regardless of its age.
+    pub fn cached_mid(&self, coin: &str) -> Option<f64> {
+        self.mids
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .prices
+            .get(coin)
+            .copied()
+    }
+
+    /// Mid of `coin`, re-fetching all mids if the cached ones are too old.
+    pub async fn mid(&self, coin: &str) -> Result<f64> {
+        if !self.mids_are_fresh() {
+            self.refresh_mids().await?;
+        }
+        self.cached_mid(coin).ok_or(Error::AssetNotFound)
+    }
+
+    fn mids_are_fresh(&self) -> bool {
+        self.mids
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .updated_at
+            .is_some_and(|updated_at| updated_at.elapsed() <= self.max_mid_age)
+    }
+
+    fn expire_mids(&self) {
+        self.mids
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .updated_at = None;
+    }
+
+    /// Keeps mids up to date from the `allMids` channel of `info_client`.
+    ///
+    /// Returns the subscription id; unsubscribing stops the updates. On disconnect the cached
+    /// mids are expired so lookups fall back to HTTP until the stream resumes.
+    pub async fn subscribe_mids(self: &Arc<Self>, info_client: &mut InfoClient) -> Result<u32> {
+        let (sender, mut receiver) = unbounded_channel();
+        let subscription_id = info_client.subscribe(Subscription::AllMids, sender).await?;
+
+        let cache = Arc::clone(self);
+        spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                match message {
+                    Message::AllMids(all_mids) => cache.update_mids(&all_mids.data.mids),
+                    Message::NoData => cache.expire_mids(),
+                    _ => {}
+                }
+            }
+        });
+
+        Ok(subscription_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BaseUrl;
+
+    fn test_assets() -> AssetMetadata {
+        let meta: Meta = serde_json::from_str(
+            r#"{"universe": [
+                {"name": "BTC", "szDecimals": 5, "maxLeverage": 50},
+                {"name": "ETH", "szDecimals": 4, "maxLeverage": 50}
+            ]}"#,
+        )
+        .unwrap();
+        let spot_meta: SpotMeta = serde_json::from_str(
+            r#"{
+                "universe": [
+                    {"tokens": [1, 0], "name": "PURR/USDC", "index": 0, "isCanonical": true},
+                    {"tokens": [2, 0], "name": "@1", "index": 1, "isCanonical": false}
+                ],
+                "tokens": [
+                    {"name": "USDC", "szDecimals": 8, "weiDecimals": 8, "index": 0,
+                     "tokenId": "0x6d1e7cde53ba9467b783cb7c530ce054", "isCanonical": true},
+                    {"name": "PURR", "szDecimals": 0, "weiDecimals": 5, "index": 1,
+                     "tokenId": "0xc1fb593aeffbeb02f85e0308e9956a90", "isCanonical": true},
+                    {"name": "HFUN", "szDecimals": 2, "weiDecimals": 8, "index": 2,
+                     "tokenId": "0xbaf265ef389da684513d98d68edf4eae", "isCanonical": false}
+                ]
+            }"#,
+        )
+        .unwrap();
+        AssetMetadata::new(meta, spot_meta)
+    }
+
+    #[test]
+    fn test_asset_metadata() {
+        let assets = test_assets();
+
+        assert_eq!(assets.asset_index("ETH"), Some(1));
+        assert_eq!(assets.asset_index("PURR/USDC"), Some(10000));
+        assert_eq!(assets.asset_index("HFUN/USDC"), Some(10001));
+        assert_eq!(assets.asset_index("@1"), Some(10001));
+        assert_eq!(assets.asset_index("DOGE"), None);
+
+        assert_eq!(assets.sz_decimals("BTC"), Some(5));
+        assert_eq!(assets.sz_decimals("PURR/USDC"), Some(0));
+        assert_eq!(assets.sz_decimals("@1"), Some(2));
+
+        assert_eq!(assets.coin(1), Some("ETH"));
+        assert_eq!(assets.coin(10001), Some("@1"));
+        assert_eq!(assets.coin(2), None);
+    }
+
+    #[test]
+    fn test_listing_changes() {
+        let assets = test_assets();
+        assert!(assets.changes_to(&assets).is_empty());
+
+        let mut meta = assets.meta.clone();
+        meta.universe[0].is_delisted = true;
+        meta.universe.push(
+            serde_json::from_str(r#"{"name": "SOL", "szDecimals": 2, "maxLeverage": 20}"#).unwrap(),
+        );
+        let mut spot_meta = assets.spot_meta.clone();
+        spot_meta.universe.pop();
+        let newer = AssetMetadata::new(meta, spot_meta);
+
+        assert_eq!(
+            assets.changes_to(&newer),
+            vec![
+                MetadataEvent::Delisted {
+                    coin: "BTC".to_string(),
+                    asset: 0
+                },
+                MetadataEvent::Listed {
+                    coin: "SOL".to_string(),
+                    asset: 2
+                },
+                MetadataEvent::Delisted {
+                    coin: "@1".to_string(),
+                    asset: 10001
+                },
+                MetadataEvent::Delisted {
+                    coin: "HFUN/USDC".to_string(),
+                    asset: 10001
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cached_mids() -> Result<()> {
+        let cache = MetadataCache::from_metadata(BaseUrl::Localhost.into(), test_assets())
+            .await?
+            .with_max_mid_age(Duration::from_secs(60));
+        assert!(!cache.mids_are_fresh());
+        assert_eq!(cache.cached_mid("ETH"), None);
+
+        cache.update_mids(&HashMap::from([
+            ("ETH".to_string(), "1800.5".to_string()),
+            ("BTC".to_string(), "not a number".to_string()),
+        ]));
+        assert!(cache.mids_are_fresh());
+        assert_eq!(cache.mid("ETH").await?, 1800.5);
+        assert_eq!(cache.cached_mid("BTC"), None);
+
+        cache.expire_mids();
+        assert!(!cache.mids_are_fresh());
+        assert_eq!(cache.cached_mid("ETH"), Some(1800.5));
+        Ok(())
+    }
+}
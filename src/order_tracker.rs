@@ -0,0 +1,546 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{
+    prelude::*, ClientOrderRequest, Cloid, Error, ExchangeDataStatus, ExchangeResponseStatus,
+    Message, OrderUpdate, TradeInfo, UserData, EPSILON,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderState {
+    /// Submitted, no response or update seen yet
+    Pending,
+    Resting,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Rejected,
+}
+
+impl OrderState {
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self,
+            OrderState::Pending | OrderState::Resting | OrderState::PartiallyFilled
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackedOrder {
+    /// Local id assigned by the [`OrderTracker`]
+    pub id: u64,
+    pub coin: String,
+    pub is_buy: bool,
+    pub limit_px: f64,
+    pub sz: f64,
+    pub oid: Option<u64>,
+    pub cloid: Option<Cloid>,
+    pub state: OrderState,
+    pub filled_sz: f64,
+    /// Average fill price, 0 until something is filled
+    pub avg_px: f64,
+    /// Set when the order was rejected
+    pub error: Option<String>,
+    fills_sz: f64,
+    fills_notional: f64,
+}
+
+impl TrackedOrder {
+    pub fn remaining_sz(&self) -> f64 {
+        (self.sz - self.filled_sz).max(0.0)
+    }
+
+    fn record_fill(&mut self, px: f64, sz: f64) {
+        self.fills_sz += sz;
+        self.fills_notional += px * sz;
+        // The placement response may already have reported a larger fill
+        if self.fills_sz > self.filled_sz - EPSILON {
+            self.filled_sz = self.fills_sz;
+            self.avg_px = self.fills_notional / self.fills_sz;
+        }
+        self.update_fill_state();
+    }
+
+    fn record_reported_fill(&mut self, total_sz: f64, avg_px: f64) {
+        if total_sz > self.filled_sz {
+            self.filled_sz = total_sz;
+            self.avg_px = avg_px;
+        }
+        self.update_fill_state();
+    }
+
+    fn update_fill_state(&mut self) {
+        if !self.state.is_open() && self.state != OrderState::Filled {
+            return;
+        }
+        self.state = if self.filled_sz >= self.sz - EPSILON {
+            OrderState::Filled
+        } else if self.filled_sz > EPSILON {
+            OrderState::PartiallyFilled
+        } else {
+            self.state
+        };
+    }
+}
+
+/// Tracks the live state of submitted orders from placement responses, order updates and fills.
+///
+/// Orders are keyed by a local id and can be looked up by oid once the exchange assigned one, or
+/// by cloid. Order updates for orders that weren't placed through the tracker are adopted, and
+/// fills that arrive before the placement response are held until the oid is known.
+///
+/// Only the most recent [`OrderTracker::MAX_SEEN_FILLS`] fills are remembered for
+/// deduplication, and fills are held for at most [`OrderTracker::MAX_UNMATCHED_OIDS`] unknown
+/// oids, oldest first out, so fills of orders placed elsewhere don't accumulate.
+#[derive(Debug, Default)]
+pub struct OrderTracker {
+    orders: HashMap<u64, TrackedOrder>,
+    by_oid: HashMap<u64, u64>,
+    by_cloid: HashMap<Cloid, u64>,
+    // Fills by trade id and oid, as both sides of a self-trade share the trade id
+    seen_fills: HashSet<(u64, u64)>,
+    // Insertion order of `seen_fills` and `unmatched_fills`, for evicting the oldest entries
+    seen_fills_order: VecDeque<(u64, u64)>,
+    unmatched_fills: HashMap<u64, Vec<TradeInfo>>,
+    unmatched_order: VecDeque<u64>,
+    next_id: u64,
+}
+
+impl OrderTracker {
+    pub const MAX_SEEN_FILLS: usize = 10_000;
+    pub const MAX_UNMATCHED_OIDS: usize = 1_000;
+
+    pub fn new() -> OrderTracker {
+        OrderTracker::default()
+    }
+
+    /// Records an order about to be submitted and returns its local id.
+    pub fn track(&mut self, order: &ClientOrderRequest) -> u64 {
+        let id = self.insert(
+            order.asset.clone(),
+            order.is_buy,
+            order.limit_px,
+            order.sz,
+            order.cloid,
+        );
+        if let Some(cloid) = order.cloid {
+            self.by_cloid.insert(cloid, id);
+        }
+        id
+    }
+
+    /// Applies the response to a (bulk) order placement. `ids` are the local ids of the submitted
+    /// orders, in submission order.
+    pub fn on_order_response(
+        &mut self,
+        ids: &[u64],
+        response: &ExchangeResponseStatus,
+    ) -> Result<()> {
+        let statuses = match response {
+            ExchangeResponseStatus::Ok(response) => response
+                .data
+                .as_ref()
+                .map(|data| data.statuses.as_slice())
+                .unwrap_or_default(),
+            ExchangeResponseStatus::Err(e) => {
+                for id in ids {
+                    self.reject(*id, e);
+                }
+                return Ok(());
+            }
+        };
+
+        for (id, status) in ids.iter().zip(statuses) {
+            match status {
+                ExchangeDataStatus::Resting(resting) => {
+                    self.assign_oid(*id, resting.oid)?;
+                    if let Some(order) = self.orders.get_mut(id) {
+                        if order.state == OrderState::Pending {
+                            order.state = OrderState::Resting;
+                        }
+                    }
+                }
+                ExchangeDataStatus::Filled(filled) => {
+                    self.assign_oid(*id, filled.oid)?;
+                    let total_sz = parse(&filled.total_sz)?;
+                    let avg_px = parse(&filled.avg_px)?;
+                    if let Some(order) = self.orders.get_mut(id) {
+                        order.record_reported_fill(total_sz, avg_px);
+                    }
+                }
+                ExchangeDataStatus::Error(e) => self.reject(*id, e),
+                ExchangeDataStatus::WaitingForFill | ExchangeDataStatus::WaitingForTrigger => {
+                    if let Some(order) = self.orders.get_mut(id) {
+                        order.state = OrderState::Resting;
+                    }
+                }
+                ExchangeDataStatus::Success => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies `OrderUpdates` and fill messages; other messages are ignored.
+    pub fn on_message(&mut self, message: &Message) -> Result<()> {
+        match message {
+            Message::OrderUpdates(updates) => {
+                for update in &updates.data {
+                    self.apply_order_update(update)?;
+                }
+            }
+            Message::UserFills(fills) => {
+                for fill in &fills.data.fills {
+                    self.apply_fill(fill)?;
+                }
+            }
+            Message::User(user_events) => {
+                if let UserData::Fills(fills) = &user_events.data {
+                    for fill in fills {
+                        self.apply_fill(fill)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn apply_order_update(&mut self, update: &OrderUpdate) -> Result<()> {
+        let basic = &update.order;
+        let id = match self.lookup(basic.oid, basic.cloid.as_ref()) {
+            Some(id) => id,
+            None => {
+                let id = self.insert(
+                    basic.coin.clone(),
+                    basic.side == "B",
+                    parse(&basic.limit_px)?,
+                    parse(&basic.orig_sz)?,
+                    basic.cloid,
+                );
+                if let Some(cloid) = basic.cloid {
+                    self.by_cloid.insert(cloid, id);
+                }
+                id
+            }
+        };
+        self.assign_oid(id, basic.oid)?;
+
+        if let Some(order) = self.orders.get_mut(&id) {
+            let status = update.status.as_str();
+            order.state = if status == "filled" {
+                OrderState::Filled
+            } else if (status == "open" || status == "triggered") && order.state.is_open() {
+                if order.filled_sz > EPSILON {
+                    OrderState::PartiallyFilled
+                } else {
+                    OrderState::Resting
+                }
+            } else if status.to_lowercase().ends_with("canceled") {
+                OrderState::Canceled
+            } else if status.to_lowercase().ends_with("rejected") {
+                order.error = Some(status.to_string());
+                OrderState::Rejected
+            } else {
+                order.state
+            };
+        }
+        Ok(())
+    }
+
+    /// Applies a fill; fills already seen (by trade id and oid) are ignored.
+    pub fn apply_fill(&mut self, fill: &TradeInfo) -> Result<()> {
+        let key = (fill.tid, fill.oid);
+        if self.seen_fills.contains(&key) {
+            return Ok(());
+        }
+        let Some(id) = self.lookup(fill.oid, fill.cloid.as_ref()) else {
+            if !self.unmatched_fills.contains_key(&fill.oid) {
+                self.unmatched_order.push_back(fill.oid);
+                if self.unmatched_order.len() > Self::MAX_UNMATCHED_OIDS {
+                    if let Some(oldest) = self.unmatched_order.pop_front() {
+                        self.unmatched_fills.remove(&oldest);
+                    }
+                }
+            }
+            self.unmatched_fills
+                .entry(fill.oid)
+                .or_default()
+                .push(fill.clone());
+            return Ok(());
+        };
+
+        let px = parse(&fill.px)?;
+        let sz = parse(&fill.sz)?;
+        if self.seen_fills.insert(key) {
+            self.seen_fills_order.push_back(key);
+            if self.seen_fills_order.len() > Self::MAX_SEEN_FILLS {
+                if let Some(oldest) = self.seen_fills_order.pop_front() {
+                    self.seen_fills.remove(&oldest);
+                }
+            }
+        }
+        if let Some(order) = self.orders.get_mut(&id) {
+            order.record_fill(px, sz);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, id: u64) -> Option<&TrackedOrder> {
+        self.orders.get(&id)
+    }
+
+    pub fn get_by_oid(&self, oid: u64) -> Option<&TrackedOrder> {
+        self.by_oid.get(&oid).and_then(|id| self.orders.get(id))
+    }
+
+    pub fn get_by_cloid(&self, cloid: &Cloid) -> Option<&TrackedOrder> {
+        self.by_cloid.get(cloid).and_then(|id| self.orders.get(id))
+    }
+
+    pub fn open_orders(&self) -> impl Iterator<Item = &TrackedOrder> {
+        self.orders.values().filter(|order| order.state.is_open())
+    }
+
+    /// Forgets orders that are filled, canceled or rejected and returns them.
+    pub fn remove_closed(&mut self) -> Vec<TrackedOrder> {
+        let closed: Vec<u64> = self
+            .orders
+            .values()
+            .filter(|order| !order.state.is_open())
+            .map(|order| order.id)
+            .collect();
+        closed
+            .into_iter()
+            .filter_map(|id| self.remove(id))
+            .collect()
+    }
+
+    fn insert(
+        &mut self,
+        coin: String,
+        is_buy: bool,
+        limit_px: f64,
+        sz: f64,
+        cloid: Option<Cloid>,
+    ) -> u64 {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.orders.insert(
+            id,
+            TrackedOrder {
+                id,
+                coin,
+                is_buy,
+                limit_px,
+                sz,
+                oid: None,
+                cloid,
+                state: OrderState::Pending,
+                filled_sz: 0.0,
+                avg_px: 0.0,
+                error: None,
+                fills_sz: 0.0,
+                fills_notional: 0.0,
+            },
+        );
+        id
+    }
+
+    fn remove(&mut self, id: u64) -> Option<TrackedOrder> {
+        let order = self.orders.remove(&id)?;
+        if let Some(oid) = order.oid {
+            self.by_oid.remove(&oid);
+        }
+        if let Some(cloid) = order.cloid {
+            self.by_cloid.remove(&cloid);
+        }
+        Some(order)
+    }
+
+    fn lookup(&self, oid: u64, cloid: Option<&Cloid>) -> Option<u64> {
+        self.by_oid
+            .get(&oid)
+            .or_else(|| cloid.and_then(|cloid| self.by_cloid.get(cloid)))
+            .copied()
+    }
+
+    fn assign_oid(&mut self, id: u64, oid: u64) -> Result<()> {
+        // An update may have arrived before the placement response and been adopted as a
+        // separate order; fold its state into ours
+        if let Some(&other) = self.by_oid.get(&oid) {
+            if other != id {
+                if let (Some(adopted), Some(order)) = (self.remove(other), self.orders.get_mut(&id))
+                {
+                    order.state = adopted.state;
+                    order.fills_sz = adopted.fills_sz;
+                    order.fills_notional = adopted.fills_notional;
+                    order.filled_sz = adopted.filled_sz;
+                    order.avg_px = adopted.avg_px;
+                }
+            }
+        }
+
+        if let Some(order) = self.orders.get_mut(&id) {
+            order.oid = Some(oid);
+            self.by_oid.insert(oid, id);
+        }
+        for fill in self.unmatched_fills.remove(&oid).unwrap_or_default() {
+            self.apply_fill(&fill)?;
+        }
+        Ok(())
+    }
+
+    fn reject(&mut self, id: u64, error: &str) {
+        if let Some(order) = self.orders.get_mut(&id) {
+            order.state = OrderState::Rejected;
+            order.error = Some(error.to_string());
+        }
+    }
+}
+
+fn parse(value: &str) -> Result<f64> {
+    value.parse().map_err(|_| Error::FloatStringParse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientLimit, ClientOrder, ExchangeDataStatuses, ExchangeResponse, RestingOrder};
+
+    fn order_request(cloid: Option<Cloid>) -> ClientOrderRequest {
+        ClientOrderRequest {
+            asset: "ETH".to_string(),
+            is_buy: true,
+            reduce_only: false,
+            limit_px: 1800.0,
+            sz: 1.0,
+            cloid,
+            order_type: ClientOrder::Limit(ClientLimit {
+                tif: "Gtc".to_string(),
+            }),
+        }
+    }
+
+    fn resting_response(oid: u64) -> ExchangeResponseStatus {
+        ExchangeResponseStatus::Ok(ExchangeResponse {
+            response_type: "order".to_string(),
+            data: Some(ExchangeDataStatuses {
+                statuses: vec![ExchangeDataStatus::Resting(RestingOrder { oid })],
+            }),
+        })
+    }
+
+    fn fill(oid: u64, tid: u64, px: &str, sz: &str) -> TradeInfo {
+        serde_json::from_value(serde_json::json!({
+            "coin": "ETH", "side": "B", "px": px, "sz": sz, "time": 0, "hash": "0x",
+            "startPosition": "0", "dir": "Open Long", "closedPnl": "0", "oid": oid,
+            "cloid": null, "crossed": false, "fee": "0", "feeToken": "USDC", "tid": tid
+        }))
+        .unwrap()
+    }
+
+    fn order_update(oid: u64, status: &str, cloid: Option<Cloid>) -> OrderUpdate {
+        serde_json::from_value(serde_json::json!({
+            "order": {
+                "coin": "ETH", "side": "B", "limitPx": "1800.0", "sz": "1.0", "oid": oid,
+                "timestamp": 0, "origSz": "1.0", "cloid": cloid
+            },
+            "status": status,
+            "statusTimestamp": 0
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_order_lifecycle() -> Result<()> {
+        let mut tracker = OrderTracker::new();
+        let id = tracker.track(&order_request(None));
+        assert_eq!(tracker.get(id).unwrap().state, OrderState::Pending);
+
+        tracker.on_order_response(&[id], &resting_response(7))?;
+        assert_eq!(tracker.get_by_oid(7).unwrap().state, OrderState::Resting);
+
+        tracker.apply_fill(&fill(7, 1, "1800", "0.25"))?;
+        tracker.apply_fill(&fill(7, 1, "1800", "0.25"))?;
+        tracker.apply_fill(&fill(7, 2, "1790", "0.25"))?;
+        let order = tracker.get(id).unwrap();
+        assert_eq!(order.state, OrderState::PartiallyFilled);
+        assert!((order.filled_sz - 0.5).abs() < EPSILON);
+        assert!((order.avg_px - 1795.0).abs() < EPSILON);
+        assert!((order.remaining_sz() - 0.5).abs() < EPSILON);
+
+        tracker.apply_order_update(&order_update(7, "canceled", None))?;
+        assert_eq!(tracker.get(id).unwrap().state, OrderState::Canceled);
+        assert_eq!(tracker.open_orders().count(), 0);
+        assert_eq!(tracker.remove_closed().len(), 1);
+        assert!(tracker.get_by_oid(7).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_out_of_order_events() -> Result<()> {
+        let mut tracker = OrderTracker::new();
+        let cloid = Cloid::from_label("first");
+        let id = tracker.track(&order_request(Some(cloid)));
+        let other = tracker.track(&order_request(None));
+
+        // The update and fill for `other` arrive before its placement response
+        tracker.apply_order_update(&order_update(9, "open", None))?;
+        tracker.apply_fill(&fill(9, 3, "1800", "1.0"))?;
+        tracker.apply_order_update(&order_update(8, "open", Some(cloid)))?;
+        assert_eq!(tracker.get_by_cloid(&cloid).unwrap().oid, Some(8));
+
+        tracker.on_order_response(
+            &[id, other],
+            &ExchangeResponseStatus::Ok(ExchangeResponse {
+                response_type: "order".to_string(),
+                data: Some(ExchangeDataStatuses {
+                    statuses: vec![
+                        ExchangeDataStatus::Resting(RestingOrder { oid: 8 }),
+                        ExchangeDataStatus::Resting(RestingOrder { oid: 9 }),
+                    ],
+                }),
+            }),
+        )?;
+        let order = tracker.get(other).unwrap();
+        assert_eq!(order.state, OrderState::Filled);
+        assert!((order.filled_sz - 1.0).abs() < EPSILON);
+        assert_eq!(tracker.get_by_oid(9).unwrap().id, other);
+        assert_eq!(tracker.get(id).unwrap().state, OrderState::Resting);
+
+        // The other side of a self-trade shares the trade id
+        tracker.apply_fill(&fill(8, 3, "1800", "1.0"))?;
+        assert_eq!(tracker.get(id).unwrap().state, OrderState::Filled);
+
+        let rejected = tracker.track(&order_request(None));
+        tracker.on_order_response(
+            &[rejected],
+            &ExchangeResponseStatus::Err("margin".to_string()),
+        )?;
+        assert_eq!(tracker.get(rejected).unwrap().state, OrderState::Rejected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bounded_fill_history() -> Result<()> {
+        let mut tracker = OrderTracker::new();
+        // Fills of orders placed elsewhere are only held for the most recent oids
+        for oid in 0..OrderTracker::MAX_UNMATCHED_OIDS as u64 + 10 {
+            tracker.apply_fill(&fill(oid, oid, "1800", "0.1"))?;
+        }
+        assert_eq!(
+            tracker.unmatched_fills.len(),
+            OrderTracker::MAX_UNMATCHED_OIDS
+        );
+        assert!(!tracker.unmatched_fills.contains_key(&0));
+
+        let id = tracker.track(&order_request(None));
+        tracker.on_order_response(&[id], &resting_response(100_000))?;
+        for tid in 0..OrderTracker::MAX_SEEN_FILLS as u64 + 10 {
+            tracker.apply_fill(&fill(100_000, tid, "1800", "0.0001"))?;
+        }
+        assert_eq!(tracker.seen_fills.len(), OrderTracker::MAX_SEEN_FILLS);
+        assert_eq!(tracker.seen_fills_order.len(), OrderTracker::MAX_SEEN_FILLS);
+        Ok(())
+    }
+}
@@ -0,0 +1,491 @@
+use std::collections::HashMap;
+
+use crate::{prelude::*, Cloid, Error, FeeSchedule, L2BookData, Trade, UserFeesResponse, EPSILON};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeInForce {
+    Gtc,
+    Ioc,
+    /// Add liquidity only, i.e. post only
+    Alo,
+}
+
+impl TimeInForce {
+    pub fn parse(tif: &str) -> Result<TimeInForce> {
+        match tif {
+            "Gtc" => Ok(TimeInForce::Gtc),
+            "Ioc" => Ok(TimeInForce::Ioc),
+            "Alo" => Ok(TimeInForce::Alo),
+            _ => Err(Error::GenericParse(format!("Unknown time in force {tif}"))),
+        }
+    }
+}
+
+/// Maker and taker fee rates, e.g. `0.00015` for 1.5 bps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeRates {
+    pub maker: f64,
+    pub taker: f64,
+}
+
+impl Default for FeeRates {
+    /// Base tier perp fees.
+    fn default() -> FeeRates {
+        FeeRates {
+            maker: 0.00015,
+            taker: 0.00045,
+        }
+    }
+}
+
+impl FeeRates {
+    /// Rates of a VIP tier of the schedule, or of the base tier if `vip_tier` is `None`.
+    pub fn from_fee_schedule(schedule: &FeeSchedule, vip_tier: Option<usize>) -> Result<FeeRates> {
+        let (maker, taker) = match vip_tier {
+            Some(tier) => {
+                let tier = schedule.tiers.vip.get(tier).ok_or_else(|| {
+                    Error::GenericParse(format!("Fee schedule has no VIP tier {tier}"))
+                })?;
+                (&tier.add, &tier.cross)
+            }
+            None => (&schedule.add, &schedule.cross),
+        };
+        Ok(FeeRates {
+            maker: parse(maker)?,
+            taker: parse(taker)?,
+        })
+    }
+
+    /// The rates the user currently pays, as returned by [`crate::InfoClient::user_fees`].
+    pub fn from_user_fees(user_fees: &UserFeesResponse) -> Result<FeeRates> {
+        Ok(FeeRates {
+            maker: parse(&user_fees.user_add_rate)?,
+            taker: parse(&user_fees.user_cross_rate)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimOrder {
+    pub oid: u64,
+    pub coin: String,
+    pub is_buy: bool,
+    pub limit_px: f64,
+    /// Remaining size
+    pub sz: f64,
+    pub orig_sz: f64,
+    pub tif: TimeInForce,
+    pub cloid: Option<Cloid>,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SimFill {
+    pub oid: u64,
+    pub tid: u64,
+    pub coin: String,
+    pub is_buy: bool,
+    pub px: f64,
+    pub sz: f64,
+    pub fee: f64,
+    /// Whether the fill took liquidity
+    pub crossed: bool,
+    pub cloid: Option<Cloid>,
+    pub time: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlacementStatus {
+    /// At least partially resting on the book
+    Resting,
+    /// Fully filled, or an IOC order partially filled with the remainder canceled
+    Filled,
+    Rejected(String),
+}
+
+/// Result of [`MatchingEngine::place`].
+#[derive(Debug, Clone)]
+pub struct Placement {
+    pub order: SimOrder,
+    pub status: PlacementStatus,
+    pub fills: Vec<SimFill>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Book {
+    // Best level first
+    bids: Vec<(f64, f64)>,
+    asks: Vec<(f64, f64)>,
+}
+
+/// Simulates matching our orders against market data.
+///
+/// Aggressive orders walk the latest L2 snapshot, consuming its liquidity until the next snapshot
+/// arrives. Resting orders are filled at their limit price when a snapshot or trade crosses them.
+/// Queue position is not modeled, so resting orders fill as soon as the price is touched.
+#[derive(Debug)]
+pub struct MatchingEngine {
+    books: HashMap<String, Book>,
+    orders: HashMap<u64, SimOrder>,
+    fees: FeeRates,
+    next_tid: u64,
+    now: u64,
+}
+
+impl MatchingEngine {
+    pub fn new(fees: FeeRates) -> MatchingEngine {
+        MatchingEngine {
+            books: HashMap::new(),
+            orders: HashMap::new(),
+            fees,
+            next_tid: 0,
+            now: 0,
+        }
+    }
+
+    pub fn fees(&self) -> FeeRates {
+        self.fees
+    }
+
+    /// Time of the latest market event, in milliseconds.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Advances the clock; it never moves backwards.
+    pub fn set_time(&mut self, time: u64) {
+        self.now = self.now.max(time);
+    }
+
+    pub fn best_bid_ask(&self, coin: &str) -> (Option<f64>, Option<f64>) {
+        self.books
+            .get(coin)
+            .map(|book| {
+                (
+                    book.bids.first().map(|level| level.0),
+                    book.asks.first().map(|level| level.0),
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn mid(&self, coin: &str) -> Option<f64> {
+        match self.best_bid_ask(coin) {
+            (Some(bid), Some(ask)) => Some((bid + ask) / 2.0),
+            _ => None,
+        }
+    }
+
+    pub fn order(&self, oid: u64) -> Option<&SimOrder> {
+        self.orders.get(&oid)
+    }
+
+    pub fn open_orders(&self) -> impl Iterator<Item = &SimOrder> {
+        self.orders.values()
+    }
+
+    pub fn place(&mut self, mut order: SimOrder) -> Placement {
+        order.timestamp = self.now;
+        let book = self.books.entry(order.coin.clone()).or_default();
+        let levels = if order.is_buy {
+            &mut book.asks
+        } else {
+            &mut book.bids
+        };
+        let crosses = |px: f64| {
+            if order.is_buy {
+                px <= order.limit_px
+            } else {
+                px >= order.limit_px
+            }
+        };
+
+        if order.tif == TimeInForce::Alo && levels.first().is_some_and(|level| crosses(level.0)) {
+            return Placement {
+                order,
+                status: PlacementStatus::Rejected(
+                    "Post only order would have immediately matched".to_string(),
+                ),
+                fills: Vec::new(),
+            };
+        }
+
+        let mut matched = Vec::new();
+        for level in levels.iter_mut() {
+            if order.sz < EPSILON || !crosses(level.0) {
+                break;
+            }
+            let sz = level.1.min(order.sz);
+            level.1 -= sz;
+            order.sz -= sz;
+            matched.push((level.0, sz));
+        }
+        levels.retain(|level| level.1 > EPSILON);
+
+        let fills: Vec<SimFill> = matched
+            .into_iter()
+            .map(|(px, sz)| self.fill(&order, px, sz, true))
+            .collect();
+
+        let status = if order.sz < EPSILON {
+            order.sz = 0.0;
+            PlacementStatus::Filled
+        } else if order.tif == TimeInForce::Ioc {
+            if fills.is_empty() {
+                PlacementStatus::Rejected(
+                    "Order could not immediately match against any resting orders.".to_string(),
+                )
+            } else {
+                PlacementStatus::Filled
+            }
+        } else {
+            self.orders.insert(order.oid, order.clone());
+            PlacementStatus::Resting
+        };
+
+        Placement {
+            order,
+            status,
+            fills,
+        }
+    }
+
+    pub fn cancel(&mut self, oid: u64) -> Option<SimOrder> {
+        self.orders.remove(&oid)
+    }
+
+    /// Replaces the book for the snapshot's coin and fills resting orders it crosses.
+    pub fn on_book(&mut self, snapshot: &L2BookData) -> Result<Vec<SimFill>> {
+        self.set_time(snapshot.time);
+        let parse_side = |side: Option<&Vec<crate::BookLevel>>| -> Result<Vec<(f64, f64)>> {
+            side.map(|levels| {
+                levels
+                    .iter()
+                    .map(|level| Ok((parse(&level.px)?, parse(&level.sz)?)))
+                    .collect()
+            })
+            .unwrap_or(Ok(Vec::new()))
+        };
+        let mut book = Book {
+            bids: parse_side(snapshot.levels.first())?,
+            asks: parse_side(snapshot.levels.get(1))?,
+        };
+
+        let mut fills = Vec::new();
+        for oid in self.resting_by_priority(&snapshot.coin) {
+            let Some(mut order) = self.orders.remove(&oid) else {
+                continue;
+            };
+            let levels = if order.is_buy {
+                &mut book.asks
+            } else {
+                &mut book.bids
+            };
+            let mut filled = 0.0;
+            for level in levels.iter_mut() {
+                let crosses = if order.is_buy {
+                    level.0 <= order.limit_px
+                } else {
+                    level.0 >= order.limit_px
+                };
+                if !crosses || order.sz - filled < EPSILON {
+                    break;
+                }
+                let sz = level.1.min(order.sz - filled);
+                level.1 -= sz;
+                filled += sz;
+            }
+            levels.retain(|level| level.1 > EPSILON);
+
+            if filled > EPSILON {
+                order.sz -= filled;
+                fills.push(self.fill(&order, order.limit_px, filled, false));
+            }
+            if order.sz > EPSILON {
+                self.orders.insert(oid, order);
+            }
+        }
+
+        self.books.insert(snapshot.coin.clone(), book);
+        Ok(fills)
+    }
+
+    /// Fills resting orders on the passive side of a trade printing through their price.
+    pub fn on_trade(&mut self, trade: &Trade) -> Result<Vec<SimFill>> {
+        self.set_time(trade.time);
+        let px = parse(&trade.px)?;
+        let mut remaining = parse(&trade.sz)?;
+        // The trade side is the aggressor's, so a sell hits resting buys
+        let hits_buys = trade.side == "A";
+
+        let mut fills = Vec::new();
+        for oid in self.resting_by_priority(&trade.coin) {
+            if remaining < EPSILON {
+                break;
+            }
+            let Some(mut order) = self.orders.remove(&oid) else {
+                continue;
+            };
+            let crosses = order.is_buy == hits_buys
+                && if order.is_buy {
+                    px <= order.limit_px
+                } else {
+                    px >= order.limit_px
+                };
+            if crosses {
+                let sz = remaining.min(order.sz);
+                remaining -= sz;
+                order.sz -= sz;
+                fills.push(self.fill(&order, order.limit_px, sz, false));
+            }
+            if order.sz > EPSILON {
+                self.orders.insert(oid, order);
+            }
+        }
+        Ok(fills)
+    }
+
+    // Resting orders for `coin`, best price first, then oldest first
+    fn resting_by_priority(&self, coin: &str) -> Vec<u64> {
+        let mut orders: Vec<&SimOrder> = self
+            .orders
+            .values()
+            .filter(|order| order.coin == coin)
+            .collect();
+        orders.sort_by(|a, b| {
+            let by_px = if a.is_buy {
+                b.limit_px.total_cmp(&a.limit_px)
+            } else {
+                a.limit_px.total_cmp(&b.limit_px)
+            };
+            a.is_buy.cmp(&b.is_buy).then(by_px).then(a.oid.cmp(&b.oid))
+        });
+        orders.into_iter().map(|order| order.oid).collect()
+    }
+
+    fn fill(&mut self, order: &SimOrder, px: f64, sz: f64, crossed: bool) -> SimFill {
+        self.next_tid += 1;
+        let rate = if crossed {
+            self.fees.taker
+        } else {
+            self.fees.maker
+        };
+        SimFill {
+            oid: order.oid,
+            tid: self.next_tid,
+            coin: order.coin.clone(),
+            is_buy: order.is_buy,
+            px,
+            sz,
+            fee: px * sz * rate,
+            crossed,
+            cloid: order.cloid,
+            time: self.now,
+        }
+    }
+}
+
+fn parse(value: &str) -> Result<f64> {
+    value.parse().map_err(|_| Error::FloatStringParse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BookLevel;
+
+    fn book(time: u64, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> L2BookData {
+        let side = |levels: &[(&str, &str)]| {
+            levels
+                .iter()
+                .map(|(px, sz)| BookLevel {
+                    px: px.to_string(),
+                    sz: sz.to_string(),
+                    n: 1,
+                })
+                .collect()
+        };
+        L2BookData {
+            coin: "ETH".to_string(),
+            time,
+            levels: vec![side(bids), side(asks)],
+        }
+    }
+
+    fn order(oid: u64, is_buy: bool, limit_px: f64, sz: f64, tif: TimeInForce) -> SimOrder {
+        SimOrder {
+            oid,
+            coin: "ETH".to_string(),
+            is_buy,
+            limit_px,
+            sz,
+            orig_sz: sz,
+            tif,
+            cloid: None,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_aggressive_orders() -> Result<()> {
+        let mut engine = MatchingEngine::new(FeeRates::default());
+        engine.on_book(&book(
+            1,
+            &[("99", "1")],
+            &[("101", "1"), ("102", "2"), ("105", "5")],
+        ))?;
+        assert_eq!(engine.mid("ETH"), Some(100.0));
+
+        let placement = engine.place(order(1, true, 102.0, 2.0, TimeInForce::Ioc));
+        assert_eq!(placement.status, PlacementStatus::Filled);
+        assert_eq!(placement.fills.len(), 2);
+        assert!(placement.fills.iter().all(|fill| fill.crossed));
+        assert!((placement.fills[1].fee - 102.0 * 0.00045).abs() < EPSILON);
+
+        // Consumed liquidity stays consumed until the next snapshot
+        let placement = engine.place(order(2, true, 102.0, 2.0, TimeInForce::Gtc));
+        assert_eq!(placement.status, PlacementStatus::Resting);
+        assert!((placement.fills[0].sz - 1.0).abs() < EPSILON);
+        assert!((engine.order(2).unwrap().sz - 1.0).abs() < EPSILON);
+
+        let placement = engine.place(order(3, false, 99.5, 1.0, TimeInForce::Ioc));
+        assert!(matches!(placement.status, PlacementStatus::Rejected(_)));
+        let placement = engine.place(order(4, false, 98.0, 1.0, TimeInForce::Alo));
+        assert!(matches!(placement.status, PlacementStatus::Rejected(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resting_orders() -> Result<()> {
+        let mut engine = MatchingEngine::new(FeeRates::default());
+        engine.on_book(&book(1, &[("99", "1")], &[("101", "1")]))?;
+        engine.place(order(1, true, 100.0, 2.0, TimeInForce::Alo));
+        engine.place(order(2, true, 99.5, 1.0, TimeInForce::Gtc));
+
+        let trade = Trade {
+            coin: "ETH".to_string(),
+            side: "A".to_string(),
+            px: "100".to_string(),
+            sz: "0.5".to_string(),
+            time: 2,
+            hash: String::new(),
+            tid: 1,
+        };
+        let fills = engine.on_trade(&trade)?;
+        assert_eq!(fills.len(), 1);
+        assert_eq!((fills[0].oid, fills[0].px, fills[0].time), (1, 100.0, 2));
+        assert!(!fills[0].crossed);
+
+        // The book moves through both orders
+        let fills = engine.on_book(&book(3, &[("98", "1")], &[("99", "3")]))?;
+        assert_eq!(fills.len(), 2);
+        assert!((fills[0].sz - 1.5).abs() < EPSILON);
+        assert_eq!((fills[1].oid, fills[1].px), (2, 99.5));
+        assert_eq!(engine.open_orders().count(), 0);
+
+        engine.place(order(3, false, 100.0, 1.0, TimeInForce::Gtc));
+        assert!(engine.cancel(3).is_some());
+        assert!(engine.cancel(3).is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,8 @@
+mod matching_engine;
+mod paper_exchange;
+
+pub use matching_engine::{
+    FeeRates, MatchingEngine, Placement, PlacementStatus, SimFill, SimOrder, TimeInForce,
+};
+pub(crate) use paper_exchange::trade_info;
+pub use paper_exchange::PaperExchange;
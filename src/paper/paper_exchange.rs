@@ -0,0 +1,694 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use ethers::types::H160;
+use log::error;
+use tokio::{
+    spawn,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+};
+
+use crate::{
+    helpers::float_to_string_for_hashing,
+    paper::{FeeRates, MatchingEngine, Placement, PlacementStatus, SimFill, SimOrder, TimeInForce},
+    prelude::*,
+    Actions, AssetMetadata, BasicOrder, Cloid, Error, ExchangeDataStatus, ExchangeDataStatuses,
+    ExchangeResponse, ExchangeResponseStatus, FilledOrder, InfoClient, Message, Order, OrderUpdate,
+    OrderUpdates, Position, PositionTracker, RestingOrder, Subscription, TradeInfo, UserFills,
+    UserFillsData, EPSILON,
+};
+
+const NOT_FOUND: &str = "Order was never placed, already canceled, or filled.";
+
+#[derive(Debug)]
+struct PaperState {
+    engine: MatchingEngine,
+    positions: PositionTracker,
+    next_oid: u64,
+}
+
+/// A simulated exchange for paper trading, used by an [`crate::ExchangeClient`] in place of the
+/// `/exchange` endpoint once set with [`crate::ExchangeClient::with_paper_exchange`].
+///
+/// Orders are matched by a [`MatchingEngine`] against the `L2Book` and `Trades` messages passed
+/// to [`PaperExchange::on_message`], either live via [`PaperExchange::connect`] or recorded.
+/// Order updates and fills are emitted to [`PaperExchange::subscribe`]rs in the same shape as the
+/// `orderUpdates` and `userFills` websocket feeds. Trigger orders and transfers are not supported.
+#[derive(Debug)]
+pub struct PaperExchange {
+    user: H160,
+    state: Mutex<PaperState>,
+    subscribers: Mutex<Vec<UnboundedSender<Message>>>,
+}
+
+impl PaperExchange {
+    /// `user` is the address reported in synthetic fill messages.
+    pub fn new(user: H160, fees: FeeRates) -> PaperExchange {
+        PaperExchange {
+            user,
+            state: Mutex::new(PaperState {
+                engine: MatchingEngine::new(fees),
+                positions: PositionTracker::new(),
+                next_oid: 0,
+            }),
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Receives `OrderUpdates` and `UserFills` messages for paper orders.
+    pub fn subscribe(&self) -> UnboundedReceiver<Message> {
+        let (sender, receiver) = unbounded_channel();
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(sender);
+        receiver
+    }
+
+    /// Subscribes to live `L2Book` and `Trades` for `coins` and feeds them to the simulation.
+    pub async fn connect(
+        self: &Arc<Self>,
+        info_client: &mut InfoClient,
+        coins: &[&str],
+    ) -> Result<JoinHandle<()>> {
+        let (sender, mut receiver) = unbounded_channel();
+        for coin in coins {
+            let coin = coin.to_string();
+            info_client
+                .subscribe(
+                    Subscription::L2Book {
+                        coin: coin.clone(),
+                        n_sig_figs: None,
+                        mantissa: None,
+                    },
+                    sender.clone(),
+                )
+                .await?;
+            info_client
+                .subscribe(Subscription::Trades { coin }, sender.clone())
+                .await?;
+        }
+
+        let exchange = self.clone();
+        Ok(spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                if let Err(err) = exchange.on_message(&message) {
+                    error!("Paper exchange could not process market data: {err}");
+                }
+            }
+        }))
+    }
+
+    /// Applies `L2Book` and `Trades` market data; other messages are ignored.
+    pub fn on_message(&self, message: &Message) -> Result<()> {
+        let mut state = self.lock();
+        // Orders filled by the market data are gone from the engine afterwards
+        let mut resting: HashMap<u64, SimOrder> = match message {
+            Message::L2Book(_) | Message::Trades(_) => state
+                .engine
+                .open_orders()
+                .map(|order| (order.oid, order.clone()))
+                .collect(),
+            _ => return Ok(()),
+        };
+        let fills = match message {
+            Message::L2Book(book) => state.engine.on_book(&book.data)?,
+            Message::Trades(trades) => {
+                let mut fills = Vec::new();
+                for trade in &trades.data {
+                    fills.extend(state.engine.on_trade(trade)?);
+                }
+                fills
+            }
+            _ => return Ok(()),
+        };
+
+        let mut updates = Vec::new();
+        for fill in &fills {
+            if state.engine.order(fill.oid).is_some() {
+                continue;
+            }
+            if let Some(order) = resting.remove(&fill.oid) {
+                updates.push(order_update(
+                    &filled_order(order),
+                    "filled",
+                    state.engine.now(),
+                ));
+            }
+        }
+        self.emit(&mut state, updates, fills)
+    }
+
+    pub fn position(&self, coin: &str) -> Option<Position> {
+        self.lock().positions.position(coin).cloned()
+    }
+
+    pub fn positions(&self) -> Vec<Position> {
+        self.lock().positions.positions().cloned().collect()
+    }
+
+    pub fn open_orders(&self) -> Vec<SimOrder> {
+        self.lock().engine.open_orders().cloned().collect()
+    }
+
+    pub fn mid(&self, coin: &str) -> Option<f64> {
+        self.lock().engine.mid(coin)
+    }
+
+    /// Executes an action the way the exchange would and returns its response.
+    pub fn handle(
+        &self,
+        action: &Actions,
+        assets: &AssetMetadata,
+    ) -> Result<ExchangeResponseStatus> {
+        let mut state = self.lock();
+        let mut updates = Vec::new();
+        let mut fills = Vec::new();
+
+        let (response_type, statuses) = match action {
+            Actions::Order(bulk_order) => {
+                // Validate the whole batch first so that a bad order can't leave earlier ones
+                // placed
+                let coins = bulk_order
+                    .orders
+                    .iter()
+                    .map(|order| {
+                        validate(
+                            assets,
+                            order.asset,
+                            &order.limit_px,
+                            &order.sz,
+                            &order.order_type,
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let mut statuses = Vec::new();
+                for (order, coin) in bulk_order.orders.iter().zip(coins) {
+                    let status = self.place(
+                        &mut state,
+                        coin,
+                        order.is_buy,
+                        &order.limit_px,
+                        &order.sz,
+                        order.reduce_only,
+                        &order.order_type,
+                        order.cloid,
+                        &mut updates,
+                        &mut fills,
+                    )?;
+                    statuses.push(status);
+                }
+                ("order", Some(statuses))
+            }
+            Actions::BatchModify(bulk_modify) => {
+                let coins = bulk_modify
+                    .modifies
+                    .iter()
+                    .map(|modify| {
+                        validate(
+                            assets,
+                            modify.order.asset,
+                            &modify.order.limit_px,
+                            &modify.order.sz,
+                            &modify.order.order_type,
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let mut statuses = Vec::new();
+                for (modify, coin) in bulk_modify.modifies.iter().zip(coins) {
+                    let order = &modify.order;
+                    let status = match state.engine.cancel(modify.oid) {
+                        Some(canceled) => {
+                            updates.push(order_update(&canceled, "canceled", state.engine.now()));
+                            self.place(
+                                &mut state,
+                                coin,
+                                order.is_buy,
+                                &order.limit_px,
+                                &order.sz,
+                                order.reduce_only,
+                                &order.order_type,
+                                order.cloid,
+                                &mut updates,
+                                &mut fills,
+                            )?
+                        }
+                        None => ExchangeDataStatus::Error(
+                            "Cannot modify canceled or filled order".to_string(),
+                        ),
+                    };
+                    statuses.push(status);
+                }
+                ("order", Some(statuses))
+            }
+            Actions::Cancel(bulk_cancel) => {
+                let statuses = bulk_cancel
+                    .cancels
+                    .iter()
+                    .map(|cancel| Self::cancel(&mut state, cancel.oid, &mut updates))
+                    .collect();
+                ("cancel", Some(statuses))
+            }
+            Actions::CancelByCloid(bulk_cancel) => {
+                let statuses = bulk_cancel
+                    .cancels
+                    .iter()
+                    .map(|cancel| {
+                        let oid = state
+                            .engine
+                            .open_orders()
+                            .find(|order| order.cloid == Some(cancel.cloid))
+                            .map(|order| order.oid);
+                        match oid {
+                            Some(oid) => Self::cancel(&mut state, oid, &mut updates),
+                            None => ExchangeDataStatus::Error(NOT_FOUND.to_string()),
+                        }
+                    })
+                    .collect();
+                ("cancel", Some(statuses))
+            }
+            Actions::UpdateLeverage(_)
+            | Actions::UpdateIsolatedMargin(_)
+            | Actions::SetReferrer(_)
+            | Actions::ApproveAgent(_)
+            | Actions::ApproveBuilderFee(_) => ("default", None),
+            Actions::UsdSend(_)
+            | Actions::Withdraw3(_)
+            | Actions::SpotUser(_)
+            | Actions::VaultTransfer(_)
+            | Actions::SpotSend(_)
+            | Actions::UsdClassTransfer(_) => {
+                return Ok(ExchangeResponseStatus::Err(
+                    "Transfers are not supported in paper trading".to_string(),
+                ))
+            }
+        };
+
+        self.emit(&mut state, updates, fills)?;
+        Ok(ExchangeResponseStatus::Ok(ExchangeResponse {
+            response_type: response_type.to_string(),
+            data: statuses.map(|statuses| ExchangeDataStatuses { statuses }),
+        }))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn place(
+        &self,
+        state: &mut PaperState,
+        coin: &str,
+        is_buy: bool,
+        limit_px: &str,
+        sz: &str,
+        reduce_only: bool,
+        order_type: &Order,
+        cloid: Option<Cloid>,
+        updates: &mut Vec<OrderUpdate>,
+        fills: &mut Vec<SimFill>,
+    ) -> Result<ExchangeDataStatus> {
+        let tif = match order_type {
+            Order::Limit(limit) => TimeInForce::parse(&limit.tif)?,
+            Order::Trigger(_) => {
+                return Ok(ExchangeDataStatus::Error(
+                    "Trigger orders are not supported in paper trading".to_string(),
+                ))
+            }
+        };
+        let mut sz = parse(sz)?;
+        if reduce_only {
+            let position = state
+                .positions
+                .position(coin)
+                .map(|position| position.szi)
+                .unwrap_or(0.0);
+            if position.abs() < EPSILON || (position > 0.0) == is_buy {
+                return Ok(ExchangeDataStatus::Error(
+                    "Reduce only order would increase position.".to_string(),
+                ));
+            }
+            sz = sz.min(position.abs());
+        }
+
+        state.next_oid += 1;
+        let Placement {
+            order,
+            status,
+            fills: order_fills,
+        } = state.engine.place(SimOrder {
+            oid: state.next_oid,
+            coin: coin.to_string(),
+            is_buy,
+            limit_px: parse(limit_px)?,
+            sz,
+            orig_sz: sz,
+            tif,
+            cloid,
+            timestamp: 0,
+        });
+
+        let now = state.engine.now();
+        let status = match status {
+            PlacementStatus::Resting => {
+                updates.push(order_update(&order, "open", now));
+                ExchangeDataStatus::Resting(RestingOrder { oid: order.oid })
+            }
+            PlacementStatus::Filled => {
+                updates.push(order_update(&order, "filled", now));
+                let total_sz: f64 = order_fills.iter().map(|fill| fill.sz).sum();
+                let notional: f64 = order_fills.iter().map(|fill| fill.px * fill.sz).sum();
+                ExchangeDataStatus::Filled(FilledOrder {
+                    total_sz: float_to_string_for_hashing(total_sz),
+                    avg_px: float_to_string_for_hashing(notional / total_sz),
+                    oid: order.oid,
+                })
+            }
+            PlacementStatus::Rejected(reason) => ExchangeDataStatus::Error(reason),
+        };
+        fills.extend(order_fills);
+        Ok(status)
+    }
+
+    fn cancel(
+        state: &mut PaperState,
+        oid: u64,
+        updates: &mut Vec<OrderUpdate>,
+    ) -> ExchangeDataStatus {
+        match state.engine.cancel(oid) {
+            Some(order) => {
+                updates.push(order_update(&order, "canceled", state.engine.now()));
+                ExchangeDataStatus::Success
+            }
+            None => ExchangeDataStatus::Error(NOT_FOUND.to_string()),
+        }
+    }
+
+    fn emit(
+        &self,
+        state: &mut PaperState,
+        updates: Vec<OrderUpdate>,
+        fills: Vec<SimFill>,
+    ) -> Result<()> {
+        let mut trade_infos = Vec::new();
+        for fill in fills {
+            let before = state
+                .positions
+                .position(&fill.coin)
+                .cloned()
+                .unwrap_or_default();
+            let mut trade_info = trade_info(&fill, before.szi);
+            state.positions.apply_fill(&trade_info)?;
+            let realized = state
+                .positions
+                .position(&fill.coin)
+                .map(|position| position.realized_pnl)
+                .unwrap_or(0.0);
+            trade_info.closed_pnl = float_to_string_for_hashing(realized - before.realized_pnl);
+            trade_infos.push(trade_info);
+        }
+
+        let mut messages = Vec::new();
+        if !updates.is_empty() {
+            messages.push(Message::OrderUpdates(OrderUpdates { data: updates }));
+        }
+        if !trade_infos.is_empty() {
+            messages.push(Message::UserFills(UserFills {
+                data: UserFillsData {
+                    is_snapshot: None,
+                    user: self.user,
+                    fills: trade_infos,
+                },
+            }));
+        }
+        if !messages.is_empty() {
+            self.subscribers
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .retain(|subscriber| {
+                    messages
+                        .iter()
+                        .all(|message| subscriber.send(message.clone()).is_ok())
+                });
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, PaperState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// A resting order completed by fills, for its "filled" order update
+fn filled_order(order: SimOrder) -> SimOrder {
+    SimOrder { sz: 0.0, ..order }
+}
+
+// Checks everything about an order that would make handling it fail, returning its coin
+fn validate<'a>(
+    assets: &'a AssetMetadata,
+    asset: u32,
+    limit_px: &str,
+    sz: &str,
+    order_type: &Order,
+) -> Result<&'a str> {
+    let coin = assets.coin(asset).ok_or(Error::AssetNotFound)?;
+    parse(limit_px)?;
+    parse(sz)?;
+    if let Order::Limit(limit) = order_type {
+        TimeInForce::parse(&limit.tif)?;
+    }
+    Ok(coin)
+}
+
+fn order_update(order: &SimOrder, status: &str, now: u64) -> OrderUpdate {
+    OrderUpdate {
+        order: BasicOrder {
+            coin: order.coin.clone(),
+            side: side(order.is_buy),
+            limit_px: float_to_string_for_hashing(order.limit_px),
+            sz: float_to_string_for_hashing(order.sz),
+            oid: order.oid,
+            timestamp: order.timestamp,
+            orig_sz: float_to_string_for_hashing(order.orig_sz),
+            cloid: order.cloid,
+        },
+        status: status.to_string(),
+        status_timestamp: now,
+    }
+}
+
+/// A fill in the shape of the `userFills` feed, opened or closed against `start_position`.
+pub(crate) fn trade_info(fill: &SimFill, start_position: f64) -> TradeInfo {
+    let dir = match (
+        start_position > EPSILON,
+        start_position < -EPSILON,
+        fill.is_buy,
+    ) {
+        (false, false, true) | (true, _, true) => "Open Long",
+        (false, false, false) | (_, true, false) => "Open Short",
+        (true, _, false) if fill.sz > start_position + EPSILON => "Long > Short",
+        (true, _, false) => "Close Long",
+        (_, true, true) if fill.sz > -start_position + EPSILON => "Short > Long",
+        (_, true, true) => "Close Short",
+    };
+    TradeInfo {
+        coin: fill.coin.clone(),
+        side: side(fill.is_buy),
+        px: float_to_string_for_hashing(fill.px),
+        sz: float_to_string_for_hashing(fill.sz),
+        time: fill.time,
+        hash: format!("{:#066x}", 0),
+        start_position: float_to_string_for_hashing(start_position),
+        dir: dir.to_string(),
+        closed_pnl: "0".to_string(),
+        oid: fill.oid,
+        cloid: fill.cloid,
+        crossed: fill.crossed,
+        fee: float_to_string_for_hashing(fill.fee),
+        fee_token: "USDC".to_string(),
+        tid: fill.tid,
+    }
+}
+
+fn side(is_buy: bool) -> String {
+    if is_buy { "B" } else { "A" }.to_string()
+}
+
+fn parse(value: &str) -> Result<f64> {
+    value.parse().map_err(|_| Error::FloatStringParse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{meta::SpotMeta, BookLevel, L2Book, L2BookData, Meta, Trade, Trades};
+
+    fn assets() -> AssetMetadata {
+        let meta: Meta = serde_json::from_str(
+            r#"{"universe": [{"name": "ETH", "szDecimals": 4, "maxLeverage": 50}]}"#,
+        )
+        .unwrap();
+        let spot_meta: SpotMeta =
+            serde_json::from_str(r#"{"universe": [], "tokens": []}"#).unwrap();
+        AssetMetadata::new(meta, spot_meta)
+    }
+
+    fn book(time: u64, bid: &str, ask: &str) -> Message {
+        let level = |px: &str| {
+            vec![BookLevel {
+                px: px.to_string(),
+                sz: "10".to_string(),
+                n: 1,
+            }]
+        };
+        Message::L2Book(L2Book {
+            data: L2BookData {
+                coin: "ETH".to_string(),
+                time,
+                levels: vec![level(bid), level(ask)],
+            },
+        })
+    }
+
+    fn order(is_buy: bool, px: &str, sz: &str, reduce_only: bool, tif: &str) -> Actions {
+        serde_json::from_value(serde_json::json!({
+            "type": "order",
+            "orders": [{
+                "a": 0, "b": is_buy, "p": px, "s": sz, "r": reduce_only,
+                "t": {"limit": {"tif": tif}}
+            }],
+            "grouping": "na"
+        }))
+        .unwrap()
+    }
+
+    fn statuses(response: ExchangeResponseStatus) -> Vec<ExchangeDataStatus> {
+        match response {
+            ExchangeResponseStatus::Ok(response) => response.data.unwrap().statuses,
+            ExchangeResponseStatus::Err(err) => panic!("{err}"),
+        }
+    }
+
+    #[test]
+    fn test_paper_orders() -> Result<()> {
+        let exchange = PaperExchange::new(H160::zero(), FeeRates::default());
+        let mut receiver = exchange.subscribe();
+        let assets = assets();
+        exchange.on_message(&book(1, "1999", "2001"))?;
+
+        let response = exchange.handle(&order(true, "2010", "1", false, "Ioc"), &assets)?;
+        assert!(matches!(
+            &statuses(response)[..],
+            [ExchangeDataStatus::Filled(FilledOrder { avg_px, .. })] if avg_px == "2001"
+        ));
+        assert!((exchange.position("ETH").unwrap().szi - 1.0).abs() < EPSILON);
+        assert!(matches!(receiver.try_recv(), Ok(Message::OrderUpdates(_))));
+        assert!(
+            matches!(receiver.try_recv(), Ok(Message::UserFills(fills)) if fills.data.fills[0].dir == "Open Long")
+        );
+
+        // Reduce only orders are clipped to the position, and rest until the book crosses them
+        let response = exchange.handle(&order(false, "2100", "5", true, "Gtc"), &assets)?;
+        let [ExchangeDataStatus::Resting(RestingOrder { oid })] = statuses(response)[..] else {
+            panic!("order did not rest");
+        };
+        assert!((exchange.open_orders()[0].sz - 1.0).abs() < EPSILON);
+        let response = exchange.handle(&order(true, "1000", "1", true, "Gtc"), &assets)?;
+        assert!(matches!(
+            &statuses(response)[..],
+            [ExchangeDataStatus::Error(_)]
+        ));
+
+        exchange.on_message(&book(2, "2100", "2101"))?;
+        let position = exchange.position("ETH").unwrap();
+        assert!(position.szi.abs() < EPSILON);
+        assert!((position.realized_pnl - 99.0).abs() < EPSILON);
+        assert!(
+            matches!(receiver.try_recv(), Ok(Message::OrderUpdates(updates)) if updates.data[0].status == "open")
+        );
+        assert!(
+            matches!(receiver.try_recv(), Ok(Message::OrderUpdates(updates)) if updates.data[0].order.oid == oid && updates.data[0].status == "filled")
+        );
+        assert!(
+            matches!(receiver.try_recv(), Ok(Message::UserFills(fills)) if fills.data.fills[0].closed_pnl == "99")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_paper_cancels() -> Result<()> {
+        let exchange = PaperExchange::new(H160::zero(), FeeRates::default());
+        let assets = assets();
+        exchange.on_message(&book(1, "1999", "2001"))?;
+        exchange.handle(&order(true, "1990", "1", false, "Alo"), &assets)?;
+
+        let cancel: Actions = serde_json::from_value(serde_json::json!({
+            "type": "cancel", "cancels": [{"a": 0, "o": 1}]
+        }))
+        .unwrap();
+        assert!(matches!(
+            &statuses(exchange.handle(&cancel, &assets)?)[..],
+            [ExchangeDataStatus::Success]
+        ));
+        assert!(matches!(
+            &statuses(exchange.handle(&cancel, &assets)?)[..],
+            [ExchangeDataStatus::Error(err)] if err == NOT_FOUND
+        ));
+        assert!(exchange.open_orders().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_paper_batch_validation_and_partial_fills() -> Result<()> {
+        let exchange = PaperExchange::new(H160::zero(), FeeRates::default());
+        let assets = assets();
+        exchange.on_message(&book(1, "1999", "2001"))?;
+
+        // An unknown asset anywhere in the batch rejects it before anything is placed
+        let batch: Actions = serde_json::from_value(serde_json::json!({
+            "type": "order",
+            "orders": [
+                {"a": 0, "b": true, "p": "1990", "s": "1", "r": false, "t": {"limit": {"tif": "Gtc"}}},
+                {"a": 5, "b": true, "p": "1990", "s": "1", "r": false, "t": {"limit": {"tif": "Gtc"}}}
+            ],
+            "grouping": "na"
+        }))
+        .unwrap();
+        assert!(matches!(
+            exchange.handle(&batch, &assets),
+            Err(Error::AssetNotFound)
+        ));
+        assert!(exchange.open_orders().is_empty());
+
+        exchange.handle(&order(true, "1995", "2", false, "Gtc"), &assets)?;
+        let mut receiver = exchange.subscribe();
+        let trade = |tid| {
+            Message::Trades(Trades {
+                data: vec![Trade {
+                    coin: "ETH".to_string(),
+                    side: "A".to_string(),
+                    px: "1990".to_string(),
+                    sz: "1".to_string(),
+                    time: 2,
+                    hash: "0x".to_string(),
+                    tid,
+                }],
+            })
+        };
+        exchange.on_message(&trade(1))?;
+        assert!(matches!(receiver.try_recv(), Ok(Message::UserFills(_))));
+        exchange.on_message(&trade(2))?;
+
+        // The filled update reports the order itself, not its last fill
+        let Ok(Message::OrderUpdates(updates)) = receiver.try_recv() else {
+            panic!("no order update");
+        };
+        let update = &updates.data[0];
+        assert_eq!(update.status, "filled");
+        assert_eq!(update.order.limit_px, "1995");
+        assert_eq!(update.order.orig_sz, "2");
+        assert_eq!(update.order.sz, "0");
+        Ok(())
+    }
+}
@@ -0,0 +1,536 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+use ethers::types::H160;
+use log::{error, warn};
+use tokio::{
+    spawn,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver},
+        Mutex,
+    },
+    task::JoinHandle,
+    time,
+};
+
+use crate::{
+    prelude::*, Error, InfoClient, Message, TradeInfo, UserData, UserFillsResponse, UserFunding,
+    UserFundingResponse, UserStateResponse, EPSILON,
+};
+
+const USDC: &str = "USDC";
+
+/// Local view of a single coin's position.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Position {
+    pub coin: String,
+    /// Signed size, negative when short
+    pub szi: f64,
+    /// Average entry price of the open position, 0 when flat
+    pub entry_px: f64,
+    pub realized_pnl: f64,
+    /// Fees paid in USDC
+    pub fees_paid: f64,
+    /// Fees paid in other tokens, e.g. the base token of spot buys, by token name
+    pub other_fees: HashMap<String, f64>,
+    /// Funding received, negative when funding was paid
+    pub funding: f64,
+    /// Latest mid price seen for the coin
+    pub mark_px: Option<f64>,
+}
+
+impl Position {
+    fn new(coin: &str) -> Position {
+        Position {
+            coin: coin.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn unrealized_pnl(&self) -> f64 {
+        self.mark_px
+            .map(|mark_px| (mark_px - self.entry_px) * self.szi)
+            .unwrap_or(0.0)
+    }
+
+    /// Realized and unrealized PnL net of USDC fees and funding.
+    pub fn total_pnl(&self) -> f64 {
+        self.realized_pnl + self.unrealized_pnl() - self.fees_paid + self.funding
+    }
+
+    fn apply_trade(&mut self, is_buy: bool, px: f64, sz: f64, fee: f64, fee_token: &str) {
+        let signed_sz = if is_buy { sz } else { -sz };
+        if fee_token == USDC {
+            self.fees_paid += fee;
+        } else {
+            *self.other_fees.entry(fee_token.to_string()).or_default() += fee;
+        }
+        if sz <= EPSILON {
+            return;
+        }
+
+        if self.szi.abs() < EPSILON || self.szi.signum() == signed_sz.signum() {
+            let new_szi = self.szi + signed_sz;
+            self.entry_px = (self.entry_px * self.szi.abs() + px * sz) / new_szi.abs();
+            self.szi = new_szi;
+            return;
+        }
+
+        let closed_sz = sz.min(self.szi.abs());
+        self.realized_pnl += (px - self.entry_px) * closed_sz * self.szi.signum();
+        self.szi += signed_sz;
+        if self.szi.abs() < EPSILON {
+            self.szi = 0.0;
+            self.entry_px = 0.0;
+        } else if self.szi.signum() == signed_sz.signum() {
+            // Flipped sides, the remainder is opened at the fill price
+            self.entry_px = px;
+        }
+    }
+}
+
+// Fields shared by websocket fills and `userFills` responses
+struct Fill<'a> {
+    tid: u64,
+    oid: u64,
+    coin: &'a str,
+    side: &'a str,
+    px: &'a str,
+    sz: &'a str,
+    fee: &'a str,
+    fee_token: &'a str,
+}
+
+/// A mismatch between the tracked position and the exchange's clearinghouse state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionDiscrepancy {
+    pub coin: String,
+    pub local_szi: f64,
+    pub exchange_szi: f64,
+    pub local_entry_px: f64,
+    pub exchange_entry_px: f64,
+}
+
+/// Maintains per-coin positions and PnL from fills, funding payments and mids.
+///
+/// Only the most recent [`PositionTracker::MAX_SEEN_FILLS`] fills and
+/// [`PositionTracker::MAX_SEEN_FUNDINGS`] funding payments are remembered for deduplication,
+/// oldest first out.
+#[derive(Debug, Default)]
+pub struct PositionTracker {
+    positions: HashMap<String, Position>,
+    // Fills by trade id and oid, as both sides of a self-trade share the trade id
+    seen_fills: HashSet<(u64, u64)>,
+    seen_fundings: HashSet<(u64, String)>,
+    // Insertion order of `seen_fills` and `seen_fundings`, for evicting the oldest entries
+    seen_fills_order: VecDeque<(u64, u64)>,
+    seen_fundings_order: VecDeque<(u64, String)>,
+}
+
+impl PositionTracker {
+    pub const MAX_SEEN_FILLS: usize = 10_000;
+    pub const MAX_SEEN_FUNDINGS: usize = 10_000;
+
+    pub fn new() -> PositionTracker {
+        PositionTracker::default()
+    }
+
+    pub fn position(&self, coin: &str) -> Option<&Position> {
+        self.positions.get(coin)
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = &Position> {
+        self.positions.values()
+    }
+
+    /// Applies fills, funding and mids; other messages are ignored.
+    pub fn on_message(&mut self, message: &Message) -> Result<()> {
+        match message {
+            Message::UserFills(fills) => {
+                for fill in &fills.data.fills {
+                    self.apply_fill(fill)?;
+                }
+            }
+            Message::UserFundings(fundings) => {
+                for funding in &fundings.data.fundings {
+                    self.apply_funding(funding)?;
+                }
+            }
+            Message::User(user_events) => match &user_events.data {
+                UserData::Fills(fills) => {
+                    for fill in fills {
+                        self.apply_fill(fill)?;
+                    }
+                }
+                UserData::Funding(funding) => self.apply_funding(funding)?,
+                _ => {}
+            },
+            Message::AllMids(all_mids) => self.update_mids(&all_mids.data.mids)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Applies a fill; fills already seen (by trade id and oid) are ignored.
+    pub fn apply_fill(&mut self, fill: &TradeInfo) -> Result<()> {
+        self.apply_trade(Fill {
+            tid: fill.tid,
+            oid: fill.oid,
+            coin: &fill.coin,
+            side: &fill.side,
+            px: &fill.px,
+            sz: &fill.sz,
+            fee: &fill.fee,
+            fee_token: &fill.fee_token,
+        })
+    }
+
+    /// Bootstraps from [`InfoClient::user_fills`], oldest fill first.
+    pub fn apply_user_fills(&mut self, fills: &[UserFillsResponse]) -> Result<()> {
+        for fill in fills {
+            self.apply_trade(Fill {
+                tid: fill.tid,
+                oid: fill.oid,
+                coin: &fill.coin,
+                side: &fill.side,
+                px: &fill.px,
+                sz: &fill.sz,
+                fee: &fill.fee,
+                fee_token: &fill.fee_token,
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn apply_funding(&mut self, funding: &UserFunding) -> Result<()> {
+        self.apply_funding_payment(funding.time, &funding.coin, &funding.usdc)
+    }
+
+    /// Bootstraps from [`InfoClient::user_funding_history`].
+    pub fn apply_user_funding(&mut self, fundings: &[UserFundingResponse]) -> Result<()> {
+        for funding in fundings {
+            self.apply_funding_payment(funding.time, &funding.delta.coin, &funding.delta.usdc)?;
+        }
+        Ok(())
+    }
+
+    /// Updates mark prices used for unrealized PnL.
+    pub fn update_mids(&mut self, mids: &HashMap<String, String>) -> Result<()> {
+        for position in self.positions.values_mut() {
+            if let Some(mid) = mids.get(&position.coin) {
+                position.mark_px = Some(parse(mid)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces sizes and entry prices with the exchange's, keeping accumulated PnL, fees and
+    /// funding.
+    pub fn sync_from_user_state(&mut self, user_state: &UserStateResponse) -> Result<()> {
+        let exchange = exchange_positions(user_state)?;
+        for position in self.positions.values_mut() {
+            if is_perp(&position.coin) && !exchange.contains_key(&position.coin) {
+                position.szi = 0.0;
+                position.entry_px = 0.0;
+            }
+        }
+        for (coin, (szi, entry_px)) in exchange {
+            let position = self
+                .positions
+                .entry(coin.clone())
+                .or_insert_with(|| Position::new(&coin));
+            position.szi = szi;
+            position.entry_px = entry_px;
+        }
+        Ok(())
+    }
+
+    /// Compares tracked perp positions against the exchange's clearinghouse state.
+    pub fn reconcile(&self, user_state: &UserStateResponse) -> Result<Vec<PositionDiscrepancy>> {
+        let exchange = exchange_positions(user_state)?;
+        let mut coins: Vec<&String> = exchange
+            .keys()
+            .chain(
+                self.positions
+                    .values()
+                    .filter(|position| is_perp(&position.coin) && position.szi.abs() > EPSILON)
+                    .map(|position| &position.coin),
+            )
+            .collect();
+        coins.sort();
+        coins.dedup();
+
+        Ok(coins
+            .into_iter()
+            .filter_map(|coin| {
+                let (local_szi, local_entry_px) = self
+                    .positions
+                    .get(coin)
+                    .map(|position| (position.szi, position.entry_px))
+                    .unwrap_or_default();
+                let (exchange_szi, exchange_entry_px) =
+                    exchange.get(coin).copied().unwrap_or_default();
+
+                let size_matches = (local_szi - exchange_szi).abs() < EPSILON;
+                let entry_matches = (local_entry_px - exchange_entry_px).abs()
+                    <= 1e-6 * exchange_entry_px.abs().max(1.0);
+                (!size_matches || !entry_matches).then(|| PositionDiscrepancy {
+                    coin: coin.clone(),
+                    local_szi,
+                    exchange_szi,
+                    local_entry_px,
+                    exchange_entry_px,
+                })
+            })
+            .collect())
+    }
+
+    /// Reconciles against `user`'s clearinghouse state every `interval`. Discrepancies are logged
+    /// and sent on the returned channel; the task stops once the receiver is dropped.
+    pub fn start_reconciliation(
+        tracker: Arc<Mutex<PositionTracker>>,
+        info_client: InfoClient,
+        user: H160,
+        interval: Duration,
+    ) -> (JoinHandle<()>, UnboundedReceiver<Vec<PositionDiscrepancy>>) {
+        let (sender, receiver) = unbounded_channel();
+        let handle = spawn(async move {
+            let mut interval = time::interval(interval);
+            loop {
+                interval.tick().await;
+                let user_state = match info_client.user_state(user).await {
+                    Ok(user_state) => user_state,
+                    Err(err) => {
+                        error!("Could not fetch user state for reconciliation: {err}");
+                        continue;
+                    }
+                };
+                match tracker.lock().await.reconcile(&user_state) {
+                    Ok(discrepancies) if discrepancies.is_empty() => {}
+                    Ok(discrepancies) => {
+                        warn!("Position discrepancies: {discrepancies:?}");
+                        if sender.send(discrepancies).is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) => error!("Could not reconcile positions: {err}"),
+                }
+            }
+        });
+        (handle, receiver)
+    }
+
+    fn apply_trade(&mut self, fill: Fill) -> Result<()> {
+        let key = (fill.tid, fill.oid);
+        if self.seen_fills.contains(&key) {
+            return Ok(());
+        }
+        let (px, sz, fee) = (parse(fill.px)?, parse(fill.sz)?, parse(fill.fee)?);
+        self.seen_fills.insert(key);
+        self.seen_fills_order.push_back(key);
+        if self.seen_fills_order.len() > Self::MAX_SEEN_FILLS {
+            if let Some(oldest) = self.seen_fills_order.pop_front() {
+                self.seen_fills.remove(&oldest);
+            }
+        }
+        self.positions
+            .entry(fill.coin.to_string())
+            .or_insert_with(|| Position::new(fill.coin))
+            .apply_trade(fill.side == "B", px, sz, fee, fill.fee_token);
+        Ok(())
+    }
+
+    fn apply_funding_payment(&mut self, time: u64, coin: &str, usdc: &str) -> Result<()> {
+        let key = (time, coin.to_string());
+        if self.seen_fundings.contains(&key) {
+            return Ok(());
+        }
+        let usdc = parse(usdc)?;
+        self.seen_fundings.insert(key.clone());
+        self.seen_fundings_order.push_back(key);
+        if self.seen_fundings_order.len() > Self::MAX_SEEN_FUNDINGS {
+            if let Some(oldest) = self.seen_fundings_order.pop_front() {
+                self.seen_fundings.remove(&oldest);
+            }
+        }
+        self.positions
+            .entry(coin.to_string())
+            .or_insert_with(|| Position::new(coin))
+            .funding += usdc;
+        Ok(())
+    }
+}
+
+fn exchange_positions(user_state: &UserStateResponse) -> Result<HashMap<String, (f64, f64)>> {
+    user_state
+        .asset_positions
+        .iter()
+        .map(|asset_position| {
+            let position = &asset_position.position;
+            let entry_px = position.entry_px.as_deref().map(parse).transpose()?;
+            Ok((
+                position.coin.clone(),
+                (parse(&position.szi)?, entry_px.unwrap_or(0.0)),
+            ))
+        })
+        .collect()
+}
+
+// Spot fills are reported under "@<index>" or "<base>/<quote>" names
+fn is_perp(coin: &str) -> bool {
+    !coin.starts_with('@') && !coin.contains('/')
+}
+
+fn parse(value: &str) -> Result<f64> {
+    value.parse().map_err(|_| Error::FloatStringParse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(tid: u64, side: &str, px: &str, sz: &str) -> TradeInfo {
+        order_fill(tid, 1, side, px, sz)
+    }
+
+    fn order_fill(tid: u64, oid: u64, side: &str, px: &str, sz: &str) -> TradeInfo {
+        serde_json::from_value(serde_json::json!({
+            "coin": "ETH", "side": side, "px": px, "sz": sz, "time": 0, "hash": "0x",
+            "startPosition": "0", "dir": "", "closedPnl": "0", "oid": oid, "cloid": null,
+            "crossed": true, "fee": "0.5", "feeToken": "USDC", "tid": tid
+        }))
+        .unwrap()
+    }
+
+    fn user_state(szi: &str, entry_px: &str) -> UserStateResponse {
+        let margin = serde_json::json!({
+            "accountValue": "0", "totalMarginUsed": "0", "totalNtlPos": "0", "totalRawUsd": "0"
+        });
+        serde_json::from_value(serde_json::json!({
+            "assetPositions": [{
+                "type": "oneWay",
+                "position": {
+                    "coin": "ETH", "entryPx": entry_px, "leverage": {"type": "cross", "value": 10},
+                    "liquidationPx": null, "marginUsed": "0", "positionValue": "0",
+                    "returnOnEquity": "0", "szi": szi, "unrealizedPnl": "0", "maxLeverage": 50,
+                    "cumFunding": {"allTime": "0", "sinceOpen": "0", "sinceChange": "0"}
+                }
+            }],
+            "crossMarginSummary": margin,
+            "marginSummary": margin,
+            "withdrawable": "0"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_position_pnl() -> Result<()> {
+        let mut tracker = PositionTracker::new();
+        tracker.apply_fill(&fill(1, "B", "100", "1"))?;
+        tracker.apply_fill(&fill(2, "B", "110", "1"))?;
+        tracker.apply_fill(&fill(2, "B", "110", "1"))?;
+        let position = tracker.position("ETH").unwrap();
+        assert!((position.szi - 2.0).abs() < EPSILON);
+        assert!((position.entry_px - 105.0).abs() < EPSILON);
+
+        // Sell 3: closes 2 at 120 and opens a 1 short
+        tracker.apply_fill(&fill(3, "A", "120", "3"))?;
+        tracker.update_mids(&HashMap::from([("ETH".to_string(), "115".to_string())]))?;
+        tracker.apply_funding(&UserFunding {
+            time: 1,
+            coin: "ETH".to_string(),
+            usdc: "-0.25".to_string(),
+            szi: "-1".to_string(),
+            funding_rate: "0.0001".to_string(),
+        })?;
+
+        let position = tracker.position("ETH").unwrap();
+        assert!((position.szi + 1.0).abs() < EPSILON);
+        assert!((position.entry_px - 120.0).abs() < EPSILON);
+        assert!((position.realized_pnl - 30.0).abs() < EPSILON);
+        assert!((position.unrealized_pnl() - 5.0).abs() < EPSILON);
+        assert!((position.fees_paid - 1.5).abs() < EPSILON);
+        assert!((position.total_pnl() - 33.25).abs() < EPSILON);
+
+        // Fees in other tokens are kept out of the USDC totals
+        let mut spot_fill = fill(4, "B", "10", "1");
+        spot_fill.coin = "PURR/USDC".to_string();
+        spot_fill.fee = "0.01".to_string();
+        spot_fill.fee_token = "PURR".to_string();
+        tracker.apply_fill(&spot_fill)?;
+        let position = tracker.position("PURR/USDC").unwrap();
+        assert_eq!(position.fees_paid, 0.0);
+        assert_eq!(position.other_fees.get("PURR"), Some(&0.01));
+        assert!(position.total_pnl().abs() < EPSILON);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reconcile_positions() -> Result<()> {
+        let mut tracker = PositionTracker::new();
+        tracker.apply_fill(&fill(1, "B", "100", "1"))?;
+        assert!(tracker.reconcile(&user_state("1.0", "100.0"))?.is_empty());
+
+        let discrepancies = tracker.reconcile(&user_state("2.0", "105.0"))?;
+        assert_eq!(
+            discrepancies,
+            vec![PositionDiscrepancy {
+                coin: "ETH".to_string(),
+                local_szi: 1.0,
+                exchange_szi: 2.0,
+                local_entry_px: 100.0,
+                exchange_entry_px: 105.0,
+            }]
+        );
+
+        tracker.sync_from_user_state(&user_state("2.0", "105.0"))?;
+        assert!(tracker.reconcile(&user_state("2.0", "105.0"))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fill_deduplication() -> Result<()> {
+        let mut tracker = PositionTracker::new();
+        // An empty fill from flat leaves the entry price alone
+        tracker.apply_fill(&fill(1, "B", "100", "0"))?;
+        let position = tracker.position("ETH").unwrap();
+        assert_eq!(position.szi, 0.0);
+        assert_eq!(position.entry_px, 0.0);
+
+        // Both sides of a self-trade share the trade id
+        tracker.apply_fill(&order_fill(2, 2, "B", "100", "1"))?;
+        tracker.apply_fill(&order_fill(2, 3, "A", "100", "1"))?;
+        tracker.apply_fill(&order_fill(2, 3, "A", "100", "1"))?;
+        let position = tracker.position("ETH").unwrap();
+        assert!(position.szi.abs() < EPSILON);
+        assert!((position.fees_paid - 1.5).abs() < EPSILON);
+
+        for tid in 0..PositionTracker::MAX_SEEN_FILLS as u64 + 10 {
+            tracker.apply_fill(&order_fill(tid, 4, "B", "100", "0.0001"))?;
+        }
+        assert_eq!(tracker.seen_fills.len(), PositionTracker::MAX_SEEN_FILLS);
+        assert_eq!(
+            tracker.seen_fills_order.len(),
+            PositionTracker::MAX_SEEN_FILLS
+        );
+        for time in 0..PositionTracker::MAX_SEEN_FUNDINGS as u64 + 10 {
+            tracker.apply_user_funding(&[serde_json::from_value(serde_json::json!({
+                "time": time, "hash": "0x", "delta": {"type": "funding", "coin": "ETH",
+                "usdc": "0.01", "szi": "1", "fundingRate": "0.0001"}
+            }))
+            .unwrap()])?;
+        }
+        assert_eq!(
+            tracker.seen_fundings.len(),
+            PositionTracker::MAX_SEEN_FUNDINGS
+        );
+        assert_eq!(
+            tracker.seen_fundings_order.len(),
+            PositionTracker::MAX_SEEN_FUNDINGS
+        );
+        Ok(())
+    }
+}
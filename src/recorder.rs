@@ -0,0 +1,373 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    select, spawn,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedSender},
+        oneshot,
+    },
+    task::{spawn_blocking, JoinHandle},
+    time::sleep,
+};
+
+use crate::{prelude::*, Error, InfoClient, Message, Subscription};
+
+const FILE_EXTENSION: &str = "jsonl.gz";
+
+/// A websocket text frame as received, with its receive time in milliseconds.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedFrame {
+    pub received_at: u64,
+    pub data: String,
+}
+
+impl RecordedFrame {
+    pub fn message(&self) -> Result<Message> {
+        serde_json::from_str(&self.data).map_err(|e| Error::JsonParse(e.to_string()))
+    }
+}
+
+// Writes frames as gzipped JSON lines, starting a new file every `rotate_every`
+#[derive(Debug)]
+struct FrameWriter {
+    dir: PathBuf,
+    prefix: String,
+    rotate_every: Duration,
+    current: Option<(u64, GzEncoder<BufWriter<File>>)>,
+}
+
+impl FrameWriter {
+    fn write(&mut self, frame: &RecordedFrame) -> Result<()> {
+        let rotate_every = self.rotate_every.as_millis() as u64;
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|(opened_at, _)| frame.received_at >= opened_at + rotate_every)
+        {
+            self.finish()?;
+        }
+
+        let (_, encoder) = match &mut self.current {
+            Some(current) => current,
+            None => {
+                let path = self.dir.join(format!(
+                    "{}-{}.{FILE_EXTENSION}",
+                    self.prefix, frame.received_at
+                ));
+                let file = File::create(&path).map_err(|e| Error::Io(e.to_string()))?;
+                self.current.insert((
+                    frame.received_at,
+                    GzEncoder::new(BufWriter::new(file), Compression::default()),
+                ))
+            }
+        };
+        let line = serde_json::to_string(frame).map_err(|e| Error::JsonParse(e.to_string()))?;
+        writeln!(encoder, "{line}").map_err(|e| Error::Io(e.to_string()))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some((_, encoder)) = self.current.take() {
+            encoder
+                .finish()
+                .and_then(|mut writer| writer.flush())
+                .map_err(|e| Error::Io(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Records raw websocket frames for a set of subscriptions to rotating gzipped files.
+///
+/// Files are named `{prefix}-{first receive time}.jsonl.gz` and hold one [`RecordedFrame`] per
+/// line, so they sort chronologically and can be read back with a [`Replayer`].
+#[derive(Debug)]
+pub struct Recorder {
+    writer: FrameWriter,
+}
+
+impl Recorder {
+    pub fn new(dir: impl Into<PathBuf>, prefix: &str, rotate_every: Duration) -> Recorder {
+        Recorder {
+            writer: FrameWriter {
+                dir: dir.into(),
+                prefix: prefix.to_string(),
+                rotate_every,
+                current: None,
+            },
+        }
+    }
+
+    /// Subscribes `info_client` to `subscriptions` and records every frame it receives until
+    /// [`RecorderHandle::stop`] is called or the connection is dropped.
+    pub async fn start(
+        mut self,
+        info_client: &mut InfoClient,
+        subscriptions: Vec<Subscription>,
+    ) -> Result<RecorderHandle> {
+        fs::create_dir_all(&self.writer.dir).map_err(|e| Error::Io(e.to_string()))?;
+
+        let (frame_sender, mut frame_receiver) = unbounded_channel();
+        info_client.subscribe_raw_frames(frame_sender).await?;
+
+        // Parsed messages are only needed to keep the subscriptions alive
+        let (sender, mut receiver) = unbounded_channel();
+        for subscription in subscriptions {
+            info_client.subscribe(subscription, sender.clone()).await?;
+        }
+
+        // Files are written and compressed on a blocking thread, which always finishes the
+        // current file, and stops taking frames after the first error
+        let (write_sender, write_receiver) = mpsc::channel::<RecordedFrame>();
+        let writer = spawn_blocking(move || {
+            let written = write_receiver
+                .iter()
+                .try_for_each(|frame| self.writer.write(&frame));
+            let finished = self.writer.finish();
+            written.and(finished)
+        });
+
+        let (stop_sender, mut stop_receiver) = oneshot::channel();
+        let task = spawn(async move {
+            loop {
+                select! {
+                    frame = frame_receiver.recv() => match frame {
+                        Some(frame) => if write_sender.send(frame).is_err() {
+                            break;
+                        },
+                        None => break,
+                    },
+                    Some(_) = receiver.recv() => {}
+                    _ = &mut stop_receiver => break,
+                }
+            }
+            // The connection drops its raw frame sender once sending to the receiver fails
+            drop(frame_receiver);
+            drop(write_sender);
+            writer.await.map_err(|e| Error::Io(e.to_string()))?
+        });
+        Ok(RecorderHandle { stop_sender, task })
+    }
+}
+
+#[derive(Debug)]
+pub struct RecorderHandle {
+    stop_sender: oneshot::Sender<()>,
+    task: JoinHandle<Result<()>>,
+}
+
+impl RecorderHandle {
+    /// Stops recording and finishes the current file.
+    pub async fn stop(self) -> Result<()> {
+        let _ = self.stop_sender.send(());
+        self.task.await.map_err(|e| Error::Io(e.to_string()))?
+    }
+}
+
+/// Reads back files written by a [`Recorder`].
+#[derive(Debug, Clone)]
+pub struct Replayer {
+    files: Vec<PathBuf>,
+}
+
+impl Replayer {
+    /// Replays `files` in the given order.
+    pub fn new(files: Vec<PathBuf>) -> Replayer {
+        Replayer { files }
+    }
+
+    /// Replays all files in `dir` recorded with `prefix`, oldest first.
+    pub fn from_dir(dir: impl AsRef<Path>, prefix: &str) -> Result<Replayer> {
+        let mut files: Vec<(u64, PathBuf)> = fs::read_dir(dir)
+            .map_err(|e| Error::GenericReader(e.to_string()))?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let started_at = path
+                    .file_name()?
+                    .to_str()?
+                    .strip_prefix(prefix)?
+                    .strip_prefix('-')?
+                    .strip_suffix(FILE_EXTENSION)?
+                    .strip_suffix('.')?
+                    .parse()
+                    .ok()?;
+                Some((started_at, path))
+            })
+            .collect();
+        files.sort();
+        Ok(Replayer::new(
+            files.into_iter().map(|(_, path)| path).collect(),
+        ))
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// All recorded frames. A file cut short, e.g. by a crash while recording, ends in an error.
+    pub fn frames(&self) -> impl Iterator<Item = Result<RecordedFrame>> + '_ {
+        self.files.iter().flat_map(|path| {
+            let frames: Box<dyn Iterator<Item = Result<RecordedFrame>> + Send> =
+                match File::open(path) {
+                    Ok(file) => Box::new(BufReader::new(GzDecoder::new(file)).lines().scan(
+                        false,
+                        |failed, line| {
+                            // Stop reading a file at its first error
+                            if *failed {
+                                return None;
+                            }
+                            let frame = line
+                                .map_err(|e| Error::GenericReader(e.to_string()))
+                                .and_then(|line| {
+                                    serde_json::from_str(&line)
+                                        .map_err(|e| Error::JsonParse(e.to_string()))
+                                });
+                            *failed = frame.is_err();
+                            Some(frame)
+                        },
+                    )),
+                    Err(err) => Box::new(std::iter::once(Err(Error::GenericReader(format!(
+                        "{}: {err}",
+                        path.display()
+                    ))))),
+                };
+            frames
+        })
+    }
+
+    /// Recorded data messages, without subscription responses and pongs, as fast as they can be
+    /// read, e.g. for a [`crate::Backtest`].
+    pub fn messages(&self) -> impl Iterator<Item = Result<Message>> + '_ {
+        self.frames()
+            .map(|frame| frame?.message())
+            .filter(|message| !matches!(message, Ok(Message::SubscriptionResponse | Message::Pong)))
+    }
+
+    /// Sends recorded messages to `sender`, pacing them by their receive times divided by
+    /// `speed`: 1.0 replays at the original pace, `f64::INFINITY` without any delay. Speeds that
+    /// aren't positive are rejected, and the replay stops with the same error once a speed too
+    /// small for the delay to be represented is hit.
+    pub fn start(
+        self,
+        speed: f64,
+        sender: UnboundedSender<Message>,
+    ) -> Result<JoinHandle<Result<()>>> {
+        if speed.is_nan() || speed <= 0.0 {
+            return Err(Error::InvalidReplaySpeed(speed));
+        }
+        Ok(spawn(async move {
+            let mut last_received_at = None;
+            for frame in self.frames() {
+                let frame = match frame {
+                    Ok(frame) => frame,
+                    Err(err) => {
+                        error!("Could not read recorded frame: {err}");
+                        continue;
+                    }
+                };
+                if let Some(last_received_at) = last_received_at {
+                    let elapsed = frame.received_at.saturating_sub(last_received_at);
+                    let delay = Duration::try_from_secs_f64(elapsed as f64 / 1000.0 / speed)
+                        .map_err(|_| Error::InvalidReplaySpeed(speed))?;
+                    if !delay.is_zero() {
+                        sleep(delay).await;
+                    }
+                }
+                last_received_at = Some(frame.received_at);
+
+                match frame.message() {
+                    Ok(Message::SubscriptionResponse | Message::Pong) => {}
+                    Ok(message) => {
+                        if sender.send(message).is_err() {
+                            warn!("Replay receiver dropped, stopping replay");
+                            break;
+                        }
+                    }
+                    Err(err) => error!("Could not parse recorded frame: {err}"),
+                }
+            }
+            Ok(())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(received_at: u64, px: &str) -> RecordedFrame {
+        RecordedFrame {
+            received_at,
+            data: format!(
+                r#"{{"channel":"trades","data":[{{"coin":"ETH","side":"B","px":"{px}","sz":"1","time":{received_at},"hash":"0x0","tid":{received_at}}}]}}"#
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("recorder-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut recorder = Recorder::new(&dir, "eth", Duration::from_secs(60));
+
+        let frames = vec![
+            frame(1_000, "2000"),
+            RecordedFrame {
+                received_at: 1_500,
+                data: r#"{"channel":"pong"}"#.to_string(),
+            },
+            frame(30_000, "2001"),
+            frame(61_000, "2002"),
+            frame(200_000, "2003"),
+        ];
+        for frame in &frames {
+            recorder.writer.write(frame)?;
+        }
+        recorder.writer.finish()?;
+
+        let replayer = Replayer::from_dir(&dir, "eth")?;
+        assert_eq!(replayer.files().len(), 3);
+        assert_eq!(replayer.frames().collect::<Result<Vec<_>>>()?, frames);
+
+        let prices: Vec<String> = replayer
+            .messages()
+            .map(|message| match message? {
+                Message::Trades(trades) => Ok(trades.data[0].px.clone()),
+                message => panic!("unexpected message {message:?}"),
+            })
+            .collect::<Result<_>>()?;
+        assert_eq!(prices, ["2000", "2001", "2002", "2003"]);
+
+        for speed in [0.0, -1.0, f64::NAN] {
+            let (sender, _receiver) = tokio::sync::mpsc::unbounded_channel();
+            assert!(matches!(
+                Replayer::from_dir(&dir, "eth")?.start(speed, sender),
+                Err(Error::InvalidReplaySpeed(_))
+            ));
+        }
+        let (sender, _receiver) = tokio::sync::mpsc::unbounded_channel();
+        assert!(matches!(
+            replayer.clone().start(1e-300, sender)?.await.unwrap(),
+            Err(Error::InvalidReplaySpeed(_))
+        ));
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        replayer.start(f64::INFINITY, sender)?.await.unwrap()?;
+        let mut replayed = 0;
+        while receiver.try_recv().is_ok() {
+            replayed += 1;
+        }
+        assert_eq!(replayed, 4);
+
+        fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+}
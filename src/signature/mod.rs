@@ -1,4 +1,6 @@
 pub(crate) mod agent;
 mod create_signature;
+mod verify_signature;
 
 pub(crate) use create_signature::{sign_l1_action, sign_typed_data};
+pub use verify_signature::{verify_l1_action, verify_typed_data};
@@ -0,0 +1,148 @@
+use ethers::types::{transaction::eip712::Eip712, RecoveryMessage, Signature, H160, H256};
+
+use crate::{prelude::*, signature::agent::l1, Actions, Error};
+
+/// Recovers the address that signed an L1 action with the given nonce and vault address.
+pub fn verify_l1_action(
+    action: &Actions,
+    nonce: u64,
+    vault_address: Option<H160>,
+    signature: &Signature,
+    is_mainnet: bool,
+) -> Result<H160> {
+    let connection_id = action.hash(nonce, vault_address)?;
+    recover_l1_action_signer(connection_id, signature, is_mainnet)
+}
+
+/// Recovers the address that signed a user action such as [`crate::UsdSend`],
+/// [`crate::Withdraw3`], [`crate::SpotSend`] or [`crate::ApproveAgent`].
+pub fn verify_typed_data<T: Eip712>(payload: &T, signature: &Signature) -> Result<H160> {
+    let encoded = payload
+        .encode_eip712()
+        .map_err(|e| Error::Eip712(e.to_string()))?;
+
+    recover_hash(H256::from(encoded), signature)
+}
+
+pub(crate) fn recover_l1_action_signer(
+    connection_id: H256,
+    signature: &Signature,
+    is_mainnet: bool,
+) -> Result<H160> {
+    let source = if is_mainnet { "a" } else { "b" }.to_string();
+    verify_typed_data(
+        &l1::Agent {
+            source,
+            connection_id,
+        },
+        signature,
+    )
+}
+
+fn recover_hash(hash: H256, signature: &Signature) -> Result<H160> {
+    signature
+        .recover(RecoveryMessage::Hash(hash))
+        .map_err(|e| Error::SignatureRecovery(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        signature::{sign_l1_action, sign_typed_data},
+        ApproveAgent, BulkCancel, SpotSend, UsdSend,
+    };
+    use ethers::signers::{LocalWallet, Signer};
+
+    fn get_wallet() -> Result<LocalWallet> {
+        let priv_key = "e908f86dbb4d55ac876378565aafeabc187f6690f046459397b17d9b9a19688e";
+        priv_key
+            .parse::<LocalWallet>()
+            .map_err(|e| Error::Wallet(e.to_string()))
+    }
+
+    #[test]
+    fn test_verify_l1_action() -> Result<()> {
+        let wallet = get_wallet()?;
+        let action = Actions::Cancel(BulkCancel { cancels: vec![] });
+        let connection_id = action.hash(1583838, None)?;
+
+        for is_mainnet in [true, false] {
+            let signature = sign_l1_action(&wallet, connection_id, is_mainnet)?;
+            assert_eq!(
+                verify_l1_action(&action, 1583838, None, &signature, is_mainnet)?,
+                wallet.address()
+            );
+            // A signature for the other network or nonce recovers to a different address
+            assert_ne!(
+                verify_l1_action(&action, 1583838, None, &signature, !is_mainnet)?,
+                wallet.address()
+            );
+            assert_ne!(
+                verify_l1_action(&action, 1583839, None, &signature, is_mainnet)?,
+                wallet.address()
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_typed_data() -> Result<()> {
+        let wallet = get_wallet()?;
+
+        let usd_send = UsdSend {
+            signature_chain_id: 421614.into(),
+            hyperliquid_chain: "Testnet".to_string(),
+            destination: "0x0D1d9635D0640821d15e323ac8AdADfA9c111414".to_string(),
+            amount: "1".to_string(),
+            time: 1690393044548,
+        };
+        let signature = sign_typed_data(&usd_send, &wallet)?;
+        assert_eq!(verify_typed_data(&usd_send, &signature)?, wallet.address());
+
+        let spot_send = SpotSend {
+            signature_chain_id: 421614.into(),
+            hyperliquid_chain: "Testnet".to_string(),
+            destination: "0x0D1d9635D0640821d15e323ac8AdADfA9c111414".to_string(),
+            token: "PURR:0xc4bf3f870c0e9465323c0b6ed28096c2".to_string(),
+            amount: "1".to_string(),
+            time: 1690393044548,
+        };
+        assert_ne!(verify_typed_data(&spot_send, &signature)?, wallet.address());
+
+        let approve_agent = ApproveAgent {
+            signature_chain_id: 421614.into(),
+            hyperliquid_chain: "Testnet".to_string(),
+            agent_address: H160::zero(),
+            agent_name: Some("bot".to_string()),
+            nonce: 1690393044548,
+        };
+        let signature = sign_typed_data(&approve_agent, &wallet)?;
+        assert_eq!(
+            verify_typed_data(&approve_agent, &signature)?,
+            wallet.address()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_payload() -> Result<()> {
+        let wallet = get_wallet()?;
+        let vault_address = Some(H160::repeat_byte(7));
+
+        let action = Actions::Cancel(BulkCancel { cancels: vec![] });
+        let payload = action.sign(&wallet, 1583838, vault_address, true)?;
+        assert_eq!(payload.verify(true)?, wallet.address());
+
+        let action = Actions::UsdSend(UsdSend {
+            signature_chain_id: 421614.into(),
+            hyperliquid_chain: "Mainnet".to_string(),
+            destination: "0x0D1d9635D0640821d15e323ac8AdADfA9c111414".to_string(),
+            amount: "1".to_string(),
+            time: 1690393044548,
+        });
+        let payload = action.sign(&wallet, 1690393044548, None, true)?;
+        assert_eq!(payload.verify(true)?, wallet.address());
+        Ok(())
+    }
+}
@@ -0,0 +1,664 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use ethers::{abi::ethereum_types::H128, types::H160};
+use log::warn;
+
+use crate::{
+    helpers::now_timestamp_ms, prelude::*, Error, InfoClient, LedgerUpdate, LedgerUpdateData,
+    Message, OpenOrdersResponse, OrderUpdate, PositionData, SpotMeta, TokenInfo, TradeInfo,
+    UserStateResponse, UserTokenBalanceResponse, EPSILON,
+};
+
+const USDC: &str = "USDC";
+
+/// A spot token balance joined with the token's metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpotBalance {
+    pub coin: String,
+    /// Index of the token in the spot metadata
+    pub token: usize,
+    pub token_id: H128,
+    pub sz_decimals: u8,
+    pub wei_decimals: u8,
+    pub total: f64,
+    /// Amount held by open orders
+    pub hold: f64,
+    /// Cost of the balance in USDC
+    pub entry_ntl: f64,
+}
+
+impl SpotBalance {
+    fn new(token: &TokenInfo) -> SpotBalance {
+        SpotBalance {
+            coin: token.name.clone(),
+            token: token.index,
+            token_id: token.token_id,
+            sz_decimals: token.sz_decimals,
+            wei_decimals: token.wei_decimals,
+            total: 0.0,
+            hold: 0.0,
+            entry_ntl: 0.0,
+        }
+    }
+
+    /// Amount not held by open orders.
+    pub fn available(&self) -> f64 {
+        self.total - self.hold
+    }
+}
+
+/// Amount of a token held by a resting spot order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpotHold {
+    pub oid: u64,
+    /// Pair the order is on, e.g. `PURR/USDC` or `@107`
+    pub pair: String,
+    /// Held token: the quote token for buys, the base token for sells
+    pub coin: String,
+    pub is_buy: bool,
+    pub limit_px: f64,
+    /// Remaining size of the order
+    pub sz: f64,
+}
+
+impl SpotHold {
+    pub fn amount(&self) -> f64 {
+        if self.is_buy {
+            self.limit_px * self.sz
+        } else {
+            self.sz
+        }
+    }
+}
+
+/// A spot balance with its USDC price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpotHolding {
+    pub balance: SpotBalance,
+    /// Mid of the token's USDC pair, `None` if it has none
+    pub px: Option<f64>,
+}
+
+impl SpotHolding {
+    pub fn value(&self) -> Option<f64> {
+        self.px.map(|px| px * self.balance.total)
+    }
+}
+
+/// Combined view of an account's perp and spot holdings.
+#[derive(Debug, Clone)]
+pub struct Portfolio {
+    /// Perp account value, including unrealized PnL
+    pub perp_account_value: f64,
+    pub perp_positions: Vec<PositionData>,
+    pub spot: Vec<SpotHolding>,
+}
+
+impl Portfolio {
+    /// USDC value of the spot balances that can be priced.
+    pub fn spot_value(&self) -> f64 {
+        self.spot.iter().filter_map(SpotHolding::value).sum()
+    }
+
+    pub fn total_value(&self) -> f64 {
+        self.perp_account_value + self.spot_value()
+    }
+}
+
+/// Maintains a user's spot balances and the holds of their open orders from fills, order
+/// updates and ledger updates.
+///
+/// Subscribe to the user's fills, order updates and non-funding ledger updates before calling
+/// [`SpotBalances::sync`], so that nothing is missed in between. Snapshots sent on subscribing
+/// are skipped, as are fills and ledger updates no later than the sync, since the synced
+/// balances already include them.
+///
+/// Only the most recent [`SpotBalances::MAX_SEEN_FILLS`] fills are remembered for
+/// deduplication, oldest first out.
+#[derive(Debug)]
+pub struct SpotBalances {
+    user: H160,
+    tokens: HashMap<usize, TokenInfo>,
+    // Base and quote token of each pair by universe name, e.g. `@107`
+    universe: HashMap<String, [usize; 2]>,
+    // Same as `universe`, plus each pair by its token names, e.g. `HYPE/USDC`
+    pairs: HashMap<String, [usize; 2]>,
+    balances: HashMap<String, SpotBalance>,
+    holds: HashMap<u64, SpotHold>,
+    // Time of the balances the last sync was made from, in ms
+    sync_time: u64,
+    // Fills by trade id and oid, as both sides of a self-trade share the trade id
+    seen_fills: HashSet<(u64, u64)>,
+    // Insertion order of `seen_fills`, for evicting the oldest entries
+    seen_fills_order: VecDeque<(u64, u64)>,
+}
+
+impl SpotBalances {
+    pub const MAX_SEEN_FILLS: usize = 10_000;
+
+    pub fn new(user: H160, spot_meta: &SpotMeta) -> SpotBalances {
+        let universe: HashMap<String, [usize; 2]> = spot_meta
+            .universe
+            .iter()
+            .map(|asset| (asset.name.clone(), asset.tokens))
+            .collect();
+        let mut pairs = universe.clone();
+        let tokens: HashMap<usize, TokenInfo> = spot_meta
+            .tokens
+            .iter()
+            .map(|token| (token.index, token.clone()))
+            .collect();
+        // Pairs can also be referred to by their token names, as in `add_pair_and_name_to_index_map`
+        for asset in &spot_meta.universe {
+            if let (Some(base), Some(quote)) =
+                (tokens.get(&asset.tokens[0]), tokens.get(&asset.tokens[1]))
+            {
+                pairs.insert(format!("{}/{}", base.name, quote.name), asset.tokens);
+            }
+        }
+        SpotBalances {
+            user,
+            tokens,
+            universe,
+            pairs,
+            balances: HashMap::new(),
+            holds: HashMap::new(),
+            sync_time: 0,
+            seen_fills: HashSet::new(),
+            seen_fills_order: VecDeque::new(),
+        }
+    }
+
+    pub fn balance(&self, coin: &str) -> Option<&SpotBalance> {
+        self.balances.get(coin)
+    }
+
+    pub fn balances(&self) -> impl Iterator<Item = &SpotBalance> {
+        self.balances.values()
+    }
+
+    /// Holds of open orders on `coin`, whether it is the base or quote token of their pair.
+    pub fn holds<'a>(&'a self, coin: &'a str) -> impl Iterator<Item = &'a SpotHold> + 'a {
+        self.holds.values().filter(move |hold| hold.coin == coin)
+    }
+
+    /// Replaces balances and holds with the exchange's.
+    ///
+    /// Fills and ledger updates are skipped up to the local time the request was sent at, which
+    /// the balances surely include. Those landing while the request is in flight may be applied a
+    /// second time, and clock skew shifts the boundary; syncing again corrects both.
+    pub async fn sync(&mut self, info_client: &InfoClient) -> Result<()> {
+        let as_of = now_timestamp_ms();
+        let balances = info_client.user_token_balances(self.user).await?;
+        let open_orders = info_client.open_orders(self.user).await?;
+        self.sync_from_token_balances(&balances, as_of)?;
+        self.sync_holds(&open_orders)
+    }
+
+    /// Replaces balances with `balances`, fetched at `as_of`: fills and ledger updates up to
+    /// `as_of` are ignored from then on. Balances in tokens missing from the spot metadata, e.g.
+    /// listed since it was loaded, are skipped with a warning.
+    pub fn sync_from_token_balances(
+        &mut self,
+        balances: &UserTokenBalanceResponse,
+        as_of: u64,
+    ) -> Result<()> {
+        self.sync_time = as_of;
+        self.balances.clear();
+        for balance in &balances.balances {
+            let Some(token) = self
+                .tokens
+                .values()
+                .find(|token| token.name == balance.coin)
+            else {
+                warn!("Skipping balance of unknown spot token {}", balance.coin);
+                continue;
+            };
+            let mut spot_balance = SpotBalance::new(token);
+            spot_balance.total = parse(&balance.total)?;
+            spot_balance.hold = parse(&balance.hold)?;
+            spot_balance.entry_ntl = parse(&balance.entry_ntl)?;
+            self.balances.insert(balance.coin.clone(), spot_balance);
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the per-order breakdown of holds from open orders; perp orders are ignored.
+    pub fn sync_holds(&mut self, open_orders: &[OpenOrdersResponse]) -> Result<()> {
+        self.holds.clear();
+        for order in open_orders {
+            if let Some(hold) = self.spot_hold(
+                order.oid,
+                &order.coin,
+                &order.side,
+                &order.limit_px,
+                &order.sz,
+            )? {
+                self.holds.insert(order.oid, hold);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the user's spot fills, order updates and ledger updates; other messages are
+    /// ignored.
+    pub fn on_message(&mut self, message: &Message) -> Result<()> {
+        match message {
+            Message::UserFills(fills) => {
+                if fills.data.user != self.user || fills.data.is_snapshot == Some(true) {
+                    return Ok(());
+                }
+                for fill in &fills.data.fills {
+                    self.apply_fill(fill)?;
+                }
+            }
+            Message::OrderUpdates(updates) => {
+                for update in &updates.data {
+                    self.apply_order_update(update)?;
+                }
+            }
+            Message::UserNonFundingLedgerUpdates(updates) => {
+                if updates.data.user != self.user || updates.data.is_snapshot == Some(true) {
+                    return Ok(());
+                }
+                for update in &updates.data.non_funding_ledger_updates {
+                    self.apply_ledger_update(update)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Applies a spot fill; perp fills, fills no later than the last sync and fills already seen
+    /// are ignored.
+    pub fn apply_fill(&mut self, fill: &TradeInfo) -> Result<()> {
+        let Some([base, quote]) = self.pairs.get(&fill.coin).copied() else {
+            return Ok(());
+        };
+        let key = (fill.tid, fill.oid);
+        if fill.time <= self.sync_time || self.seen_fills.contains(&key) {
+            return Ok(());
+        }
+        let (px, sz, fee) = (parse(&fill.px)?, parse(&fill.sz)?, parse(&fill.fee)?);
+        self.seen_fills.insert(key);
+        self.seen_fills_order.push_back(key);
+        if self.seen_fills_order.len() > Self::MAX_SEEN_FILLS {
+            if let Some(oldest) = self.seen_fills_order.pop_front() {
+                self.seen_fills.remove(&oldest);
+            }
+        }
+
+        let is_buy = fill.side == "B";
+        let base = self.balance_mut(base)?;
+        if is_buy {
+            base.total += sz;
+            base.entry_ntl += px * sz;
+        } else {
+            if base.total > EPSILON {
+                base.entry_ntl *= (1.0 - sz / base.total).max(0.0);
+            }
+            base.total -= sz;
+        }
+        let quote = self.balance_mut(quote)?;
+        quote.total += if is_buy { -px * sz } else { px * sz };
+
+        if fee.abs() > EPSILON {
+            let fee_token = self.token_index(&fill.fee_token)?;
+            self.balance_mut(fee_token)?.total -= fee;
+        }
+
+        // Filling releases the order's hold on what was spent
+        if let Some(hold) = self.holds.get_mut(&fill.oid) {
+            let before = hold.amount();
+            hold.sz = (hold.sz - sz).max(0.0);
+            let released = before - hold.amount();
+            let (coin, remaining) = (hold.coin.clone(), hold.sz);
+            if remaining < EPSILON {
+                self.holds.remove(&fill.oid);
+            }
+            if let Some(balance) = self.balances.get_mut(&coin) {
+                balance.hold = (balance.hold - released).max(0.0);
+            }
+        }
+        Ok(())
+    }
+
+    /// Tracks the hold of an order while it is open; perp orders are ignored.
+    pub fn apply_order_update(&mut self, update: &OrderUpdate) -> Result<()> {
+        let order = &update.order;
+        let hold = if update.status == "open" {
+            self.spot_hold(
+                order.oid,
+                &order.coin,
+                &order.side,
+                &order.limit_px,
+                &order.sz,
+            )?
+        } else {
+            None
+        };
+
+        if let Some(previous) = self.holds.remove(&order.oid) {
+            if let Some(balance) = self.balances.get_mut(&previous.coin) {
+                balance.hold = (balance.hold - previous.amount()).max(0.0);
+            }
+        }
+        if let Some(hold) = hold {
+            let token = self.token_index(&hold.coin)?;
+            self.balance_mut(token)?.hold += hold.amount();
+            self.holds.insert(order.oid, hold);
+        }
+        Ok(())
+    }
+
+    /// Applies transfers in and out of the user's spot account; other updates and updates no
+    /// later than the last sync are ignored.
+    pub fn apply_ledger_update(&mut self, update: &LedgerUpdateData) -> Result<()> {
+        if update.time <= self.sync_time {
+            return Ok(());
+        }
+        match &update.delta {
+            LedgerUpdate::SpotTransfer(transfer) => {
+                // Tokens may be given as `name:token id`
+                let name = transfer.token.split(':').next().unwrap_or_default();
+                let token = self.token_index(name)?;
+                let amount = parse(&transfer.amount)?;
+                if transfer.user == self.user {
+                    self.balance_mut(token)?.total -= amount;
+                    let fee = parse(&transfer.fee)?;
+                    if fee.abs() > EPSILON {
+                        self.usdc_mut()?.total -= fee;
+                    }
+                }
+                if transfer.destination == self.user {
+                    self.balance_mut(token)?.total += amount;
+                }
+            }
+            LedgerUpdate::AccountClassTransfer(transfer) => {
+                let usdc = parse(&transfer.usdc)?;
+                self.usdc_mut()?.total += if transfer.to_perp { -usdc } else { usdc };
+            }
+            LedgerUpdate::SpotGenesis(genesis) => {
+                let token = self.token_index(&genesis.token)?;
+                self.balance_mut(token)?.total += parse(&genesis.amount)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Values spot balances at the mids of their USDC pairs, next to the perp account.
+    pub fn portfolio(
+        &self,
+        user_state: &UserStateResponse,
+        mids: &HashMap<String, String>,
+    ) -> Result<Portfolio> {
+        let usdc = self.token_index(USDC).ok();
+        let mut spot = Vec::new();
+        for balance in self.balances.values() {
+            let px = if balance.coin == USDC {
+                Some(1.0)
+            } else {
+                // All mids are keyed by universe name only
+                self.universe
+                    .iter()
+                    .find(|(_, tokens)| tokens[0] == balance.token && Some(tokens[1]) == usdc)
+                    .and_then(|(pair, _)| mids.get(pair))
+                    .map(|mid| parse(mid))
+                    .transpose()?
+            };
+            spot.push(SpotHolding {
+                balance: balance.clone(),
+                px,
+            });
+        }
+        spot.sort_by_key(|holding| holding.balance.token);
+
+        Ok(Portfolio {
+            perp_account_value: parse(&user_state.margin_summary.account_value)?,
+            perp_positions: user_state
+                .asset_positions
+                .iter()
+                .map(|asset_position| asset_position.position.clone())
+                .collect(),
+            spot,
+        })
+    }
+
+    fn spot_hold(
+        &self,
+        oid: u64,
+        pair: &str,
+        side: &str,
+        limit_px: &str,
+        sz: &str,
+    ) -> Result<Option<SpotHold>> {
+        let Some(tokens) = self.pairs.get(pair) else {
+            return Ok(None);
+        };
+        let is_buy = side == "B";
+        let held = if is_buy { tokens[1] } else { tokens[0] };
+        let coin = self
+            .tokens
+            .get(&held)
+            .map(|token| token.name.clone())
+            .ok_or(Error::AssetNotFound)?;
+        Ok(Some(SpotHold {
+            oid,
+            pair: pair.to_string(),
+            coin,
+            is_buy,
+            limit_px: parse(limit_px)?,
+            sz: parse(sz)?,
+        }))
+    }
+
+    fn token_index(&self, name: &str) -> Result<usize> {
+        self.tokens
+            .values()
+            .find(|token| token.name == name)
+            .map(|token| token.index)
+            .ok_or(Error::AssetNotFound)
+    }
+
+    fn balance_mut(&mut self, token: usize) -> Result<&mut SpotBalance> {
+        let token = self.tokens.get(&token).ok_or(Error::AssetNotFound)?;
+        Ok(self
+            .balances
+            .entry(token.name.clone())
+            .or_insert_with(|| SpotBalance::new(token)))
+    }
+
+    fn usdc_mut(&mut self) -> Result<&mut SpotBalance> {
+        let token = self.token_index(USDC)?;
+        self.balance_mut(token)
+    }
+}
+
+fn parse(value: &str) -> Result<f64> {
+    value.parse().map_err(|_| Error::FloatStringParse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spot_balances(user: H160) -> SpotBalances {
+        let spot_meta: SpotMeta = serde_json::from_value(serde_json::json!({
+            "universe": [
+                {"tokens": [1, 0], "name": "PURR/USDC", "index": 0, "isCanonical": true},
+                {"tokens": [2, 0], "name": "@1", "index": 1, "isCanonical": false}
+            ],
+            "tokens": [
+                {"name": "USDC", "szDecimals": 8, "weiDecimals": 8, "index": 0,
+                 "tokenId": "0x6d1e7cde53ba9467b783cb7c530ce054", "isCanonical": true},
+                {"name": "PURR", "szDecimals": 0, "weiDecimals": 5, "index": 1,
+                 "tokenId": "0xc1fb593aeffbeb02f85e0308e9956a90", "isCanonical": true},
+                {"name": "HFUN", "szDecimals": 2, "weiDecimals": 8, "index": 2,
+                 "tokenId": "0xbaf265ef389da684513d98d68edf4eae", "isCanonical": false}
+            ]
+        }))
+        .unwrap();
+        SpotBalances::new(user, &spot_meta)
+    }
+
+    fn message(value: serde_json::Value) -> Message {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_spot_balances() -> Result<()> {
+        let user: H160 = "0x0000000000000000000000000000000000000001"
+            .parse()
+            .unwrap();
+        let mut balances = spot_balances(user);
+        balances.sync_from_token_balances(
+            &serde_json::from_value(serde_json::json!({"balances": [
+                {"coin": "USDC", "hold": "20.0", "total": "100.0", "entryNtl": "0.0"}
+            ]}))
+            .unwrap(),
+            0,
+        )?;
+        balances.sync_holds(
+            &serde_json::from_value::<Vec<OpenOrdersResponse>>(serde_json::json!([
+                {"coin": "PURR/USDC", "limitPx": "0.2", "oid": 1, "side": "B", "sz": "100",
+                 "timestamp": 0},
+                {"coin": "ETH", "limitPx": "2000", "oid": 2, "side": "B", "sz": "1", "timestamp": 0}
+            ]))
+            .unwrap(),
+        )?;
+        let usdc = balances.balance("USDC").unwrap();
+        assert_eq!(
+            usdc.token_id,
+            "0x6d1e7cde53ba9467b783cb7c530ce054".parse().unwrap()
+        );
+        assert_eq!(usdc.available(), 80.0);
+        assert_eq!(balances.holds("USDC").count(), 1);
+
+        // Half of the buy fills, releasing half of its hold
+        let fill = serde_json::json!({
+            "coin": "PURR/USDC", "side": "B", "px": "0.2", "sz": "50", "time": 1, "hash": "0x",
+            "startPosition": "0", "dir": "Buy", "closedPnl": "0", "oid": 1, "cloid": null,
+            "crossed": false, "fee": "0.05", "feeToken": "PURR", "tid": 7
+        });
+        let fills = serde_json::json!({"channel": "userFills", "data": {
+            "isSnapshot": false, "user": user, "fills": [fill, fill]
+        }});
+        balances.on_message(&message(fills))?;
+        let purr = balances.balance("PURR").unwrap();
+        assert!((purr.total - 49.95).abs() < EPSILON);
+        assert!((purr.entry_ntl - 10.0).abs() < EPSILON);
+        let usdc = balances.balance("USDC").unwrap();
+        assert!((usdc.total - 90.0).abs() < EPSILON);
+        assert!((usdc.hold - 10.0).abs() < EPSILON);
+
+        // The rest is canceled and a sell is placed
+        balances.on_message(&message(
+            serde_json::json!({"channel": "orderUpdates", "data": [
+                {"order": {"coin": "PURR/USDC", "side": "B", "limitPx": "0.2", "sz": "50", "oid": 1,
+                 "timestamp": 0, "origSz": "100", "cloid": null},
+                 "status": "canceled", "statusTimestamp": 0},
+                {"order": {"coin": "PURR/USDC", "side": "A", "limitPx": "0.3", "sz": "40", "oid": 3,
+                 "timestamp": 0, "origSz": "40", "cloid": null},
+                 "status": "open", "statusTimestamp": 0}
+            ]}),
+        ))?;
+        assert!(balances.balance("USDC").unwrap().hold.abs() < EPSILON);
+        assert!((balances.balance("PURR").unwrap().available() - 9.95).abs() < EPSILON);
+
+        balances.on_message(&message(serde_json::json!({
+            "channel": "userNonFundingLedgerUpdates",
+            "data": {"isSnapshot": false, "user": user, "nonFundingLedgerUpdates": [
+                {"time": 1, "hash": "0x", "delta": {"type": "accountClassTransfer", "usdc": "10.0",
+                 "toPerp": true}}
+            ]}
+        })))?;
+
+        let margin = serde_json::json!({
+            "accountValue": "500.0", "totalMarginUsed": "0", "totalNtlPos": "0", "totalRawUsd": "0"
+        });
+        let user_state: UserStateResponse = serde_json::from_value(serde_json::json!({
+            "assetPositions": [], "crossMarginSummary": margin, "marginSummary": margin,
+            "withdrawable": "0"
+        }))
+        .unwrap();
+        let portfolio = balances.portfolio(
+            &user_state,
+            &HashMap::from([("PURR/USDC".to_string(), "0.4".to_string())]),
+        )?;
+        assert_eq!(portfolio.spot.len(), 2);
+        assert!((portfolio.spot_value() - (80.0 + 49.95 * 0.4)).abs() < EPSILON);
+        assert!((portfolio.total_value() - (580.0 + 49.95 * 0.4)).abs() < EPSILON);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_time_and_self_trades() -> Result<()> {
+        let user: H160 = "0x0000000000000000000000000000000000000001"
+            .parse()
+            .unwrap();
+        let mut balances = spot_balances(user);
+        balances.sync_from_token_balances(
+            &serde_json::from_value(serde_json::json!({"balances": [
+                {"coin": "USDC", "hold": "0.0", "total": "100.0", "entryNtl": "0.0"},
+                {"coin": "HFUN", "hold": "0.0", "total": "2.0", "entryNtl": "8.0"},
+                {"coin": "NEW", "hold": "0.0", "total": "5.0", "entryNtl": "1.0"}
+            ]}))
+            .unwrap(),
+            1000,
+        )?;
+        assert_eq!(balances.balances().count(), 2);
+
+        let fill = |time, tid, oid, side| {
+            serde_json::json!({
+                "coin": "@1", "side": side, "px": "5.0", "sz": "1", "time": time, "hash": "0x",
+                "startPosition": "0", "dir": "Buy", "closedPnl": "0", "oid": oid, "cloid": null,
+                "crossed": false, "fee": "0.01", "feeToken": "USDC", "tid": tid
+            })
+        };
+        // The fill before the sync is already included, both sides of the self-trade apply once
+        let fills = serde_json::json!({"channel": "userFills", "data": {
+            "isSnapshot": false, "user": user,
+            "fills": [fill(900, 1, 1, "B"), fill(2000, 2, 2, "B"), fill(2000, 2, 3, "A"),
+                      fill(2000, 2, 3, "A")]
+        }});
+        balances.on_message(&message(fills))?;
+        assert!((balances.balance("HFUN").unwrap().total - 2.0).abs() < EPSILON);
+        assert!((balances.balance("USDC").unwrap().total - 99.98).abs() < EPSILON);
+
+        balances.on_message(&message(serde_json::json!({
+            "channel": "userNonFundingLedgerUpdates",
+            "data": {"isSnapshot": false, "user": user, "nonFundingLedgerUpdates": [
+                {"time": 1000, "hash": "0x", "delta": {"type": "accountClassTransfer",
+                 "usdc": "10.0", "toPerp": true}}
+            ]}
+        })))?;
+        assert!((balances.balance("USDC").unwrap().total - 99.98).abs() < EPSILON);
+
+        // Mids are keyed by universe name, not by the `HFUN/USDC` alias
+        let margin = serde_json::json!({
+            "accountValue": "0.0", "totalMarginUsed": "0", "totalNtlPos": "0", "totalRawUsd": "0"
+        });
+        let user_state: UserStateResponse = serde_json::from_value(serde_json::json!({
+            "assetPositions": [], "crossMarginSummary": margin, "marginSummary": margin,
+            "withdrawable": "0"
+        }))
+        .unwrap();
+        let portfolio = balances.portfolio(
+            &user_state,
+            &HashMap::from([("@1".to_string(), "6.0".to_string())]),
+        )?;
+        assert!((portfolio.spot_value() - (99.98 + 12.0)).abs() < EPSILON);
+
+        for tid in 0..SpotBalances::MAX_SEEN_FILLS as u64 + 10 {
+            balances.apply_fill(&serde_json::from_value(fill(3000, tid, 4, "B")).unwrap())?;
+        }
+        assert_eq!(balances.seen_fills.len(), SpotBalances::MAX_SEEN_FILLS);
+        assert_eq!(
+            balances.seen_fills_order.len(),
+            SpotBalances::MAX_SEEN_FILLS
+        );
+        Ok(())
+    }
+}
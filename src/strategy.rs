@@ -0,0 +1,416 @@
+use std::{
+    collections::HashMap,
+    future::{pending, Future},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use ethers::{signers::Signer, types::H160};
+use log::{debug, error, warn};
+use tokio::{
+    select, spawn,
+    sync::mpsc::unbounded_channel,
+    time::{self, Interval, MissedTickBehavior},
+};
+
+use crate::{
+    prelude::*, ExchangeClient, InfoClient, L2BookData, Message, OrderUpdate, Subscription, Trade,
+    TradeInfo,
+};
+
+/// What a [`Strategy`] has access to from its callbacks.
+#[derive(Debug)]
+pub struct StrategyContext {
+    pub exchange_client: ExchangeClient,
+    /// Account the strategy trades for, i.e. the vault if one is set, otherwise the wallet
+    pub user: H160,
+    shutdown_requested: AtomicBool,
+}
+
+impl StrategyContext {
+    pub fn new(exchange_client: ExchangeClient) -> StrategyContext {
+        let user = exchange_client
+            .vault_address
+            .unwrap_or_else(|| exchange_client.wallet.address());
+        StrategyContext {
+            exchange_client,
+            user,
+            shutdown_requested: AtomicBool::new(false),
+        }
+    }
+
+    /// Asks the runtime to shut down once the current callback returns.
+    pub fn request_shutdown(&self) {
+        self.shutdown_requested.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutdown_requested(&self) -> bool {
+        self.shutdown_requested.load(Ordering::SeqCst)
+    }
+}
+
+/// Event handlers of a trading strategy run by a [`StrategyRuntime`].
+///
+/// The same strategy runs live or against a [`crate::PaperExchange`] set on the exchange client.
+/// All callbacks default to doing nothing. Errors returned from them are logged and the strategy
+/// keeps running.
+pub trait Strategy: Send {
+    /// Market data subscriptions to make. The user's order updates and fills are always
+    /// delivered, from the paper exchange if the exchange client has one.
+    fn subscriptions(&self) -> Vec<Subscription>;
+
+    fn on_book(
+        &mut self,
+        _book: &L2BookData,
+        _context: &StrategyContext,
+    ) -> impl Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    fn on_trade(
+        &mut self,
+        _trade: &Trade,
+        _context: &StrategyContext,
+    ) -> impl Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    fn on_mids(
+        &mut self,
+        _mids: &HashMap<String, String>,
+        _context: &StrategyContext,
+    ) -> impl Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Called for new fills; the snapshot of past fills sent on subscribing is skipped.
+    fn on_fill(
+        &mut self,
+        _fill: &TradeInfo,
+        _context: &StrategyContext,
+    ) -> impl Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    fn on_order_update(
+        &mut self,
+        _update: &OrderUpdate,
+        _context: &StrategyContext,
+    ) -> impl Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Called every [`StrategyRuntime::with_timer`] interval.
+    fn on_timer(&mut self, _context: &StrategyContext) -> impl Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Called once when the websocket connection drops, and again only after data was received
+    /// in between. Subscriptions are restored automatically if the info client was created with
+    /// reconnection enabled.
+    fn on_disconnect(
+        &mut self,
+        _context: &StrategyContext,
+    ) -> impl Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Called once before the runtime exits, e.g. to cancel open orders.
+    fn on_shutdown(
+        &mut self,
+        _context: &StrategyContext,
+    ) -> impl Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+}
+
+/// Wires websocket subscriptions and an [`ExchangeClient`] into a [`Strategy`].
+#[derive(Debug)]
+pub struct StrategyRuntime {
+    info_client: InfoClient,
+    context: StrategyContext,
+    timer_interval: Option<Duration>,
+    // Every subscription is told about a disconnect, this keeps it to one callback per outage
+    disconnected: bool,
+}
+
+impl StrategyRuntime {
+    pub fn new(info_client: InfoClient, exchange_client: ExchangeClient) -> StrategyRuntime {
+        StrategyRuntime {
+            info_client,
+            context: StrategyContext::new(exchange_client),
+            timer_interval: None,
+            disconnected: false,
+        }
+    }
+
+    /// Calls [`Strategy::on_timer`] every `interval`.
+    pub fn with_timer(mut self, interval: Duration) -> StrategyRuntime {
+        self.timer_interval = Some(interval);
+        self
+    }
+
+    pub fn context(&self) -> &StrategyContext {
+        &self.context
+    }
+
+    /// Runs `strategy` until `shutdown` completes, a callback requests a shutdown or the
+    /// subscriptions end, then calls [`Strategy::on_shutdown`].
+    ///
+    /// `shutdown` is typically `tokio::signal::ctrl_c()`.
+    pub async fn run<S: Strategy, F: Future>(
+        mut self,
+        strategy: &mut S,
+        shutdown: F,
+    ) -> Result<()> {
+        let (sender, mut receiver) = unbounded_channel();
+        let user = self.context.user;
+        let mut subscriptions = strategy.subscriptions();
+        match &self.context.exchange_client.paper_exchange {
+            // Paper orders never reach the exchange, so their updates and fills come from the
+            // paper exchange
+            Some(paper_exchange) => {
+                let mut paper_receiver = paper_exchange.subscribe();
+                let sender = sender.clone();
+                spawn(async move {
+                    while let Some(message) = paper_receiver.recv().await {
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            None => subscriptions.extend([
+                Subscription::OrderUpdates { user },
+                Subscription::UserFills { user },
+            ]),
+        }
+        for subscription in subscriptions {
+            self.info_client
+                .subscribe(subscription, sender.clone())
+                .await?;
+        }
+        drop(sender);
+
+        let mut timer = self.timer_interval.map(|interval| {
+            let mut timer = time::interval(interval);
+            timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            timer
+        });
+        tokio::pin!(shutdown);
+
+        while !self.context.is_shutdown_requested() {
+            select! {
+                message = receiver.recv() => match message {
+                    Some(message) => self.dispatch(strategy, message).await,
+                    None => {
+                        warn!("Strategy subscriptions ended");
+                        break;
+                    }
+                },
+                _ = tick(&mut timer) => {
+                    log_error("on_timer", strategy.on_timer(&self.context).await);
+                }
+                _ = &mut shutdown => break,
+            }
+        }
+
+        strategy.on_shutdown(&self.context).await
+    }
+
+    async fn dispatch<S: Strategy>(&mut self, strategy: &mut S, message: Message) {
+        match message {
+            Message::NoData if self.disconnected => return,
+            Message::NoData => self.disconnected = true,
+            Message::HyperliquidError(_) => {}
+            _ => self.disconnected = false,
+        }
+        let context = &self.context;
+        match message {
+            Message::L2Book(book) => {
+                log_error("on_book", strategy.on_book(&book.data, context).await)
+            }
+            Message::Trades(trades) => {
+                for trade in &trades.data {
+                    log_error("on_trade", strategy.on_trade(trade, context).await);
+                }
+            }
+            Message::AllMids(all_mids) => log_error(
+                "on_mids",
+                strategy.on_mids(&all_mids.data.mids, context).await,
+            ),
+            Message::UserFills(fills) => {
+                if fills.data.is_snapshot == Some(true) {
+                    return;
+                }
+                for fill in &fills.data.fills {
+                    log_error("on_fill", strategy.on_fill(fill, context).await);
+                }
+            }
+            Message::OrderUpdates(updates) => {
+                for update in &updates.data {
+                    log_error(
+                        "on_order_update",
+                        strategy.on_order_update(update, context).await,
+                    );
+                }
+            }
+            Message::NoData => log_error("on_disconnect", strategy.on_disconnect(context).await),
+            Message::HyperliquidError(err) => error!("Websocket error: {err}"),
+            message => debug!("Strategy runtime ignoring {message:?}"),
+        }
+    }
+}
+
+// Never completes without a timer
+async fn tick(timer: &mut Option<Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => pending().await,
+    }
+}
+
+fn log_error(callback: &str, result: Result<()>) {
+    if let Err(err) = result {
+        error!("Strategy {callback} failed: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        BaseUrl, BookLevel, ClientLimit, ClientOrder, ClientOrderRequest, L2Book, PaperExchange,
+    };
+
+    // Buys once and stops after its fill
+    #[derive(Default)]
+    struct BuyOnce {
+        ordered: bool,
+        updates: usize,
+        fills: usize,
+        disconnects: usize,
+    }
+
+    impl BuyOnce {
+        async fn buy(&mut self, context: &StrategyContext) -> Result<()> {
+            if self.ordered {
+                return Ok(());
+            }
+            self.ordered = true;
+            context
+                .exchange_client
+                .order(
+                    ClientOrderRequest {
+                        asset: "ETH".to_string(),
+                        is_buy: true,
+                        reduce_only: false,
+                        limit_px: 2010.0,
+                        sz: 1.0,
+                        cloid: None,
+                        order_type: ClientOrder::Limit(ClientLimit {
+                            tif: "Ioc".to_string(),
+                        }),
+                    },
+                    None,
+                )
+                .await?;
+            Ok(())
+        }
+    }
+
+    impl Strategy for BuyOnce {
+        fn subscriptions(&self) -> Vec<Subscription> {
+            Vec::new()
+        }
+
+        async fn on_book(&mut self, _book: &L2BookData, context: &StrategyContext) -> Result<()> {
+            self.buy(context).await
+        }
+
+        async fn on_timer(&mut self, context: &StrategyContext) -> Result<()> {
+            self.buy(context).await
+        }
+
+        async fn on_order_update(
+            &mut self,
+            _update: &OrderUpdate,
+            _context: &StrategyContext,
+        ) -> Result<()> {
+            self.updates += 1;
+            Ok(())
+        }
+
+        async fn on_fill(&mut self, _fill: &TradeInfo, context: &StrategyContext) -> Result<()> {
+            self.fills += 1;
+            context.request_shutdown();
+            Ok(())
+        }
+
+        async fn on_disconnect(&mut self, _context: &StrategyContext) -> Result<()> {
+            self.disconnects += 1;
+            Ok(())
+        }
+    }
+
+    fn book() -> Message {
+        let level = |px: &str| {
+            vec![BookLevel {
+                px: px.to_string(),
+                sz: "10".to_string(),
+                n: 1,
+            }]
+        };
+        Message::L2Book(L2Book {
+            data: L2BookData {
+                coin: "ETH".to_string(),
+                time: 1,
+                levels: vec![level("1999"), level("2001")],
+            },
+        })
+    }
+
+    async fn paper_runtime() -> Result<(StrategyRuntime, Arc<PaperExchange>)> {
+        let (exchange_client, paper_exchange) = ExchangeClient::paper_test_client().await?;
+        let info_client =
+            InfoClient::from_network_config(None, BaseUrl::Localhost.into(), false).await?;
+        Ok((
+            StrategyRuntime::new(info_client, exchange_client),
+            paper_exchange,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_run_against_paper_exchange() -> Result<()> {
+        let (runtime, paper_exchange) = paper_runtime().await?;
+        paper_exchange.on_message(&book())?;
+        let mut strategy = BuyOnce::default();
+        runtime
+            .with_timer(Duration::from_millis(10))
+            .run(&mut strategy, time::sleep(Duration::from_secs(5)))
+            .await?;
+        assert_eq!(strategy.fills, 1);
+        assert_eq!(strategy.updates, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_one_disconnect_per_outage() -> Result<()> {
+        let (mut runtime, _) = paper_runtime().await?;
+        let mut strategy = BuyOnce::default();
+        // Each of the runtime's subscriptions is sent the disconnect
+        for message in [Message::NoData, Message::NoData, Message::NoData] {
+            runtime.dispatch(&mut strategy, message).await;
+        }
+        assert_eq!(strategy.disconnects, 1);
+
+        runtime.dispatch(&mut strategy, book()).await;
+        runtime.dispatch(&mut strategy, Message::NoData).await;
+        assert_eq!(strategy.disconnects, 2);
+        Ok(())
+    }
+}
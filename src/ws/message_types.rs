@@ -11,6 +11,11 @@ pub struct L2Book {
     pub data: L2BookData,
 }
 
+#[derive(Deserialize, Clone, Debug)]
+pub struct Bbo {
+    pub data: BboData,
+}
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct AllMids {
     pub data: AllMidsData,
@@ -53,10 +58,16 @@ pub struct Notification {
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct WebData2 {
-    pub data: WebData2Data,
+    // Boxed as the payload is much larger than other messages
+    pub data: Box<WebData2Data>,
 }
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct ActiveAssetCtx {
     pub data: ActiveAssetCtxData,
 }
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ActiveAssetData {
+    pub data: ActiveAssetDataData,
+}
@@ -1,3 +1,7 @@
+use crate::{
+    prelude::*, BasicOrderInfo, Cloid, Error, Leverage, Meta, UserStateResponse,
+    UserTokenBalanceResponse,
+};
 use ethers::types::H160;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -27,6 +31,24 @@ pub struct L2BookData {
     pub levels: Vec<Vec<BookLevel>>,
 }
 
+#[derive(Deserialize, Clone, Debug)]
+pub struct BboData {
+    pub coin: String,
+    pub time: u64,
+    /// Best bid and best ask, `None` for an empty side
+    pub bbo: [Option<BookLevel>; 2],
+}
+
+impl BboData {
+    pub fn bid(&self) -> Option<&BookLevel> {
+        self.bbo[0].as_ref()
+    }
+
+    pub fn ask(&self) -> Option<&BookLevel> {
+        self.bbo[1].as_ref()
+    }
+}
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct AllMidsData {
     pub mids: HashMap<String, String>,
@@ -45,7 +67,7 @@ pub struct TradeInfo {
     pub dir: String,
     pub closed_pnl: String,
     pub oid: u64,
-    pub cloid: Option<String>,
+    pub cloid: Option<Cloid>,
     pub crossed: bool,
     pub fee: String,
     pub fee_token: String,
@@ -126,7 +148,7 @@ pub struct BasicOrder {
     pub oid: u64,
     pub timestamp: u64,
     pub orig_sz: String,
-    pub cloid: Option<String>,
+    pub cloid: Option<Cloid>,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -279,6 +301,39 @@ pub struct NotificationData {
 #[serde(rename_all = "camelCase")]
 pub struct WebData2Data {
     pub user: H160,
+    pub clearinghouse_state: UserStateResponse,
+    /// Resting orders, including trigger orders and their TP/SL children
+    #[serde(default)]
+    pub open_orders: Vec<BasicOrderInfo>,
+    /// Perp universe that `asset_ctxs` is indexed by
+    #[serde(default)]
+    pub meta: Option<Meta>,
+    #[serde(default)]
+    pub asset_ctxs: Vec<PerpsAssetCtx>,
+    pub server_time: u64,
+    #[serde(default)]
+    pub is_vault: bool,
+    #[serde(default)]
+    pub agent_address: Option<H160>,
+    #[serde(default)]
+    pub agent_valid_until: Option<u64>,
+    /// Spot balances, absent for users that never held spot tokens
+    #[serde(default)]
+    pub spot_state: Option<UserTokenBalanceResponse>,
+    #[serde(default)]
+    pub spot_asset_ctxs: Vec<SpotAssetCtx>,
+}
+
+impl WebData2Data {
+    pub fn asset_ctx(&self, coin: &str) -> Option<&PerpsAssetCtx> {
+        let index = self
+            .meta
+            .as_ref()?
+            .universe
+            .iter()
+            .position(|asset| asset.name == coin)?;
+        self.asset_ctxs.get(index)
+    }
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -288,6 +343,36 @@ pub struct ActiveAssetCtxData {
     pub ctx: AssetCtx,
 }
 
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveAssetDataData {
+    pub user: H160,
+    pub coin: String,
+    pub leverage: Leverage,
+    /// Max buy and sell sizes at the current leverage
+    pub max_trade_szs: [String; 2],
+    /// Buy and sell notional available to trade
+    pub available_to_trade: [String; 2],
+    #[serde(default)]
+    pub mark_px: Option<String>,
+}
+
+impl ActiveAssetDataData {
+    pub fn max_trade_sz_for(&self, is_buy: bool) -> Result<f64> {
+        side_value(&self.max_trade_szs, is_buy)
+    }
+
+    pub fn available_to_trade_for(&self, is_buy: bool) -> Result<f64> {
+        side_value(&self.available_to_trade, is_buy)
+    }
+}
+
+fn side_value(values: &[String; 2], is_buy: bool) -> Result<f64> {
+    values[if is_buy { 0 } else { 1 }]
+        .parse()
+        .map_err(|_| Error::FloatStringParse)
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 #[serde(untagged)]
@@ -322,3 +407,57 @@ pub struct SpotAssetCtx {
     pub shared: SharedAssetCtx,
     pub circulating_supply: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::Message;
+
+    #[test]
+    fn test_web_data2_parsing() {
+        let message: Message = serde_json::from_str(
+            r#"{"channel":"webData2","data":{
+            "clearinghouseState":{"marginSummary":{"accountValue":"1000.0","totalNtlPos":"200.0",
+            "totalRawUsd":"800.0","totalMarginUsed":"20.0"},"crossMarginSummary":{
+            "accountValue":"1000.0","totalNtlPos":"200.0","totalRawUsd":"800.0","totalMarginUsed":"20.0"},
+            "crossMaintenanceMarginUsed":"5.0","withdrawable":"980.0","assetPositions":[{"type":"oneWay",
+            "position":{"coin":"ETH","szi":"0.1","leverage":{"type":"cross","value":10},"entryPx":"2000.0",
+            "positionValue":"200.0","unrealizedPnl":"0.0","returnOnEquity":"0.0","liquidationPx":null,
+            "marginUsed":"20.0","maxLeverage":50,"cumFunding":{"allTime":"0.0","sinceOpen":"0.0",
+            "sinceChange":"0.0"}}}],"time":1700000000000},
+            "leadingVaults":[],"totalVaultEquity":"0.0",
+            "openOrders":[{"coin":"ETH","side":"B","limitPx":"1900.0","sz":"0.1","oid":1,
+            "timestamp":1700000000000,"triggerCondition":"N/A","isTrigger":false,"triggerPx":"0.0",
+            "children":[],"isPositionTpsl":false,"reduceOnly":false,"orderType":"Limit","origSz":"0.1",
+            "tif":"Gtc","cloid":null}],
+            "agentAddress":null,"agentValidUntil":null,"cumLedger":"1000.0",
+            "meta":{"universe":[{"szDecimals":5,"name":"BTC","maxLeverage":50},
+            {"szDecimals":4,"name":"ETH","maxLeverage":50}]},
+            "assetCtxs":[{"funding":"0.0000125","openInterest":"100.0","prevDayPx":"40000.0",
+            "dayNtlVlm":"1000000.0","premium":"0.0","oraclePx":"40010.0","markPx":"40005.0",
+            "midPx":"40004.5","impactPxs":["40004.0","40005.0"]},{"funding":"0.0000125",
+            "openInterest":"1000.0","prevDayPx":"2010.0","dayNtlVlm":"500000.0","premium":"0.0",
+            "oraclePx":"2001.0","markPx":"2000.5","midPx":null,"impactPxs":null}],
+            "serverTime":1700000000123,"isVault":false,
+            "user":"0x0000000000000000000000000000000000000001","twapStates":[],
+            "spotState":{"balances":[{"coin":"USDC","token":0,"hold":"0.0","total":"50.0",
+            "entryNtl":"0.0"}]}}}"#,
+        )
+        .unwrap();
+        let Message::WebData2(web_data2) = message else {
+            panic!("unexpected message {message:?}");
+        };
+        let data = web_data2.data;
+
+        assert_eq!(data.clearinghouse_state.withdrawable, "980.0");
+        assert_eq!(
+            data.clearinghouse_state.asset_positions[0].position.szi,
+            "0.1"
+        );
+        assert_eq!(data.open_orders[0].oid, 1);
+        assert_eq!(data.asset_ctx("ETH").unwrap().oracle_px, "2001.0");
+        assert_eq!(data.asset_ctx("SOL").map(|ctx| &ctx.oracle_px), None);
+        assert_eq!(data.server_time, 1700000000123);
+        assert_eq!(data.spot_state.unwrap().balances[0].total, "50.0");
+        assert!(data.spot_asset_ctxs.is_empty());
+    }
+}
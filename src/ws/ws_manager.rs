@@ -1,8 +1,9 @@
 use crate::{
+    helpers::now_timestamp_ms,
     prelude::*,
-    ws::message_types::{AllMids, Candle, L2Book, OrderUpdates, Trades, User},
-    ActiveAssetCtx, Error, Notification, UserFills, UserFundings, UserNonFundingLedgerUpdates,
-    WebData2,
+    ws::message_types::{AllMids, Bbo, Candle, L2Book, OrderUpdates, Trades, User},
+    ActiveAssetCtx, ActiveAssetData, CandleInterval, Error, Notification, RecordedFrame, UserFills,
+    UserFundings, UserNonFundingLedgerUpdates, WebData2,
 };
 use futures_util::{stream::SplitSink, SinkExt, StreamExt};
 use log::{error, info, warn};
@@ -44,6 +45,13 @@ pub(crate) struct WsManager {
     subscriptions: Arc<Mutex<HashMap<String, Vec<SubscriptionData>>>>,
     subscription_id: u32,
     subscription_identifiers: HashMap<u32, String>,
+    raw_frame_senders: Arc<Mutex<Vec<UnboundedSender<RecordedFrame>>>>,
+    url: String,
+    reconnect: bool,
+    // Connections for further aggregations of books already subscribed here, and the connection
+    // each subscription made on them is on
+    book_connections: Vec<WsManager>,
+    book_subscriptions: HashMap<u32, usize>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -51,17 +59,55 @@ pub(crate) struct WsManager {
 #[serde(rename_all = "camelCase")]
 pub enum Subscription {
     AllMids,
-    Notification { user: H160 },
-    WebData2 { user: H160 },
-    Candle { coin: String, interval: String },
-    L2Book { coin: String },
-    Trades { coin: String },
-    OrderUpdates { user: H160 },
-    UserEvents { user: H160 },
-    UserFills { user: H160 },
-    UserFundings { user: H160 },
-    UserNonFundingLedgerUpdates { user: H160 },
-    ActiveAssetCtx { coin: String },
+    Notification {
+        user: H160,
+    },
+    WebData2 {
+        user: H160,
+    },
+    Candle {
+        coin: String,
+        interval: CandleInterval,
+    },
+    /// `n_sig_figs` and `mantissa` aggregate levels as in [`crate::InfoClient::l2_snapshot_aggregated`].
+    /// Books don't say how they were aggregated, so each further aggregation of a coin is
+    /// subscribed on a connection of its own.
+    #[serde(rename_all = "camelCase")]
+    L2Book {
+        coin: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        n_sig_figs: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mantissa: Option<u32>,
+    },
+    Bbo {
+        coin: String,
+    },
+    Trades {
+        coin: String,
+    },
+    OrderUpdates {
+        user: H160,
+    },
+    UserEvents {
+        user: H160,
+    },
+    UserFills {
+        user: H160,
+    },
+    UserFundings {
+        user: H160,
+    },
+    UserNonFundingLedgerUpdates {
+        user: H160,
+    },
+    ActiveAssetCtx {
+        coin: String,
+    },
+    ActiveAssetData {
+        user: H160,
+        coin: String,
+    },
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -73,6 +119,7 @@ pub enum Message {
     AllMids(AllMids),
     Trades(Trades),
     L2Book(L2Book),
+    Bbo(Bbo),
     User(User),
     UserFills(UserFills),
     Candle(Candle),
@@ -83,6 +130,7 @@ pub enum Message {
     Notification(Notification),
     WebData2(WebData2),
     ActiveAssetCtx(ActiveAssetCtx),
+    ActiveAssetData(ActiveAssetData),
     Pong,
 }
 
@@ -101,6 +149,14 @@ impl WsManager {
     const SEND_PING_INTERVAL: u64 = 50;
 
     pub(crate) async fn new(url: String, reconnect: bool) -> Result<WsManager> {
+        Self::with_raw_frame_senders(url, reconnect, Arc::new(Mutex::new(Vec::new()))).await
+    }
+
+    async fn with_raw_frame_senders(
+        url: String,
+        reconnect: bool,
+        raw_frame_senders: Arc<Mutex<Vec<UnboundedSender<RecordedFrame>>>>,
+    ) -> Result<WsManager> {
         let stop_flag = Arc::new(AtomicBool::new(false));
 
         let (writer, mut reader) = Self::connect(&url).await?.split();
@@ -109,13 +165,18 @@ impl WsManager {
         let subscriptions_map: HashMap<String, Vec<SubscriptionData>> = HashMap::new();
         let subscriptions = Arc::new(Mutex::new(subscriptions_map));
         let subscriptions_copy = Arc::clone(&subscriptions);
+        let raw_frame_senders_copy = Arc::clone(&raw_frame_senders);
 
         {
             let writer = writer.clone();
             let stop_flag = Arc::clone(&stop_flag);
+            let url = url.clone();
             let reader_fut = async move {
                 while !stop_flag.load(Ordering::Relaxed) {
                     if let Some(data) = reader.next().await {
+                        if let Ok(protocol::Message::Text(text)) = &data {
+                            WsManager::send_raw_frame(&raw_frame_senders_copy, text).await;
+                        }
                         if let Err(err) =
                             WsManager::parse_and_send_data(data, &subscriptions_copy).await
                         {
@@ -158,9 +219,11 @@ impl WsManager {
                                                     );
                                                 }
                                             }
-                                        } else if let Err(err) =
-                                            Self::subscribe(writer_guard.deref_mut(), identifier)
-                                                .await
+                                        } else if let Err(err) = Self::subscribe(
+                                            writer_guard.deref_mut(),
+                                            v.first().map_or(identifier, |data| &data.id),
+                                        )
+                                        .await
                                         {
                                             error!("Could not resubscribe correctly {identifier}: {err}");
                                         }
@@ -207,6 +270,11 @@ impl WsManager {
             subscriptions,
             subscription_id: 0,
             subscription_identifiers: HashMap::new(),
+            raw_frame_senders,
+            url,
+            reconnect,
+            book_connections: Vec::new(),
+            book_subscriptions: HashMap::new(),
         })
     }
 
@@ -238,11 +306,17 @@ impl WsManager {
             }
             Message::L2Book(l2_book) => serde_json::to_string(&Subscription::L2Book {
                 coin: l2_book.data.coin.clone(),
+                n_sig_figs: None,
+                mantissa: None,
+            })
+            .map_err(|e| Error::JsonParse(e.to_string())),
+            Message::Bbo(bbo) => serde_json::to_string(&Subscription::Bbo {
+                coin: bbo.data.coin.clone(),
             })
             .map_err(|e| Error::JsonParse(e.to_string())),
             Message::Candle(candle) => serde_json::to_string(&Subscription::Candle {
                 coin: candle.data.coin.clone(),
-                interval: candle.data.interval.clone(),
+                interval: candle.data.interval.parse()?,
             })
             .map_err(|e| Error::JsonParse(e.to_string())),
             Message::OrderUpdates(_) => Ok("orderUpdates".to_string()),
@@ -267,12 +341,37 @@ impl WsManager {
                 })
                 .map_err(|e| Error::JsonParse(e.to_string()))
             }
+            Message::ActiveAssetData(active_asset_data) => {
+                serde_json::to_string(&Subscription::ActiveAssetData {
+                    user: active_asset_data.data.user,
+                    coin: active_asset_data.data.coin.clone(),
+                })
+                .map_err(|e| Error::JsonParse(e.to_string()))
+            }
             Message::SubscriptionResponse | Message::Pong => Ok(String::default()),
             Message::NoData => Ok("".to_string()),
             Message::HyperliquidError(err) => Ok(format!("hyperliquid error: {err:?}")),
         }
     }
 
+    // Key subscriptions are stored under, matching what `get_identifier` derives from messages
+    fn identifier_entry(identifier: &str) -> Result<String> {
+        match serde_json::from_str::<Subscription>(identifier)
+            .map_err(|e| Error::JsonParse(e.to_string()))?
+        {
+            Subscription::UserEvents { user: _ } => Ok("userEvents".to_string()),
+            Subscription::OrderUpdates { user: _ } => Ok("orderUpdates".to_string()),
+            // Aggregation parameters aren't sent back with the book, so all books of a coin share a key
+            Subscription::L2Book { coin, .. } => serde_json::to_string(&Subscription::L2Book {
+                coin,
+                n_sig_figs: None,
+                mantissa: None,
+            })
+            .map_err(|e| Error::JsonParse(e.to_string())),
+            _ => Ok(identifier.to_string()),
+        }
+    }
+
     async fn parse_and_send_data(
         data: std::result::Result<protocol::Message, tungstenite::Error>,
         subscriptions: &Arc<Mutex<HashMap<String, Vec<SubscriptionData>>>>,
@@ -325,6 +424,21 @@ impl WsManager {
         }
     }
 
+    async fn send_raw_frame(
+        raw_frame_senders: &Mutex<Vec<UnboundedSender<RecordedFrame>>>,
+        text: &str,
+    ) {
+        let mut raw_frame_senders = raw_frame_senders.lock().await;
+        if raw_frame_senders.is_empty() || !text.starts_with('{') {
+            return;
+        }
+        let frame = RecordedFrame {
+            received_at: now_timestamp_ms(),
+            data: text.to_string(),
+        };
+        raw_frame_senders.retain(|sender| sender.send(frame.clone()).is_ok());
+    }
+
     async fn send_to_all_subscriptions(
         subscriptions: &Arc<Mutex<HashMap<String, Vec<SubscriptionData>>>>,
         message: Message,
@@ -383,21 +497,66 @@ impl WsManager {
         identifier: String,
         sending_channel: UnboundedSender<Message>,
     ) -> Result<u32> {
-        let mut subscriptions = self.subscriptions.lock().await;
-
-        let identifier_entry = if let Subscription::UserEvents { user: _ } =
-            serde_json::from_str::<Subscription>(&identifier)
-                .map_err(|e| Error::JsonParse(e.to_string()))?
-        {
-            "userEvents".to_string()
-        } else if let Subscription::OrderUpdates { user: _ } =
-            serde_json::from_str::<Subscription>(&identifier)
-                .map_err(|e| Error::JsonParse(e.to_string()))?
-        {
-            "orderUpdates".to_string()
+        let subscription_id = self.subscription_id;
+        if self.has_other_aggregation(&identifier).await? {
+            let mut free = None;
+            for (index, connection) in self.book_connections.iter().enumerate() {
+                if !connection.has_other_aggregation(&identifier).await? {
+                    free = Some(index);
+                    break;
+                }
+            }
+            let index = match free {
+                Some(index) => index,
+                None => {
+                    let connection = Self::with_raw_frame_senders(
+                        self.url.clone(),
+                        self.reconnect,
+                        Arc::clone(&self.raw_frame_senders),
+                    )
+                    .await?;
+                    self.book_connections.push(connection);
+                    self.book_connections.len() - 1
+                }
+            };
+            self.book_connections[index]
+                .insert_subscription(subscription_id, identifier, sending_channel)
+                .await?;
+            self.book_subscriptions.insert(subscription_id, index);
         } else {
-            identifier.clone()
+            self.insert_subscription(subscription_id, identifier, sending_channel)
+                .await?;
+        }
+        self.subscription_id += 1;
+        Ok(subscription_id)
+    }
+
+    // Whether a different aggregation of the same book is subscribed on this connection
+    async fn has_other_aggregation(&self, identifier: &str) -> Result<bool> {
+        let Subscription::L2Book { .. } =
+            serde_json::from_str(identifier).map_err(|e| Error::JsonParse(e.to_string()))?
+        else {
+            return Ok(false);
         };
+        let identifier_entry = Self::identifier_entry(identifier)?;
+        Ok(self
+            .subscriptions
+            .lock()
+            .await
+            .get(&identifier_entry)
+            .and_then(|subscriptions| subscriptions.first())
+            .is_some_and(|subscribed| subscribed.id != identifier))
+    }
+
+    async fn insert_subscription(
+        &mut self,
+        subscription_id: u32,
+        identifier: String,
+        sending_channel: UnboundedSender<Message>,
+    ) -> Result<()> {
+        let mut subscriptions = self.subscriptions.lock().await;
+
+        let identifier_entry = Self::identifier_entry(&identifier)?;
         let subscriptions = subscriptions
             .entry(identifier_entry.clone())
             .or_insert(Vec::new());
@@ -410,7 +569,6 @@ impl WsManager {
             Self::subscribe(self.writer.lock().await.borrow_mut(), identifier.as_str()).await?;
         }
 
-        let subscription_id = self.subscription_id;
         self.subscription_identifiers
             .insert(subscription_id, identifier.clone());
         subscriptions.push(SubscriptionData {
@@ -418,31 +576,26 @@ impl WsManager {
             subscription_id,
             id: identifier,
         });
+        Ok(())
+    }
 
-        self.subscription_id += 1;
-        Ok(subscription_id)
+    pub(crate) async fn add_raw_frame_sender(&self, sender: UnboundedSender<RecordedFrame>) {
+        self.raw_frame_senders.lock().await.push(sender);
     }
 
     pub(crate) async fn remove_subscription(&mut self, subscription_id: u32) -> Result<()> {
+        if let Some(index) = self.book_subscriptions.remove(&subscription_id) {
+            // Emptied connections are kept for later aggregations
+            return Box::pin(self.book_connections[index].remove_subscription(subscription_id))
+                .await;
+        }
         let identifier = self
             .subscription_identifiers
             .get(&subscription_id)
             .ok_or(Error::SubscriptionNotFound)?
             .clone();
 
-        let identifier_entry = if let Subscription::UserEvents { user: _ } =
-            serde_json::from_str::<Subscription>(&identifier)
-                .map_err(|e| Error::JsonParse(e.to_string()))?
-        {
-            "userEvents".to_string()
-        } else if let Subscription::OrderUpdates { user: _ } =
-            serde_json::from_str::<Subscription>(&identifier)
-                .map_err(|e| Error::JsonParse(e.to_string()))?
-        {
-            "orderUpdates".to_string()
-        } else {
-            identifier.clone()
-        };
+        let identifier_entry = Self::identifier_entry(&identifier)?;
 
         self.subscription_identifiers.remove(&subscription_id);
 
@@ -469,3 +622,147 @@ impl Drop for WsManager {
         self.stop_flag.store(true, Ordering::Relaxed);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bbo_and_active_asset_data_routing() -> Result<()> {
+        let bbo: Message = serde_json::from_str(
+            r#"{"channel":"bbo","data":{"coin":"ETH","time":1700000000000,
+            "bbo":[{"px":"1999.9","sz":"3.2","n":4},null]}}"#,
+        )
+        .map_err(|e| Error::JsonParse(e.to_string()))?;
+        let Message::Bbo(ref bbo_data) = bbo else {
+            panic!("unexpected message {bbo:?}");
+        };
+        assert_eq!(
+            bbo_data.data.bid().map(|level| level.px.as_str()),
+            Some("1999.9")
+        );
+        assert!(bbo_data.data.ask().is_none());
+        assert_eq!(
+            WsManager::get_identifier(&bbo)?,
+            serde_json::to_string(&Subscription::Bbo {
+                coin: "ETH".to_string()
+            })
+            .unwrap()
+        );
+
+        let user: H160 = "0x0000000000000000000000000000000000000001"
+            .parse()
+            .unwrap();
+        let active_asset_data: Message = serde_json::from_str(
+            r#"{"channel":"activeAssetData","data":{"user":"0x0000000000000000000000000000000000000001",
+            "coin":"ETH","leverage":{"type":"cross","value":20},"maxTradeSzs":["4.5","5.1"],
+            "availableToTrade":["9000.0","10200.0"],"markPx":"2000.0"}}"#,
+        )
+        .map_err(|e| Error::JsonParse(e.to_string()))?;
+        let Message::ActiveAssetData(ref data) = active_asset_data else {
+            panic!("unexpected message {active_asset_data:?}");
+        };
+        assert_eq!(data.data.leverage.value, 20);
+        assert_eq!(data.data.max_trade_sz_for(true)?, 4.5);
+        assert_eq!(data.data.available_to_trade_for(false)?, 10200.0);
+        assert_eq!(
+            WsManager::get_identifier(&active_asset_data)?,
+            serde_json::to_string(&Subscription::ActiveAssetData {
+                user,
+                coin: "ETH".to_string()
+            })
+            .unwrap()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_l2_book_aggregation_routing() -> Result<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| Error::Websocket(e.to_string()))?;
+        let url = format!(
+            "ws://{}",
+            listener
+                .local_addr()
+                .map_err(|e| Error::Websocket(e.to_string()))?
+        );
+        spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                spawn(async move {
+                    let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    while let Some(Ok(_)) = ws.next().await {}
+                });
+            }
+        });
+        let mut ws_manager = WsManager::new(url, false).await?;
+
+        let identifier = |n_sig_figs, mantissa| {
+            serde_json::to_string(&Subscription::L2Book {
+                coin: "BTC".to_string(),
+                n_sig_figs,
+                mantissa,
+            })
+            .unwrap()
+        };
+        assert_eq!(identifier(None, None), r#"{"type":"l2Book","coin":"BTC"}"#);
+        assert_eq!(
+            identifier(Some(5), Some(2)),
+            r#"{"type":"l2Book","coin":"BTC","nSigFigs":5,"mantissa":2}"#
+        );
+
+        let (full_sender, mut full_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let (five_sender, mut five_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let (three_sender, mut three_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let full_id = ws_manager
+            .add_subscription(identifier(None, None), full_sender.clone())
+            .await?;
+        let five_id = ws_manager
+            .add_subscription(identifier(Some(5), None), five_sender.clone())
+            .await?;
+        ws_manager
+            .add_subscription(identifier(None, None), full_sender)
+            .await?;
+        ws_manager
+            .add_subscription(identifier(Some(5), None), five_sender)
+            .await?;
+        let three_id = ws_manager
+            .add_subscription(identifier(Some(3), None), three_sender)
+            .await?;
+        assert_eq!(ws_manager.book_connections.len(), 2);
+        assert_eq!(
+            ws_manager.subscription_identifiers.len() + ws_manager.book_subscriptions.len(),
+            5
+        );
+
+        // Each connection only delivers the aggregation subscribed on it
+        let book = r#"{"channel":"l2Book","data":{"coin":"BTC","time":0,
+            "levels":[[{"px":"65432.1","sz":"1","n":1}],[{"px":"65433.2","sz":"1","n":1}]]}}"#;
+        let send_book = |subscriptions| {
+            WsManager::parse_and_send_data(
+                Ok(protocol::Message::Text(book.to_string())),
+                subscriptions,
+            )
+        };
+        send_book(&ws_manager.subscriptions).await?;
+        assert!(matches!(full_receiver.try_recv(), Ok(Message::L2Book(_))));
+        assert!(matches!(full_receiver.try_recv(), Ok(Message::L2Book(_))));
+        assert!(five_receiver.try_recv().is_err());
+        send_book(&ws_manager.book_connections[0].subscriptions).await?;
+        assert!(matches!(five_receiver.try_recv(), Ok(Message::L2Book(_))));
+        assert!(matches!(five_receiver.try_recv(), Ok(Message::L2Book(_))));
+        assert!(full_receiver.try_recv().is_err() && three_receiver.try_recv().is_err());
+        send_book(&ws_manager.book_connections[1].subscriptions).await?;
+        assert!(matches!(three_receiver.try_recv(), Ok(Message::L2Book(_))));
+
+        ws_manager.remove_subscription(five_id).await?;
+        ws_manager.remove_subscription(three_id).await?;
+        ws_manager.remove_subscription(full_id).await?;
+        assert_eq!(ws_manager.book_subscriptions.len(), 1);
+        assert!(matches!(
+            ws_manager.remove_subscription(three_id).await,
+            Err(Error::SubscriptionNotFound)
+        ));
+        Ok(())
+    }
+}